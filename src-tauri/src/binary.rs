@@ -0,0 +1,122 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::hash::first_difference;
+use crate::models::BinaryDiff;
+
+/// How much of a file is inspected to decide whether it is binary (same as git).
+const SNIFF_LEN: u64 = 8000;
+/// How many bytes around the first difference are shown in the hex summary.
+const SUMMARY_LEN: u64 = 16;
+
+pub fn is_binary(path: &Path) -> io::Result<bool> {
+    let mut buf = Vec::with_capacity(SNIFF_LEN as usize);
+    File::open(path)?.take(SNIFF_LEN).read_to_end(&mut buf)?;
+    Ok(looks_binary(&buf))
+}
+
+/// A NUL byte or invalid UTF-8 makes a file binary. A multi-byte character cut off
+/// at the end of the sniffed window does not.
+pub fn looks_binary(bytes: &[u8]) -> bool {
+    if bytes.contains(&0) {
+        return true;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(_) => false,
+        Err(e) => e.error_len().is_some(),
+    }
+}
+
+pub fn describe_difference(left: &Path, right: &Path) -> io::Result<Option<BinaryDiff>> {
+    let Some(offset) = first_difference(left, right)? else {
+        return Ok(None);
+    };
+    Ok(Some(BinaryDiff {
+        offset,
+        left_bytes: hex_at(left, offset)?,
+        right_bytes: hex_at(right, offset)?,
+    }))
+}
+
+fn hex_at(path: &Path, offset: u64) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut buf = Vec::with_capacity(SUMMARY_LEN as usize);
+    file.take(SUMMARY_LEN).read_to_end(&mut buf)?;
+    Ok(buf
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn plain_text_is_not_binary() {
+        assert!(!looks_binary(b"hello\nworld\n"));
+        assert!(!looks_binary("héllo wörld".as_bytes()));
+        assert!(!looks_binary(b""));
+    }
+
+    #[test]
+    fn nul_byte_is_binary() {
+        assert!(looks_binary(b"abc\0def"));
+    }
+
+    #[test]
+    fn invalid_utf8_is_binary() {
+        assert!(looks_binary(&[b'a', 0xff, 0xfe, b'b']));
+    }
+
+    #[test]
+    fn truncated_multibyte_char_is_not_binary() {
+        let bytes = "aé".as_bytes();
+        assert!(!looks_binary(&bytes[..bytes.len() - 1]));
+    }
+
+    #[test]
+    fn is_binary_reads_file() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("img.png"), [0x89, b'P', b'N', b'G', 0, 0]).unwrap();
+        fs::write(dir.path().join("a.txt"), "text").unwrap();
+
+        assert!(is_binary(&dir.path().join("img.png")).unwrap());
+        assert!(!is_binary(&dir.path().join("a.txt")).unwrap());
+    }
+
+    #[test]
+    fn describe_difference_shows_hex_at_offset() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut left = vec![0u8; 40];
+        let mut right = left.clone();
+        left[20] = 0xab;
+        right[20] = 0xcd;
+        right.truncate(22);
+        fs::write(dir.path().join("l"), &left).unwrap();
+        fs::write(dir.path().join("r"), &right).unwrap();
+
+        let diff = describe_difference(&dir.path().join("l"), &dir.path().join("r"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(diff.offset, 20);
+        assert_eq!(diff.left_bytes.split(' ').count(), 16);
+        assert!(diff.left_bytes.starts_with("ab 00"));
+        assert_eq!(diff.right_bytes, "cd 00");
+    }
+
+    #[test]
+    fn describe_difference_of_identical_files_is_none() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("l"), [1, 2, 3]).unwrap();
+        fs::write(dir.path().join("r"), [1, 2, 3]).unwrap();
+
+        assert!(describe_difference(&dir.path().join("l"), &dir.path().join("r"))
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,348 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::compare::{compare_path, relative, replace_entries};
+use crate::error::DivergeError;
+use crate::history;
+use crate::models::{CompareEntry, CompareOptions, CompareResult, Side};
+use crate::write::{self, FileState, WriteOptions};
+
+/// New contents for one side of an entry, as edited in the app.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Change {
+    pub rel_path: String,
+    /// The entry's path on the left, if it was renamed.
+    #[serde(default)]
+    pub renamed_from: Option<String>,
+    pub side: Side,
+    pub content: String,
+    /// The file's state when it was compared; `None` if it did not exist.
+    #[serde(default)]
+    pub expected: Option<FileState>,
+    /// Replaces `ApplyOptions::write` for this change, e.g. to write a copy of the other
+    /// side as is.
+    #[serde(default)]
+    pub write: Option<WriteOptions>,
+}
+
+impl Change {
+    /// The paths of the entry on the left and on the right.
+    fn paths(&self) -> (&str, &str) {
+        (self.renamed_from.as_deref().unwrap_or(&self.rel_path), &self.rel_path)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct ApplyOptions {
+    /// All or nothing: write nothing if a file changed on disk since the comparison, and if
+    /// a write still fails, put back the files the others already wrote. On by default.
+    pub rollback: bool,
+    /// Overwrite files that changed on disk since the comparison.
+    pub force: bool,
+    /// What each write keeps of the file it replaces.
+    pub write: WriteOptions,
+}
+
+impl Default for ApplyOptions {
+    fn default() -> Self {
+        Self {
+            rollback: true,
+            force: false,
+            write: WriteOptions::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeResult {
+    pub rel_path: String,
+    pub side: Side,
+    /// The file as written, to check the next write against; `None` if it was not
+    /// written, was held back with a refused batch, or was rolled back.
+    pub state: Option<FileState>,
+    pub error: Option<DivergeError>,
+    /// Written, then put back because another change failed.
+    pub rolled_back: bool,
+    /// Why putting the file back failed; it still has the new contents.
+    pub rollback_error: Option<DivergeError>,
+}
+
+/// How a batch of changes went, one result per change in order.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ChangesOutcome {
+    pub results: Vec<ChangeResult>,
+    pub written: usize,
+    /// Changes not written, and written ones that could not be rolled back.
+    pub failed: usize,
+    /// Every written file was put back after a failure.
+    pub rolled_back: bool,
+    /// The entries of the changed paths compared again; a path whose files are all gone
+    /// has none.
+    pub entries: Vec<CompareEntry>,
+    /// The comparison passed in, with `entries` in place of the old ones.
+    pub result: Option<CompareResult>,
+}
+
+/// Writes each change to its side of the trees at `left` and `right` as one undoable
+/// operation, refusing files changed on disk since their `expected` state unless forced.
+/// Every change is checked before the first write. With `rollback`, one refused change
+/// means nothing is written, and if a write still fails, the files already written are put
+/// back as they were; without it, a failed change does not stop the others. The changed
+/// entries are then compared again with `compare`, and put into `result`, the last
+/// comparison, if given.
+pub fn apply_changes(
+    left: &str,
+    right: &str,
+    changes: &[Change],
+    options: &ApplyOptions,
+    compare: &CompareOptions,
+    result: Option<&CompareResult>,
+) -> ChangesOutcome {
+    let mut outcome = ChangesOutcome::default();
+    let checked: Vec<Result<PathBuf, DivergeError>> = changes
+        .iter()
+        .map(|change| {
+            let path = target(left, right, change)?;
+            if !options.force {
+                write::check_unchanged(&path, change.expected.as_ref())?;
+            }
+            Ok(path)
+        })
+        .collect();
+    let refused = checked.iter().any(Result::is_err);
+
+    // The contents each written file had before, `None` if it did not exist.
+    let mut previous: Vec<(PathBuf, Option<Vec<u8>>)> = Vec::new();
+    let label = match changes {
+        [change] => format!("save {}", change.rel_path),
+        _ => format!("save {} files", changes.len()),
+    };
+    history::operation(label, || {
+        for (change, path) in changes.iter().zip(checked) {
+            let mut result = ChangeResult {
+                rel_path: change.rel_path.clone(),
+                side: change.side,
+                state: None,
+                error: None,
+                rolled_back: false,
+                rollback_error: None,
+            };
+            // Held back with the rest of the batch because another change was refused.
+            if options.rollback && refused && path.is_ok() {
+                outcome.results.push(result);
+                continue;
+            }
+            let written = path.and_then(|path| {
+                let before = read_previous(&path)?;
+                let state = write::write_file_checked(
+                    &path,
+                    change.content.as_bytes(),
+                    change.expected.as_ref(),
+                    options.force,
+                    change.write.unwrap_or(options.write),
+                )?;
+                previous.push((path, before));
+                Ok(state)
+            });
+            match written {
+                Ok(state) => {
+                    result.state = Some(state);
+                    outcome.written += 1;
+                }
+                Err(e) => {
+                    result.error = Some(e);
+                    outcome.failed += 1;
+                }
+            }
+            outcome.results.push(result);
+        }
+
+        if options.rollback && outcome.failed > 0 {
+            roll_back(&mut outcome, &previous);
+        }
+    });
+
+    for change in changes {
+        let (from, to) = change.paths();
+        if outcome.entries.iter().any(|e| e.rel_path == to) {
+            continue;
+        }
+        if let Ok(Some(entry)) = compare_path(left, right, from, to, compare) {
+            outcome.entries.push(entry);
+        }
+    }
+    let paths: Vec<String> = changes.iter().map(|c| c.rel_path.clone()).collect();
+    outcome.result = result.cloned().map(|mut result| {
+        replace_entries(&mut result, &paths, &outcome.entries);
+        result
+    });
+    outcome
+}
+
+fn target(left: &str, right: &str, change: &Change) -> Result<PathBuf, DivergeError> {
+    let (from, to) = change.paths();
+    Ok(match change.side {
+        Side::Left => Path::new(left).join(relative(from)?),
+        Side::Right => Path::new(right).join(relative(to)?),
+    })
+}
+
+fn read_previous(path: &Path) -> Result<Option<Vec<u8>>, DivergeError> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(DivergeError::io(path, e)),
+    }
+}
+
+/// Puts back the files of the successful changes, newest first. A file that cannot be put
+/// back keeps its new contents, counts as failed and reports why.
+fn roll_back(outcome: &mut ChangesOutcome, previous: &[(PathBuf, Option<Vec<u8>>)]) {
+    let mut all_restored = true;
+    let mut written = outcome.results.iter_mut().filter(|r| r.state.is_some()).rev();
+    for (path, before) in previous.iter().rev() {
+        let Some(result) = written.next() else { break };
+        let restored = match before {
+            Some(bytes) => write::write_file(path, bytes),
+            None => write::remove_file(path),
+        };
+        match restored {
+            Ok(()) => {
+                result.state = None;
+                result.rolled_back = true;
+                outcome.written -= 1;
+            }
+            Err(e) => {
+                result.rollback_error = Some(e);
+                outcome.failed += 1;
+                all_restored = false;
+            }
+        }
+    }
+    outcome.rolled_back = all_restored;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::hash_file;
+    use crate::models::EntryStatus;
+
+    fn change(rel_path: &str, content: &str) -> Change {
+        Change {
+            rel_path: rel_path.to_string(),
+            renamed_from: None,
+            side: Side::Right,
+            content: content.to_string(),
+            expected: None,
+            write: None,
+        }
+    }
+
+    #[test]
+    fn applies_a_batch_only_if_no_file_changed() {
+        let (left, right) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let (l, r) = (left.path().to_str().unwrap(), right.path().to_str().unwrap());
+        fs::write(left.path().join("a.txt"), "new\n").unwrap();
+        fs::write(right.path().join("a.txt"), "old\n").unwrap();
+        fs::write(right.path().join("b.txt"), "edited elsewhere\n").unwrap();
+        let compare = CompareOptions::default();
+
+        // b.txt did not exist when compared, so writing it conflicts.
+        let changes = [change("a.txt", "new\n"), change("c.txt", "c\n"), change("b.txt", "b\n")];
+        let hash = hash_file(&right.path().join("a.txt")).ok();
+        let expected = FileState { size: 4, modified: None, hash };
+        let changes = changes.map(|mut c| {
+            if c.rel_path == "a.txt" {
+                c.expected = Some(expected.clone());
+            }
+            c
+        });
+        let outcome = apply_changes(l, r, &changes, &ApplyOptions::default(), &compare, None);
+        assert_eq!((outcome.written, outcome.failed), (0, 1));
+        assert!(outcome.results.iter().all(|r| r.state.is_none() && !r.rolled_back));
+        assert!(outcome.results[0].error.is_none());
+        assert_eq!(outcome.results[2].error.as_ref().unwrap().kind(), "conflict");
+        assert_eq!(fs::read_to_string(right.path().join("a.txt")).unwrap(), "old\n");
+        assert!(!right.path().join("c.txt").exists());
+
+        let partial = ApplyOptions { rollback: false, ..Default::default() };
+        let outcome = apply_changes(l, r, &changes, &partial, &compare, None);
+        assert_eq!((outcome.written, outcome.failed), (2, 1));
+        fs::write(right.path().join("a.txt"), "old\n").unwrap();
+        fs::remove_file(right.path().join("c.txt")).unwrap();
+
+        let outcome = apply_changes(l, r, &changes[..2], &ApplyOptions::default(), &compare, None);
+        assert_eq!((outcome.written, outcome.failed), (2, 0));
+        assert_eq!(outcome.results[0].state.as_ref().unwrap().size, 4);
+        let statuses: Vec<_> = outcome.entries.iter().map(|e| (e.rel_path.as_str(), e.status)).collect();
+        assert_eq!(statuses, [("a.txt", EntryStatus::Identical), ("c.txt", EntryStatus::OnlyRight)]);
+    }
+
+    #[test]
+    fn rolls_back_when_a_write_fails() {
+        let (left, right) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let (l, r) = (left.path().to_str().unwrap(), right.path().to_str().unwrap());
+        fs::write(right.path().join("a.txt"), "old\n").unwrap();
+        fs::create_dir(right.path().join("dir")).unwrap();
+
+        // Forced, so only the write itself finds that `dir` is a directory.
+        let options = ApplyOptions { force: true, ..Default::default() };
+        let changes = [change("a.txt", "new\n"), change("dir", "x\n")];
+        let outcome = apply_changes(l, r, &changes, &options, &CompareOptions::default(), None);
+        assert_eq!((outcome.written, outcome.failed), (0, 1));
+        assert!(outcome.rolled_back && outcome.results[0].rolled_back);
+        assert!(outcome.results[1].error.is_some());
+        assert_eq!(fs::read_to_string(right.path().join("a.txt")).unwrap(), "old\n");
+    }
+
+    #[test]
+    fn copies_are_written_as_is() {
+        let (left, right) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let (l, r) = (left.path().to_str().unwrap(), right.path().to_str().unwrap());
+        fs::write(left.path().join("a.txt"), "one\r\ntwo").unwrap();
+        fs::write(right.path().join("a.txt"), "one\ntwo\n").unwrap();
+
+        // Edits keep the line endings of the file they replace, a copy of the left side does not.
+        let edited = WriteOptions { keep_line_endings: true, ..Default::default() };
+        let options = ApplyOptions { force: true, write: edited, ..Default::default() };
+        let mut copy = change("a.txt", "one\r\ntwo");
+        copy.write = Some(WriteOptions::default());
+        let outcome = apply_changes(l, r, &[copy], &options, &CompareOptions::default(), None);
+
+        assert_eq!(outcome.written, 1);
+        let hash = |dir: &tempfile::TempDir| hash_file(&dir.path().join("a.txt")).unwrap();
+        assert_eq!(hash(&left), hash(&right));
+        assert_eq!(outcome.entries[0].status, EntryStatus::Identical);
+    }
+
+    #[test]
+    fn rollback_failures_count_as_failed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut outcome = ChangesOutcome {
+            written: 1,
+            failed: 1,
+            ..Default::default()
+        };
+        for error in [None, Some(DivergeError::invalid("write failed"))] {
+            outcome.results.push(ChangeResult {
+                rel_path: "a.txt".to_string(),
+                side: Side::Right,
+                state: error.is_none().then_some(FileState { size: 1, modified: None, hash: None }),
+                error,
+                rolled_back: false,
+                rollback_error: None,
+            });
+        }
+        // Created by the batch, then removed by someone else before the rollback.
+        roll_back(&mut outcome, &[(dir.path().join("a.txt"), None)]);
+
+        assert!(!outcome.rolled_back);
+        assert_eq!((outcome.written, outcome.failed), (1, 2));
+        assert!(outcome.results[0].rollback_error.is_some());
+        assert_eq!(outcome.results[1].error.as_ref().unwrap().kind(), "invalid_input");
+    }
+}
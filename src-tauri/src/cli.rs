@@ -0,0 +1,920 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+
+use crate::comparer::Comparer;
+use crate::config;
+use crate::diff::DEFAULT_CONTEXT;
+use crate::error::DivergeError;
+use crate::history;
+use crate::merge::{Favor, MergeOptions};
+use crate::models::{AppConfig, CompareOptions, CompareResult};
+use crate::patch;
+use crate::report::{self, OutputFormat};
+use crate::sync::{self, SyncDirection, SyncFilters};
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "diverge",
+    version,
+    about = "A visual directory diff tool",
+    after_help = "Headless commands exit with 0 if the directories are identical, 1 if they differ and 2 on error."
+)]
+pub struct Cli {
+    /// Use this config file instead of ~/.diverge/config.yaml
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Compare without opening a window and print the result (same as `diverge compare`)
+    #[arg(long, requires_all = ["left", "right"], conflicts_with = "wait")]
+    pub headless: bool,
+
+    /// Output format for --headless
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Text, requires = "headless")]
+    pub format: OutputFormat,
+
+    #[command(flatten)]
+    pub gui: GuiArgs,
+
+    /// Scan options for --headless
+    #[command(flatten, next_help_heading = "Headless options")]
+    pub scan: ScanArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// Parses `std::env::args`, exiting with a usage error like clap does for anything it rejects.
+    pub fn parse_args() -> Self {
+        let cli = Self::parse();
+        if let Err(e) = cli.validate() {
+            e.exit();
+        }
+        cli
+    }
+
+    fn validate(&self) -> Result<(), clap::Error> {
+        let scanning = self.scan != ScanArgs::default();
+        if self.command.is_some() && (self.gui.left.is_some() || self.gui.wait || self.headless || scanning) {
+            return Err(Self::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "options and directories must come after the subcommand",
+            ));
+        }
+        if scanning && !self.headless {
+            return Err(Self::command().error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "scan options need --headless or the `compare` subcommand",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Open the comparison window (default when no subcommand is given)
+    Gui(GuiArgs),
+    /// Compare two directories and print the result without opening a window
+    Compare(CompareArgs),
+    /// Print a patch turning LEFT into RIGHT, for `patch -p1` or `git apply`
+    Diff(DiffArgs),
+    /// Apply a unified diff or git patch to DIR; exits 1 if any hunk is rejected
+    Patch(PatchArgs),
+    /// Compare OURS and THEIRS with their common ancestor BASE; exits 1 if files need a manual merge
+    Merge(MergeArgs),
+    /// Write a comparison report to a file or stdout; exits 0 whether or not the trees differ
+    Report(ReportArgs),
+    /// Copy differing and left-only files from LEFT into RIGHT
+    Sync(SyncArgs),
+    /// List the files Diverge wrote or deleted, or undo those changes
+    History(HistoryArgs),
+    /// Inspect or reset the configuration file
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Print a shell completion script to stdout
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Print the man page (roff) to stdout
+    Man,
+}
+
+#[derive(Debug, Clone, Default, Args)]
+pub struct GuiArgs {
+    /// Left directory to compare
+    pub left: Option<String>,
+    /// Right directory to compare
+    pub right: Option<String>,
+    /// Wait for the window to be closed before returning
+    #[arg(short, long)]
+    pub wait: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct CompareArgs {
+    /// Left directory
+    pub left: String,
+    /// Right directory
+    pub right: String,
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+    #[command(flatten)]
+    pub scan: ScanArgs,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct DiffArgs {
+    /// Left directory (the patch applies here)
+    pub left: String,
+    /// Right directory
+    pub right: String,
+    /// Unified diff, with LINES lines of context (the only format; default 3)
+    #[arg(
+        short = 'u',
+        long,
+        value_name = "LINES",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "3"
+    )]
+    pub unified: Option<usize>,
+    /// Lines of context, as in `diff -U5`
+    #[arg(short = 'U', value_name = "LINES", conflicts_with = "unified")]
+    pub context: Option<usize>,
+    /// Write the patch to this file instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+    #[command(flatten)]
+    pub scan: ScanArgs,
+}
+
+impl DiffArgs {
+    /// Lines of context requested by `-U` or `--unified`.
+    pub fn context(&self) -> usize {
+        self.context.or(self.unified).unwrap_or(DEFAULT_CONTEXT)
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct PatchArgs {
+    /// Directory to patch
+    pub dir: String,
+    /// Patch file, or `-` for stdin (the default)
+    pub patch: Option<PathBuf>,
+    /// Strip NUM leading components from the paths in the patch, as in `patch -p`
+    #[arg(short = 'p', long, value_name = "NUM", default_value_t = 1)]
+    pub strip: usize,
+    /// Check that the patch applies without changing any file
+    #[arg(short = 'n', long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct MergeArgs {
+    /// Common ancestor, such as the previous upstream release
+    pub base: String,
+    /// Our version, which --apply writes to
+    pub ours: String,
+    /// Their version, such as the new upstream release
+    pub theirs: String,
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Text, conflicts_with = "apply")]
+    pub format: OutputFormat,
+    /// Bring the changes of THEIRS into OURS: copy the files only they changed and merge the
+    /// files both changed on different lines
+    #[arg(long)]
+    pub apply: bool,
+    /// With --apply, resolve conflicting lines this way instead of leaving the file alone
+    #[arg(long, value_enum, requires = "apply")]
+    pub favor: Option<Favor>,
+    /// With --apply, treat lines that differ only in whitespace as equal
+    #[arg(long, requires = "apply")]
+    pub ignore_space_change: bool,
+    #[command(flatten)]
+    pub scan: ScanArgs,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct ReportArgs {
+    #[command(flatten)]
+    pub compare: CompareArgs,
+    /// Write the report to this file instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct SyncArgs {
+    /// Source directory (the target with --reverse)
+    pub left: String,
+    /// Target directory (the source with --reverse)
+    pub right: String,
+    /// Print the plan, with sizes and files changed since the comparison, without touching
+    /// any file
+    #[arg(short = 'n', long)]
+    pub dry_run: bool,
+    /// Format of the --dry-run plan
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Text, requires = "dry_run")]
+    pub format: OutputFormat,
+    /// Also delete files that only exist in the target
+    #[arg(long)]
+    pub delete: bool,
+    /// Copy from RIGHT into LEFT instead
+    #[arg(short = 'R', long)]
+    pub reverse: bool,
+    #[command(flatten)]
+    pub scan: ScanArgs,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Args)]
+pub struct ScanArgs {
+    /// Additional gitignore-style pattern to ignore, on top of the configured ones (repeatable)
+    #[arg(long = "ignore", value_name = "PATTERN")]
+    pub patterns: Vec<String>,
+    /// Do not use the ignore list from the config file
+    #[arg(long)]
+    pub no_config_ignores: bool,
+    /// Treat files with the same size and modification time as identical without reading them
+    #[arg(long)]
+    pub trust_mtime: bool,
+    /// Confirm every hash match with a byte-by-byte comparison
+    #[arg(long)]
+    pub paranoid: bool,
+    /// List files larger than this many bytes as too large instead of diffing them
+    #[arg(long, value_name = "BYTES")]
+    pub max_file_size: Option<u64>,
+    /// Also skip files ignored by .gitignore, .ignore, .git/info/exclude and global git excludes
+    #[arg(long, overrides_with = "no_gitignore")]
+    pub gitignore: bool,
+    /// Do not read git ignore files, even if the config enables it
+    #[arg(long)]
+    pub no_gitignore: bool,
+    /// Worker threads for scanning and hashing (0 = one per CPU core)
+    #[arg(short = 'j', long, value_name = "N")]
+    pub threads: Option<usize>,
+    /// Pair moved files as renames, down to PERCENT similarity (default 50; 100 = identical only)
+    #[arg(
+        short = 'M',
+        long,
+        value_name = "PERCENT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "50",
+        value_parser = clap::value_parser!(u8).range(0..=100),
+        overrides_with = "no_renames"
+    )]
+    pub find_renames: Option<u8>,
+    /// Do not detect renames, even if the config enables it
+    #[arg(long)]
+    pub no_renames: bool,
+}
+
+impl ScanArgs {
+    fn options(&self, cfg: &AppConfig) -> CompareOptions {
+        let mut options = CompareOptions::from_config(cfg);
+        if self.no_config_ignores {
+            options.ignore_dirs.clear();
+        }
+        options.ignore_dirs.extend(self.patterns.iter().cloned());
+        options.trust_mtime |= self.trust_mtime;
+        options.paranoid |= self.paranoid;
+        if let Some(max) = self.max_file_size {
+            options.max_file_size = max;
+        }
+        if self.gitignore {
+            options.respect_gitignore = true;
+        }
+        if self.no_gitignore {
+            options.respect_gitignore = false;
+        }
+        if let Some(threads) = self.threads {
+            options.threads = threads;
+        }
+        if self.find_renames.is_some() {
+            options.renames = self.find_renames;
+        }
+        if self.no_renames {
+            options.renames = None;
+        }
+        options
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct HistoryArgs {
+    #[command(subcommand)]
+    pub command: Option<HistoryCommand>,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum HistoryCommand {
+    /// List the sessions and their operations, oldest first (the default)
+    List,
+    /// Restore the files of the latest operation not undone yet
+    Undo,
+    /// Undo every operation of SESSION, newest first
+    Restore {
+        /// Session id, as listed by `diverge history`
+        session: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the path of the config file
+    Path,
+    /// Print the current configuration as YAML
+    Show,
+    /// Overwrite the config file with the defaults
+    Reset,
+}
+
+pub fn resolve_path(p: &str) -> String {
+    let expanded = if let Some(rest) = p.strip_prefix("~/") {
+        dirs::home_dir()
+            .map(|h| h.join(rest))
+            .unwrap_or_else(|| PathBuf::from(p))
+    } else {
+        PathBuf::from(p)
+    };
+
+    let absolute = if expanded.is_absolute() {
+        expanded
+    } else {
+        std::env::current_dir()
+            .map(|cwd| cwd.join(&expanded))
+            .unwrap_or(expanded)
+    };
+
+    absolute
+        .canonicalize()
+        .unwrap_or(absolute)
+        .to_string_lossy()
+        .to_string()
+}
+
+pub fn config_path(custom: Option<&Path>) -> Result<PathBuf, DivergeError> {
+    match custom {
+        Some(p) => Ok(PathBuf::from(resolve_path(&p.to_string_lossy()))),
+        None => config::config_path(),
+    }
+}
+
+/// Keeps the previous version of every file `command` overwrites or deletes in the undo
+/// history, if it writes any.
+pub fn start_history(command: &Command, config: Option<&Path>) {
+    let writes = match command {
+        Command::Patch(args) => !args.dry_run,
+        Command::Merge(args) => args.apply,
+        Command::Sync(args) => !args.dry_run,
+        _ => false,
+    };
+    if !writes {
+        return;
+    }
+    let started = config_path(config).and_then(|path| {
+        let cfg = config::load_config(&path)?;
+        history::start_session(&history::history_dir(&path), &cfg.history)
+    });
+    if let Err(e) = started {
+        eprintln!("diverge: undo history is off: {}", e);
+    }
+}
+
+/// Runs a headless subcommand and returns the process exit code.
+pub fn execute(command: Command, config: Option<&Path>) -> i32 {
+    let result = match command {
+        Command::Gui(_) => Err(DivergeError::invalid(
+            "the gui subcommand must be launched by the binary",
+        )),
+        Command::Compare(args) => run_compare(&args, config),
+        Command::Diff(args) => run_diff(&args, config),
+        Command::Patch(args) => run_patch(&args),
+        Command::Merge(args) => run_merge(&args, config),
+        Command::Report(args) => run_report(&args, config),
+        Command::Sync(args) => run_sync(&args, config),
+        Command::History(args) => run_history(&args, config),
+        Command::Config(cmd) => run_config(cmd, config),
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "diverge", &mut io::stdout());
+            Ok(0)
+        }
+        Command::Man => clap_mangen::Man::new(Cli::command())
+            .render(&mut io::stdout())
+            .map(|_| 0)
+            .map_err(|e| DivergeError::Io {
+                path: None,
+                message: format!("Failed to render man page: {}", e),
+            }),
+    };
+
+    result.unwrap_or_else(|e| {
+        eprintln!("diverge: {}", e);
+        2
+    })
+}
+
+fn load(config: Option<&Path>) -> Result<AppConfig, DivergeError> {
+    config::load_config(&config_path(config)?)
+}
+
+fn compare_dirs(args: &CompareArgs, config: Option<&Path>) -> Result<CompareResult, DivergeError> {
+    let (left, right) = (resolve_path(&args.left), resolve_path(&args.right));
+    let cfg = load(config)?;
+    Comparer::new(&left, &right).options(args.scan.options(&cfg)).run()
+}
+
+fn run_compare(args: &CompareArgs, config: Option<&Path>) -> Result<i32, DivergeError> {
+    let result = compare_dirs(args, config)?;
+    print!("{}", report::render(&result, args.format)?);
+    Ok(report::exit_code(&result))
+}
+
+fn run_diff(args: &DiffArgs, config: Option<&Path>) -> Result<i32, DivergeError> {
+    let (left, right) = (resolve_path(&args.left), resolve_path(&args.right));
+    let cfg = load(config)?;
+    let comparer = Comparer::new(&left, &right).options(args.scan.options(&cfg));
+    let result = comparer.run()?;
+    let diff = comparer.diff(&result, args.context())?;
+    for path in &diff.skipped {
+        eprintln!("diverge: {}: binary or too large, not included in the patch", path);
+    }
+    match &args.output {
+        Some(path) => fs::write(path, &diff.patch).map_err(|e| DivergeError::io(path, e))?,
+        None => print!("{}", diff.patch),
+    }
+    Ok(report::exit_code(&result))
+}
+
+fn run_patch(args: &PatchArgs) -> Result<i32, DivergeError> {
+    let dir = resolve_path(&args.dir);
+    DivergeError::check_dir(&dir)?;
+    let patch = match args.patch.as_deref().filter(|p| *p != Path::new("-")) {
+        Some(path) => fs::read_to_string(path).map_err(|e| DivergeError::io(path, e))?,
+        None => io::read_to_string(io::stdin()).map_err(|e| DivergeError::Io {
+            path: None,
+            message: format!("Failed to read the patch from stdin: {}", e),
+        })?,
+    };
+
+    let outcome = patch::apply_patch(&dir, &patch, args.strip, args.dry_run)?;
+    for path in &outcome.patched {
+        println!("patch   {}", path);
+    }
+    for conflict in &outcome.conflicts {
+        match conflict.hunk {
+            Some(hunk) => eprintln!(
+                "diverge: {}: hunk #{} rejected ({}): {}",
+                conflict.path, hunk, conflict.header, conflict.reason
+            ),
+            None => eprintln!("diverge: {}: {}", conflict.path, conflict.reason),
+        }
+    }
+    Ok(if outcome.conflicts.is_empty() { 0 } else { 1 })
+}
+
+fn run_merge(args: &MergeArgs, config: Option<&Path>) -> Result<i32, DivergeError> {
+    let base = resolve_path(&args.base);
+    let (ours, theirs) = (resolve_path(&args.ours), resolve_path(&args.theirs));
+    let cfg = load(config)?;
+    let comparer = Comparer::new(&ours, &theirs).options(args.scan.options(&cfg));
+    let result = comparer.three_way(&base)?;
+    if !args.apply {
+        print!("{}", report::render_three_way(&result, args.format)?);
+        return Ok(if result.conflicting > result.mergeable { 1 } else { 0 });
+    }
+
+    let options = MergeOptions {
+        favor: args.favor,
+        ignore_whitespace: args.ignore_space_change,
+        ..Default::default()
+    };
+    let outcome = comparer.auto_merge(&base, &result, &options)?;
+    for path in &outcome.merged {
+        println!("merge     {}", path);
+    }
+    for path in &outcome.conflicts {
+        println!("conflict  {}", path);
+    }
+    Ok(if outcome.conflicts.is_empty() { 0 } else { 1 })
+}
+
+fn run_report(args: &ReportArgs, config: Option<&Path>) -> Result<i32, DivergeError> {
+    let result = compare_dirs(&args.compare, config)?;
+    let rendered = report::render(&result, args.compare.format)?;
+    match &args.output {
+        Some(path) => fs::write(path, rendered).map_err(|e| DivergeError::io(path, e))?,
+        None => print!("{}", rendered),
+    }
+    Ok(0)
+}
+
+fn run_sync(args: &SyncArgs, config: Option<&Path>) -> Result<i32, DivergeError> {
+    let (left, right) = (resolve_path(&args.left), resolve_path(&args.right));
+    let cfg = load(config)?;
+    // Sync works path by path, so a moved file is copied to its old path rather than paired.
+    let options = CompareOptions {
+        renames: None,
+        ..args.scan.options(&cfg)
+    };
+    let result = Comparer::new(&left, &right).options(options).run()?;
+    let direction = match args.reverse {
+        false => SyncDirection::LeftToRight,
+        true => SyncDirection::RightToLeft,
+    };
+    let filters = SyncFilters {
+        delete: args.delete,
+        paths: None,
+    };
+    let plan = sync::plan_sync(&left, &right, &result, direction, &filters);
+    if args.dry_run {
+        print!("{}", report::render_sync_plan(&plan, args.format)?);
+        return Ok(if plan.is_valid() { 0 } else { 1 });
+    }
+
+    let outcome = sync::execute_plan(&plan)?;
+    for (op, item) in plan.operations.iter().zip(&outcome.results) {
+        match &item.error {
+            Some(e) => eprintln!("diverge: {}", e),
+            None => println!("{:<9}  {}", op.kind.as_str(), op.rel_path),
+        }
+    }
+    Ok(if outcome.failed > 0 { 2 } else { 0 })
+}
+
+fn run_history(args: &HistoryArgs, config: Option<&Path>) -> Result<i32, DivergeError> {
+    let root = history::history_dir(&config_path(config)?);
+    let undone = match &args.command {
+        None | Some(HistoryCommand::List) => {
+            for session in history::list(&root)? {
+                println!("{}", session.id);
+                for op in &session.operations {
+                    let time = UNIX_EPOCH + Duration::from_millis(op.time);
+                    let files = match op.files.len() {
+                        1 => "1 file".to_string(),
+                        n => format!("{} files", n),
+                    };
+                    let undone = if op.undone { ", undone" } else { "" };
+                    let stamp = history::stamp(time);
+                    println!("{:>6}  {}  {} ({}{})", op.id, stamp, op.label, files, undone);
+                }
+            }
+            return Ok(0);
+        }
+        Some(HistoryCommand::Undo) => history::undo_last(&root)?.into_iter().collect(),
+        Some(HistoryCommand::Restore { session }) => history::restore_session(&root, session)?,
+    };
+    if undone.is_empty() {
+        eprintln!("diverge: nothing to undo");
+        return Ok(1);
+    }
+    for op in &undone {
+        println!("undo  {} {}  {}", op.session, op.id, op.label);
+    }
+    Ok(0)
+}
+
+fn run_config(cmd: ConfigCommand, config: Option<&Path>) -> Result<i32, DivergeError> {
+    let path = config_path(config)?;
+    match cmd {
+        ConfigCommand::Path => println!("{}", path.display()),
+        ConfigCommand::Show => {
+            let cfg = config::load_config(&path)?;
+            let yaml = serde_yaml::to_string(&cfg)
+                .map_err(|e| DivergeError::config(format!("Failed to serialize config: {}", e)))?;
+            print!("{}", yaml);
+        }
+        ConfigCommand::Reset => {
+            config::save_config(&path, &config::default_config())?;
+            println!("Reset {}", path.display());
+        }
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        let cli = Cli::try_parse_from(std::iter::once("diverge").chain(args.iter().copied()))?;
+        cli.validate()?;
+        Ok(cli)
+    }
+
+    #[test]
+    fn cli_definition_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn bare_positionals_open_gui() {
+        let cli = parse(&["a", "b", "--wait"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.gui.left.as_deref(), Some("a"));
+        assert_eq!(cli.gui.right.as_deref(), Some("b"));
+        assert!(cli.gui.wait);
+    }
+
+    #[test]
+    fn double_dash_allows_dash_prefixed_dirs() {
+        let cli = parse(&["--", "-weird-dir", "other"]).unwrap();
+        assert_eq!(cli.gui.left.as_deref(), Some("-weird-dir"));
+    }
+
+    #[test]
+    fn unknown_flag_is_an_error() {
+        let err = parse(&["a", "b", "--frobnicate"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::UnknownArgument);
+    }
+
+    #[test]
+    fn compare_takes_valued_options() {
+        let cli = parse(&[
+            "--config", "/tmp/cfg.yaml", "compare", "a", "b", "--format", "jsonl", "--ignore",
+            "*.log", "--ignore", "dist",
+        ])
+        .unwrap();
+        assert_eq!(cli.config.as_deref(), Some(Path::new("/tmp/cfg.yaml")));
+        match cli.command {
+            Some(Command::Compare(args)) => {
+                assert_eq!(args.format, OutputFormat::Jsonl);
+                assert_eq!(args.scan.patterns, vec!["*.log", "dist"]);
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn diff_context_defaults_to_three() {
+        let context = |args: &[&str]| match parse(args).unwrap().command {
+            Some(Command::Diff(args)) => args.context(),
+            other => panic!("unexpected command: {:?}", other),
+        };
+        assert_eq!(context(&["diff", "a", "b"]), 3);
+        assert_eq!(context(&["diff", "a", "b", "--unified"]), 3);
+        assert_eq!(context(&["diff", "-u", "a", "b"]), 3);
+        assert_eq!(context(&["diff", "a", "b", "-U0"]), 0);
+        assert_eq!(context(&["diff", "-U", "5", "a", "b"]), 5);
+        assert_eq!(context(&["diff", "a", "b", "--unified=10"]), 10);
+    }
+
+    #[test]
+    fn report_writes_to_output_file() {
+        let (left, right) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        fs::write(left.path().join("a.txt"), "one").unwrap();
+        fs::write(right.path().join("a.txt"), "two").unwrap();
+        let out = left.path().join("report.json");
+        let config = right.path().join("config.yaml");
+
+        let cli = parse(&[
+            "report",
+            left.path().to_str().unwrap(),
+            right.path().to_str().unwrap(),
+            "-f",
+            "json",
+            "--ignore",
+            "report.json",
+            "-o",
+            out.to_str().unwrap(),
+        ])
+        .unwrap();
+        assert_eq!(execute(cli.command.unwrap(), Some(&config)), 0);
+
+        let parsed: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&out).unwrap()).unwrap();
+        assert_eq!(parsed["summary"]["different"], 1);
+    }
+
+    #[test]
+    fn merge_applies_their_changes() {
+        let dirs: Vec<_> = (0..3).map(|_| tempfile::tempdir().unwrap()).collect();
+        for (dir, text) in dirs.iter().zip(["1\n2\n3\n", "one\n2\n3\n", "1\n2\nthree\n"]) {
+            fs::write(dir.path().join("f.txt"), text).unwrap();
+        }
+        let config = dirs[0].path().join("config.yaml");
+        let merge = |extra: &[&str]| {
+            let mut args = vec!["merge"];
+            args.extend(dirs.iter().map(|d| d.path().to_str().unwrap()));
+            args.extend(extra);
+            execute(parse(&args).unwrap().command.unwrap(), Some(&config))
+        };
+
+        assert_eq!(merge(&[]), 0);
+        assert_eq!(merge(&["--apply"]), 0);
+        let merged = fs::read_to_string(dirs[1].path().join("f.txt")).unwrap();
+        assert_eq!(merged, "one\n2\nthree\n");
+
+        fs::write(dirs[2].path().join("f.txt"), "uno\n2\nthree\n").unwrap();
+        assert_eq!(merge(&["-f", "json"]), 1);
+        assert_eq!(merge(&["--apply"]), 1);
+        assert_eq!(merge(&["--apply", "--favor", "theirs"]), 0);
+        let merged = fs::read_to_string(dirs[1].path().join("f.txt")).unwrap();
+        assert_eq!(merged, "uno\n2\nthree\n");
+        assert!(parse(&["merge", "a", "b", "c", "--apply", "-f", "json"]).is_err());
+        assert!(parse(&["merge", "a", "b", "c", "--favor", "ours"]).is_err());
+    }
+
+    #[test]
+    fn patch_exits_one_on_rejected_hunks() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "one\n").unwrap();
+        let patch_file = dir.path().join("fix.patch");
+        let root = dir.path().to_str().unwrap();
+        let run = |patch: &str| {
+            fs::write(&patch_file, patch).unwrap();
+            let cli = parse(&["patch", root, patch_file.to_str().unwrap(), "-p0"]).unwrap();
+            execute(cli.command.unwrap(), None)
+        };
+
+        assert_eq!(run("--- a.txt\n+++ a.txt\n@@ -1 +1 @@\n-one\n+two\n"), 0);
+        assert_eq!(fs::read_to_string(dir.path().join("a.txt")).unwrap(), "two\n");
+        assert_eq!(run("--- a.txt\n+++ a.txt\n@@ -1 +1 @@\n-one\n+three\n"), 1);
+        assert_eq!(run("not a patch\n"), 2);
+    }
+
+    #[test]
+    fn history_lists_by_default() {
+        match parse(&["history"]).unwrap().command {
+            Some(Command::History(args)) => assert!(args.command.is_none()),
+            other => panic!("unexpected command: {:?}", other),
+        }
+        match parse(&["history", "restore", "20261017-120000-42"]).unwrap().command {
+            Some(Command::History(HistoryArgs {
+                command: Some(HistoryCommand::Restore { session }),
+            })) => assert_eq!(session, "20261017-120000-42"),
+            other => panic!("unexpected command: {:?}", other),
+        }
+        assert!(parse(&["history", "restore"]).is_err());
+    }
+
+    #[test]
+    fn directories_before_subcommand_are_rejected() {
+        let err = parse(&["a", "compare", "x", "y"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn config_is_global() {
+        let cli = parse(&["config", "show", "--config", "x.yaml"]).unwrap();
+        assert_eq!(cli.config.as_deref(), Some(Path::new("x.yaml")));
+    }
+
+    #[test]
+    fn headless_flag_needs_both_dirs() {
+        assert!(parse(&["--headless", "a", "b", "-f", "json"]).is_ok());
+        assert!(parse(&["--headless", "a"]).is_err());
+        assert!(parse(&["a", "b", "--format", "json"]).is_err());
+        assert!(parse(&["--headless", "a", "b", "--wait"]).is_err());
+    }
+
+    #[test]
+    fn headless_flag_takes_scan_options() {
+        let cli = parse(&["--headless", "a", "b", "--ignore", "*.log", "-j", "2", "--gitignore"]).unwrap();
+        assert_eq!(cli.scan.patterns, vec!["*.log"]);
+        assert_eq!((cli.scan.threads, cli.scan.gitignore), (Some(2), true));
+        assert!(parse(&["a", "b", "--ignore", "*.log"]).is_err());
+        assert!(parse(&["--ignore", "*.log", "compare", "a", "b"]).is_err());
+    }
+
+    #[test]
+    fn scan_args_extend_config() {
+        let cfg = config::default_config();
+        let args = ScanArgs {
+            patterns: vec!["extra".to_string()],
+            ..Default::default()
+        };
+        let options = args.options(&cfg);
+        assert!(options.ignore_dirs.contains(&".git".to_string()));
+        assert!(options.ignore_dirs.contains(&"extra".to_string()));
+        assert!(!options.trust_mtime);
+        assert_eq!(options.max_file_size, cfg.max_file_size);
+
+        let args = ScanArgs {
+            patterns: vec!["extra".to_string()],
+            no_config_ignores: true,
+            paranoid: true,
+            max_file_size: Some(1024),
+            threads: Some(2),
+            ..Default::default()
+        };
+        let options = args.options(&cfg);
+        assert_eq!(options.ignore_dirs, vec!["extra"]);
+        assert!(options.paranoid);
+        assert_eq!(options.max_file_size, 1024);
+        assert!(!options.respect_gitignore);
+        assert_eq!(options.threads, 2);
+    }
+
+    #[test]
+    fn gitignore_flags_override_config() {
+        let mut cfg = config::default_config();
+        let Some(Command::Compare(args)) = parse(&["compare", "a", "b", "--gitignore"]).unwrap().command else {
+            panic!("expected compare");
+        };
+        assert!(args.scan.options(&cfg).respect_gitignore);
+
+        cfg.respect_gitignore = true;
+        let Some(Command::Compare(args)) = parse(&["compare", "a", "b", "--no-gitignore"]).unwrap().command else {
+            panic!("expected compare");
+        };
+        assert!(!args.scan.options(&cfg).respect_gitignore);
+    }
+
+    #[test]
+    fn find_renames_takes_optional_percentage() {
+        let mut cfg = config::default_config();
+        let renames = |args: &[&str], cfg: &AppConfig| match parse(args).unwrap().command {
+            Some(Command::Compare(args)) => args.scan.options(cfg).renames,
+            other => panic!("unexpected command: {:?}", other),
+        };
+        assert_eq!(renames(&["compare", "a", "b"], &cfg), None);
+        assert_eq!(renames(&["compare", "a", "b", "-M"], &cfg), Some(50));
+        assert_eq!(renames(&["compare", "a", "b", "-M=80"], &cfg), Some(80));
+        assert_eq!(renames(&["compare", "a", "b", "--find-renames=100"], &cfg), Some(100));
+        assert!(parse(&["compare", "a", "b", "-M=101"]).is_err());
+
+        cfg.detect_renames = true;
+        cfg.rename_similarity = 70;
+        assert_eq!(renames(&["compare", "a", "b"], &cfg), Some(70));
+        assert_eq!(renames(&["compare", "a", "b", "--no-renames"], &cfg), None);
+    }
+
+    #[test]
+    fn sync_copies_and_deletes() {
+        let left = tempfile::tempdir().unwrap();
+        let right = tempfile::tempdir().unwrap();
+        let cfg_dir = tempfile::tempdir().unwrap();
+        let cfg_path = cfg_dir.path().join("config.yaml");
+        fs::create_dir_all(left.path().join("sub")).unwrap();
+        fs::write(left.path().join("sub/new.txt"), "new").unwrap();
+        fs::write(left.path().join("changed.txt"), "left").unwrap();
+        fs::write(right.path().join("changed.txt"), "right").unwrap();
+        fs::write(right.path().join("extra.txt"), "extra").unwrap();
+
+        let args = SyncArgs {
+            left: left.path().to_string_lossy().to_string(),
+            right: right.path().to_string_lossy().to_string(),
+            dry_run: false,
+            format: OutputFormat::Text,
+            delete: true,
+            reverse: false,
+            scan: ScanArgs::default(),
+        };
+        assert_eq!(run_sync(&args, Some(&cfg_path)).unwrap(), 0);
+
+        assert_eq!(fs::read_to_string(right.path().join("sub/new.txt")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(right.path().join("changed.txt")).unwrap(), "left");
+        assert!(!right.path().join("extra.txt").exists());
+    }
+
+    #[test]
+    fn sync_dry_run_touches_nothing() {
+        let left = tempfile::tempdir().unwrap();
+        let right = tempfile::tempdir().unwrap();
+        let cfg_dir = tempfile::tempdir().unwrap();
+        fs::write(left.path().join("a.txt"), "a").unwrap();
+
+        let args = SyncArgs {
+            left: left.path().to_string_lossy().to_string(),
+            right: right.path().to_string_lossy().to_string(),
+            dry_run: true,
+            format: OutputFormat::Text,
+            delete: false,
+            reverse: false,
+            scan: ScanArgs::default(),
+        };
+        let cfg_path = cfg_dir.path().join("config.yaml");
+        assert_eq!(run_sync(&args, Some(&cfg_path)).unwrap(), 0);
+        assert!(!right.path().join("a.txt").exists());
+    }
+
+    #[test]
+    fn sync_reverse_copies_into_left() {
+        let (left, right) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let config = left.path().join("config.yaml");
+        fs::write(right.path().join("b.txt"), "b").unwrap();
+        fs::write(left.path().join("a.txt"), "a").unwrap();
+        let (l, r) = (left.path().to_str().unwrap(), right.path().to_str().unwrap());
+        let sync = |extra: &[&str]| {
+            let mut args = vec!["sync", l, r, "--ignore", "config.yaml"];
+            args.extend(extra);
+            execute(parse(&args).unwrap().command.unwrap(), Some(&config))
+        };
+
+        assert_eq!(sync(&["-R", "--delete", "-n", "-f", "json"]), 0);
+        assert!(left.path().join("a.txt").exists());
+        assert_eq!(sync(&["--reverse", "--delete"]), 0);
+        assert_eq!(fs::read_to_string(left.path().join("b.txt")).unwrap(), "b");
+        assert!(!left.path().join("a.txt").exists());
+        assert!(parse(&["sync", "a", "b", "-f", "json"]).is_err());
+    }
+}
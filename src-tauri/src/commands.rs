@@ -1,69 +1,424 @@
 use std::fs;
-use std::path::Path;
-use std::sync::Mutex;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
+use tauri::Manager;
+
+use crate::changes::{ApplyOptions, Change, ChangesOutcome};
 use crate::compare;
+use crate::comparer::Comparer;
 use crate::config;
-use crate::models::{AppConfig, AppState, CliArgs, CompareResult};
+use crate::diff::{self, UnifiedDiff, DEFAULT_CONTEXT};
+use crate::error::DivergeError;
+use crate::history::{self, Operation, RestoreOutcome, Session};
+use crate::job::CompareJob;
+use crate::merge::{self, MergeOptions, TextMerge};
+use crate::models::{
+    AppConfig, AppState, CliArgs, CompareOptions, CompareResult, EntryContents, Side,
+    ThreeWayResult,
+};
+use crate::patch::PatchOutcome;
+use crate::scanner;
+use crate::sync::{self, SyncDirection, SyncFilters, SyncItem, SyncOutcome, SyncPlan};
+use crate::threeway::MergeOutcome;
+use crate::write::{self, FileState, WriteOptions};
+
+fn compare_options(
+    left: &str,
+    right: &str,
+    respect_gitignore: Option<bool>,
+    state: &Mutex<AppState>,
+) -> Result<CompareOptions, DivergeError> {
+    DivergeError::check_dir(left)?;
+    DivergeError::check_dir(right)?;
+    let mut options = state
+        .lock()
+        .map(|s| CompareOptions::from_config(&s.config))
+        .map_err(|_| DivergeError::state())?;
+    if let Some(respect) = respect_gitignore {
+        options.respect_gitignore = respect;
+    }
+    scanner::check_patterns(&options.ignore_dirs)?;
+    Ok(options)
+}
+
+/// The directories of the last comparison run.
+fn compared_dirs(state: &Mutex<AppState>) -> Result<(String, String), DivergeError> {
+    state
+        .lock()
+        .map(|s| s.compared_dirs.clone())
+        .map_err(|_| DivergeError::state())?
+        .ok_or_else(|| DivergeError::invalid("No comparison has been run yet"))
+}
+
+/// Keeps the comparison a command ran again, for sync plans.
+fn remember(result: Option<&CompareResult>, state: &Mutex<AppState>) {
+    if let (Some(result), Ok(mut s)) = (result, state.lock()) {
+        s.last_result = Some(result.clone());
+    }
+}
 
 #[tauri::command]
 pub fn compare_directories(
     left: String,
     right: String,
+    respect_gitignore: Option<bool>,
     state: tauri::State<'_, Mutex<AppState>>,
-) -> Result<CompareResult, String> {
-    if !Path::new(&left).is_dir() {
-        return Err(format!("Left path is not a directory: {}", left));
-    }
-    if !Path::new(&right).is_dir() {
-        return Err(format!("Right path is not a directory: {}", right));
-    }
-    let ignore_dirs = state
+) -> Result<CompareResult, DivergeError> {
+    let options = compare_options(&left, &right, respect_gitignore, &state)?;
+    let result = compare::compare(&left, &right, &options);
+    let mut s = state.lock().map_err(|_| DivergeError::state())?;
+    s.compared_dirs = Some((left, right));
+    s.last_result = Some(result.clone());
+    Ok(result)
+}
+
+/// Starts a comparison in the background and returns its job id right away. Progress,
+/// partial entries and the final result arrive as `compare-*` events tagged with that id.
+/// Any comparison still running is cancelled first.
+#[tauri::command]
+pub fn start_compare(
+    left: String,
+    right: String,
+    respect_gitignore: Option<bool>,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<u64, DivergeError> {
+    let options = compare_options(&left, &right, respect_gitignore, &state)?;
+    let cancel = Arc::new(AtomicBool::new(false));
+    let job_id = {
+        let mut s = state
+            .lock()
+            .map_err(|_| DivergeError::state())?;
+        for flag in s.jobs.values() {
+            flag.store(true, Ordering::Relaxed);
+        }
+        let job_id = s.next_job_id;
+        s.next_job_id += 1;
+        s.jobs.insert(job_id, cancel.clone());
+        // Set up front so entries can be opened while results are still streaming in.
+        s.compared_dirs = Some((left.clone(), right.clone()));
+        s.last_result = None;
+        job_id
+    };
+
+    std::thread::spawn(move || {
+        let job = CompareJob::new(app.clone(), job_id, cancel);
+        let result = compare::compare_with_progress(&left, &right, &options, &job);
+        let state = app.state::<Mutex<AppState>>();
+        if let Ok(mut s) = state.lock() {
+            s.jobs.remove(&job_id);
+            // A newer comparison may have started meanwhile.
+            if s.next_job_id == job_id + 1 {
+                s.last_result = result.clone();
+            }
+        }
+        job.finish(result);
+    });
+    Ok(job_id)
+}
+
+/// Asks a running comparison to stop. Returns false if no such job is running.
+#[tauri::command]
+pub fn cancel_compare(job_id: u64, state: tauri::State<'_, Mutex<AppState>>) -> Result<bool, DivergeError> {
+    let s = state
         .lock()
-        .map(|s| s.config.ignore_dirs.clone())
-        .map_err(|_| "Failed to read application state".to_string())?;
-    Ok(compare::compare(&left, &right, &ignore_dirs))
+        .map_err(|_| DivergeError::state())?;
+    Ok(match s.jobs.get(&job_id) {
+        Some(flag) => {
+            flag.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    })
 }
 
 #[tauri::command]
-pub fn write_file(path: String, content: String) -> Result<(), String> {
-    if let Some(parent) = Path::new(&path).parent() {
-        fs::create_dir_all(parent)
-            .map_err(|e| format!("Failed to create directory {}: {}", parent.display(), e))?;
+pub fn get_entry_contents(
+    rel_path: String,
+    renamed_from: Option<String>,
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<EntryContents, DivergeError> {
+    let (left, right) = compared_dirs(&state)?;
+    let max_file_size = state
+        .lock()
+        .map(|s| s.config.max_file_size)
+        .map_err(|_| DivergeError::state())?;
+    let from = renamed_from.as_deref().unwrap_or(&rel_path);
+    compare::entry_contents(&left, &right, from, &rel_path, max_file_size)
+}
+
+/// A patch for the compared directories: for one entry when `rel_path` is given, otherwise
+/// for the whole tree (compared again with the current settings).
+#[tauri::command]
+pub fn unified_diff(
+    rel_path: Option<String>,
+    renamed_from: Option<String>,
+    context: Option<usize>,
+    respect_gitignore: Option<bool>,
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<UnifiedDiff, DivergeError> {
+    let (left, right) = compared_dirs(&state)?;
+    let context = context.unwrap_or(DEFAULT_CONTEXT);
+
+    match rel_path {
+        Some(rel_path) => {
+            let from = renamed_from.as_deref().unwrap_or(&rel_path);
+            Ok(UnifiedDiff {
+                patch: diff::file_diff(&left, &right, from, &rel_path, context)?,
+                skipped: vec![],
+            })
+        }
+        None => {
+            let options = compare_options(&left, &right, respect_gitignore, &state)?;
+            let result = compare::compare(&left, &right, &options);
+            diff::tree_diff(&left, &right, &result, context)
+        }
     }
-    fs::write(&path, &content).map_err(|e| format!("Failed to write {}: {}", path, e))
+}
+
+/// Applies a unified diff or git patch to one side of the comparison, then compares again.
+/// `strip` leading path components are removed from the patch's paths (default 1, as for
+/// `git diff` output).
+#[tauri::command]
+pub fn apply_patch(
+    patch: String,
+    side: Side,
+    strip: Option<usize>,
+    respect_gitignore: Option<bool>,
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<PatchOutcome, DivergeError> {
+    let (left, right) = compared_dirs(&state)?;
+    let options = compare_options(&left, &right, respect_gitignore, &state)?;
+    let outcome = Comparer::new(&left, &right)
+        .options(options)
+        .apply_patch(side, &patch, strip.unwrap_or(1))?;
+    remember(outcome.result.as_ref(), &state);
+    Ok(outcome)
+}
+
+/// Copies and deletes files between the compared directories, one action per item, and
+/// reports how each went along with the comparison afterwards.
+#[tauri::command]
+pub fn sync_entries(
+    items: Vec<SyncItem>,
+    respect_gitignore: Option<bool>,
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<SyncOutcome, DivergeError> {
+    let (left, right) = compared_dirs(&state)?;
+    let options = compare_options(&left, &right, respect_gitignore, &state)?;
+    let outcome = Comparer::new(&left, &right).options(options).sync(&items)?;
+    remember(outcome.result.as_ref(), &state);
+    Ok(outcome)
+}
+
+/// Plans a sync of the last comparison: every copy, overwrite and delete with its size,
+/// and the targets that changed on disk since, which make the plan invalid.
+#[tauri::command]
+pub fn plan_sync(
+    direction: SyncDirection,
+    filters: Option<SyncFilters>,
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<SyncPlan, DivergeError> {
+    let (left, right) = compared_dirs(&state)?;
+    let result = state
+        .lock()
+        .map(|s| s.last_result.clone())
+        .map_err(|_| DivergeError::state())?;
+    let result = result.ok_or_else(|| DivergeError::invalid("The comparison has not finished yet"))?;
+    Ok(sync::plan_sync(&left, &right, &result, direction, &filters.unwrap_or_default()))
+}
+
+/// Runs a plan from `plan_sync` if it is still valid, then compares again.
+#[tauri::command]
+pub fn execute_sync_plan(
+    plan: SyncPlan,
+    respect_gitignore: Option<bool>,
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<SyncOutcome, DivergeError> {
+    let (left, right) = compared_dirs(&state)?;
+    let options = compare_options(&left, &right, respect_gitignore, &state)?;
+    let outcome = Comparer::new(&left, &right).options(options).run_plan(&plan)?;
+    remember(outcome.result.as_ref(), &state);
+    Ok(outcome)
+}
+
+/// Compares `ours` and `theirs` with their common ancestor `base`.
+#[tauri::command]
+pub fn compare_three_way(
+    base: String,
+    ours: String,
+    theirs: String,
+    respect_gitignore: Option<bool>,
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<ThreeWayResult, DivergeError> {
+    let options = compare_options(&ours, &theirs, respect_gitignore, &state)?;
+    Comparer::new(&ours, &theirs).options(options).three_way(&base)
+}
+
+/// Compares the three trees and merges the changes of `theirs` into `ours` where they do
+/// not conflict, returning what was merged and the comparison afterwards.
+#[tauri::command]
+pub fn auto_merge(
+    base: String,
+    ours: String,
+    theirs: String,
+    merge_options: Option<MergeOptions>,
+    respect_gitignore: Option<bool>,
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<MergeOutcome, DivergeError> {
+    let merge_options = merge_options.unwrap_or_default();
+    let options = compare_options(&ours, &theirs, respect_gitignore, &state)?;
+    let comparer = Comparer::new(&ours, &theirs).options(options);
+    let result = comparer.three_way(&base)?;
+    comparer.auto_merge(&base, &result, &merge_options)
+}
+
+/// Three-way merge of one file, with conflicts marked diff3-style and listed as hunks.
+/// A missing file merges as empty.
+#[tauri::command]
+pub fn merge_file(
+    base: String,
+    ours: String,
+    theirs: String,
+    options: Option<MergeOptions>,
+) -> Result<TextMerge, DivergeError> {
+    let read = |path: &str| match fs::read(path) {
+        Ok(bytes) => Ok(String::from_utf8_lossy(&bytes).into_owned()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(DivergeError::io(Path::new(path), e)),
+    };
+    let (base, ours, theirs) = (read(&base)?, read(&ours)?, read(&theirs)?);
+    Ok(merge::merge_text(&base, &ours, &theirs, &options.unwrap_or_default()))
+}
+
+/// Writes a file the comparison read, unless it changed on disk since: `expected` is its
+/// state when compared (`null` if it did not exist), and a mismatch fails with a conflict
+/// unless `force` is set. `options` says what to keep of the file it replaces. Returns the
+/// state of the file as written, for the next write.
+#[tauri::command]
+pub fn write_file(
+    path: String,
+    content: String,
+    expected: Option<FileState>,
+    force: Option<bool>,
+    options: Option<WriteOptions>,
+) -> Result<FileState, DivergeError> {
+    let (force, options) = (force.unwrap_or(false), options.unwrap_or_default());
+    write::write_file_checked(Path::new(&path), content.as_bytes(), expected.as_ref(), force, options)
+}
+
+/// Writes a batch of edited files into the compared directories as one undoable operation,
+/// rolling every write back if one fails and `options.rollback` is set. Returns how each
+/// change went and the changed entries compared again, merged into the last comparison
+/// without rescanning.
+#[tauri::command]
+pub fn apply_changes(
+    changes: Vec<Change>,
+    options: Option<ApplyOptions>,
+    respect_gitignore: Option<bool>,
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<ChangesOutcome, DivergeError> {
+    let (left, right) = compared_dirs(&state)?;
+    let compare = compare_options(&left, &right, respect_gitignore, &state)?;
+    let last = state
+        .lock()
+        .map(|s| s.last_result.clone())
+        .map_err(|_| DivergeError::state())?;
+    let outcome = Comparer::new(&left, &right).options(compare).apply_changes(
+        &changes,
+        &options.unwrap_or_default(),
+        last.as_ref(),
+    )?;
+    remember(outcome.result.as_ref(), &state);
+    Ok(outcome)
+}
+
+/// The undo history, oldest session first.
+#[tauri::command]
+pub fn list_history(state: tauri::State<'_, Mutex<AppState>>) -> Result<Vec<Session>, DivergeError> {
+    history::list(&history_root(&state)?)
+}
+
+/// Undoes the latest write or delete, from this session or an earlier one, and compares
+/// again. Nothing is restored if there is nothing left to undo.
+#[tauri::command]
+pub fn undo_last_operation(
+    respect_gitignore: Option<bool>,
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<RestoreOutcome, DivergeError> {
+    let restored = history::undo_last(&history_root(&state)?)?;
+    restore_outcome(restored.into_iter().collect(), respect_gitignore, &state)
+}
+
+/// Undoes every operation of a session, newest first, and compares again.
+#[tauri::command]
+pub fn restore_session(
+    session: String,
+    respect_gitignore: Option<bool>,
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<RestoreOutcome, DivergeError> {
+    let restored = history::restore_session(&history_root(&state)?, &session)?;
+    restore_outcome(restored, respect_gitignore, &state)
+}
+
+fn history_root(state: &Mutex<AppState>) -> Result<PathBuf, DivergeError> {
+    state
+        .lock()
+        .map(|s| history::history_dir(&s.config_path))
+        .map_err(|_| DivergeError::state())
+}
+
+/// The operations undone with the comparison redone after them, if one was run.
+fn restore_outcome(
+    restored: Vec<Operation>,
+    respect_gitignore: Option<bool>,
+    state: &Mutex<AppState>,
+) -> Result<RestoreOutcome, DivergeError> {
+    let result = match compared_dirs(state) {
+        Ok((left, right)) => {
+            let options = compare_options(&left, &right, respect_gitignore, state)?;
+            Some(Comparer::new(&left, &right).options(options).run()?)
+        }
+        Err(DivergeError::InvalidInput { .. }) => None,
+        Err(e) => return Err(e),
+    };
+    remember(result.as_ref(), state);
+    Ok(RestoreOutcome { restored, result })
 }
 
 #[tauri::command]
-pub fn read_file(path: String) -> Result<String, String> {
-    fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path, e))
+pub fn read_file(path: String) -> Result<String, DivergeError> {
+    fs::read_to_string(&path).map_err(|e| DivergeError::io(Path::new(&path), e))
 }
 
 #[tauri::command]
-pub fn get_cli_args(state: tauri::State<'_, Mutex<AppState>>) -> Result<CliArgs, String> {
+pub fn get_cli_args(state: tauri::State<'_, Mutex<AppState>>) -> Result<CliArgs, DivergeError> {
     state
         .lock()
         .map(|s| s.cli_args.clone())
-        .map_err(|_| "Failed to read application state".to_string())
+        .map_err(|_| DivergeError::state())
 }
 
 #[tauri::command]
-pub fn get_config(state: tauri::State<'_, Mutex<AppState>>) -> Result<AppConfig, String> {
+pub fn get_config(state: tauri::State<'_, Mutex<AppState>>) -> Result<AppConfig, DivergeError> {
     state
         .lock()
         .map(|s| s.config.clone())
-        .map_err(|_| "Failed to read application state".to_string())
+        .map_err(|_| DivergeError::state())
 }
 
 #[tauri::command]
 pub fn save_config(
     new_config: AppConfig,
     state: tauri::State<'_, Mutex<AppState>>,
-) -> Result<(), String> {
-    config::save_config(&new_config)?;
+) -> Result<(), DivergeError> {
+    scanner::check_patterns(&new_config.ignore_dirs)?;
     let mut s = state
         .lock()
-        .map_err(|_| "Failed to read application state".to_string())?;
+        .map_err(|_| DivergeError::state())?;
+    config::save_config(&s.config_path, &new_config)?;
     s.config = new_config;
     Ok(())
 }
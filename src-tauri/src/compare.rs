@@ -1,88 +1,349 @@
-use crate::models::{CompareEntry, CompareResult};
-use crate::scanner::scan_dir;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use std::fs;
+use std::path::{Component, Path};
+
+use crate::binary::{describe_difference, is_binary};
+use crate::error::DivergeError;
+use crate::hash::{hash_file, same_contents};
+use crate::models::{
+    CompareEntry, CompareOptions, CompareResult, EntryContents, EntryKind, EntryStatus,
+};
+use crate::rename::detect_renames;
+use crate::scanner::{scan_dir, IgnoreFiles, ScannedFile, DIVERGE_IGNORE};
+use crate::stats::{count_lines, line_diff, LineStats};
+
+/// Hooks for following a long comparison. Methods are called from worker threads.
+pub trait Progress: Sync {
+    /// A file was found while scanning either side.
+    fn discovered(&self, _path: &Path) {}
+    /// Scanning is done and `total` entries are about to be compared.
+    fn scanned(&self, _total: usize) {}
+    /// One entry has been compared. Entries arrive in no particular order.
+    fn compared(&self, _entry: &CompareEntry) {}
+    /// Polled between files; returning true abandons the comparison.
+    fn cancelled(&self) -> bool {
+        false
+    }
+}
+
+impl Progress for () {}
 
-pub fn compare(left: &str, right: &str, ignore_dirs: &[String]) -> CompareResult {
-    let (left_files, left_ignored) = scan_dir(left, ignore_dirs);
-    let (right_files, right_ignored) = scan_dir(right, ignore_dirs);
+/// Compares two directory trees. Both sides are scanned concurrently and files are
+/// hashed on a pool of `options.threads` workers; `entries` are sorted by path.
+pub fn compare(left: &str, right: &str, options: &CompareOptions) -> CompareResult {
+    compare_with_progress(left, right, options, &()).unwrap_or_default()
+}
+
+/// Like `compare`, reporting to `progress` as it goes. Returns `None` if cancelled.
+pub fn compare_with_progress(
+    left: &str,
+    right: &str,
+    options: &CompareOptions,
+    progress: &dyn Progress,
+) -> Option<CompareResult> {
+    match ThreadPoolBuilder::new().num_threads(options.threads).build() {
+        Ok(pool) => pool.install(|| compare_on_pool(left, right, options, progress)),
+        Err(_) => compare_on_pool(left, right, options, progress),
+    }
+}
+
+fn compare_on_pool(
+    left: &str,
+    right: &str,
+    options: &CompareOptions,
+    progress: &dyn Progress,
+) -> Option<CompareResult> {
+    // A .divergeignore at either root applies to both sides, so the two trees are filtered alike.
+    let ignore_files = IgnoreFiles {
+        vcs: options.respect_gitignore,
+        extra: vec![Path::new(left).join(DIVERGE_IGNORE), Path::new(right).join(DIVERGE_IGNORE)],
+    };
+    let on_file = |path: &Path| {
+        progress.discovered(path);
+        !progress.cancelled()
+    };
+    let ((left_files, left_ignored), (right_files, right_ignored)) = rayon::join(
+        || scan_dir(left, &options.ignore_dirs, &ignore_files, &on_file),
+        || scan_dir(right, &options.ignore_dirs, &ignore_files, &on_file),
+    );
+    if progress.cancelled() {
+        return None;
+    }
 
     let mut ignored_dirs = left_ignored;
     ignored_dirs.extend(right_ignored);
     ignored_dirs.sort();
     ignored_dirs.dedup();
 
-    let mut all_keys: Vec<String> = left_files
-        .keys()
-        .chain(right_files.keys())
-        .cloned()
-        .collect();
+    let mut all_keys: Vec<&String> = left_files.keys().chain(right_files.keys()).collect();
     all_keys.sort();
     all_keys.dedup();
-
-    let mut entries = Vec::new();
-    let (mut identical, mut different, mut only_left, mut only_right) = (0, 0, 0, 0);
-
-    for key in &all_keys {
-        let l = left_files.get(key);
-        let r = right_files.get(key);
-
-        let entry = match (l, r) {
-            (Some((lp, lc)), Some((rp, rc))) => {
-                if lc == rc {
-                    identical += 1;
-                } else {
-                    different += 1;
-                }
-                CompareEntry {
-                    rel_path: key.clone(),
-                    status: if lc == rc { "identical" } else { "different" }.to_string(),
-                    left_content: lc.clone(),
-                    right_content: rc.clone(),
-                    left_path: lp.clone(),
-                    right_path: rp.clone(),
-                }
+    progress.scanned(all_keys.len());
+
+    // An indexed parallel iterator collects in input order, so entries stay sorted.
+    let mut entries: Vec<CompareEntry> = all_keys
+        .par_iter()
+        .filter_map(|key| {
+            if progress.cancelled() {
+                return None;
             }
-            (Some((lp, lc)), None) => {
-                only_left += 1;
-                CompareEntry {
-                    rel_path: key.clone(),
-                    status: "only_left".to_string(),
-                    left_content: lc.clone(),
-                    right_content: String::new(),
-                    left_path: lp.clone(),
-                    right_path: String::new(),
-                }
-            }
-            (None, Some((rp, rc))) => {
-                only_right += 1;
-                CompareEntry {
-                    rel_path: key.clone(),
-                    status: "only_right".to_string(),
-                    left_content: String::new(),
-                    right_content: rc.clone(),
-                    left_path: String::new(),
-                    right_path: rp.clone(),
-                }
-            }
-            (None, None) => continue,
-        };
-        entries.push(entry);
+            let entry = compare_entry(key, left_files.get(*key), right_files.get(*key), options)?;
+            progress.compared(&entry);
+            Some(entry)
+        })
+        .collect();
+    if progress.cancelled() {
+        return None;
+    }
+    if let Some(threshold) = options.renames {
+        entries = detect_renames(entries, threshold);
     }
 
-    CompareResult {
-        total: entries.len(),
-        identical,
-        different,
-        only_left,
-        only_right,
+    let mut result = CompareResult {
         entries,
         ignored_dirs,
+        ..Default::default()
+    };
+    recount(&mut result);
+    Some(result)
+}
+
+/// Sets the totals of `result` from its entries.
+fn recount(result: &mut CompareResult) {
+    let entries = &result.entries;
+    let count = |status| entries.iter().filter(|e| e.status == status).count();
+    result.total = entries.len();
+    result.identical = count(EntryStatus::Identical);
+    result.different = count(EntryStatus::Different);
+    result.only_left = count(EntryStatus::OnlyLeft);
+    result.only_right = count(EntryStatus::OnlyRight);
+    result.renamed = count(EntryStatus::Renamed);
+    result.too_large = entries.iter().filter(|e| e.kind == EntryKind::TooLarge).count();
+    result.line_totals = entries.iter().filter_map(|e| e.line_stats).sum();
+}
+
+/// Compares one entry again after its files were written, found at `from` on the left and
+/// `to` on the right (the same path unless the entry was renamed). `None` if neither file
+/// exists.
+pub(crate) fn compare_path(
+    left: &str,
+    right: &str,
+    from: &str,
+    to: &str,
+    options: &CompareOptions,
+) -> Result<Option<CompareEntry>, DivergeError> {
+    let scanned = |root: &str, rel: &str| -> Result<Option<ScannedFile>, DivergeError> {
+        let path = Path::new(root).join(relative(rel)?);
+        Ok(fs::metadata(&path).ok().filter(|m| m.is_file()).map(|meta| ScannedFile {
+            abs_path: path.to_string_lossy().into_owned(),
+            size: meta.len(),
+            modified: meta.modified().ok(),
+        }))
+    };
+    let (l, r) = (scanned(left, from)?, scanned(right, to)?);
+    let Some(mut entry) = compare_entry(to, l.as_ref(), r.as_ref(), options) else {
+        return Ok(None);
+    };
+    if from != to && l.is_some() && r.is_some() {
+        entry.status = EntryStatus::Renamed;
+        entry.renamed_from = Some(from.to_string());
+        entry.similarity = entry.similarity.or(Some(100));
+    }
+    Ok(Some(entry))
+}
+
+/// Puts `entries` into `result` in place of those with the same path, in path order, and
+/// recounts the totals. A path with no entry any more is dropped.
+pub(crate) fn replace_entries(result: &mut CompareResult, paths: &[String], entries: &[CompareEntry]) {
+    result.entries.retain(|e| !paths.contains(&e.rel_path));
+    result.entries.extend(entries.iter().cloned());
+    result.entries.sort_by(|a, b| a.rel_path.cmp(&b.rel_path));
+    recount(result);
+}
+
+fn compare_entry(
+    key: &str,
+    l: Option<&ScannedFile>,
+    r: Option<&ScannedFile>,
+    options: &CompareOptions,
+) -> Option<CompareEntry> {
+    let (status, left_hash, right_hash) = match (l, r) {
+        (Some(lf), Some(rf)) => {
+            let verdict = compare_files(lf, rf, options);
+            let status = if verdict.identical {
+                EntryStatus::Identical
+            } else {
+                EntryStatus::Different
+            };
+            (status, verdict.left_hash, verdict.right_hash)
+        }
+        (Some(lf), None) => (EntryStatus::OnlyLeft, hash_scanned(lf), None),
+        (None, Some(rf)) => (EntryStatus::OnlyRight, None, hash_scanned(rf)),
+        (None, None) => return None,
+    };
+
+    let kind = entry_kind(
+        [l, r].into_iter().flatten().map(|f| (Path::new(&f.abs_path), f.size)),
+        options.max_file_size,
+    );
+    let binary_diff = match (l, r) {
+        (Some(lf), Some(rf)) if kind == EntryKind::Binary && status == EntryStatus::Different => {
+            describe_difference(Path::new(&lf.abs_path), Path::new(&rf.abs_path))
+                .ok()
+                .flatten()
+        }
+        _ => None,
+    };
+    let (line_stats, similarity) = if kind == EntryKind::Text && status != EntryStatus::Identical {
+        text_diff(l, r).unzip()
+    } else {
+        (None, None)
+    };
+
+    Some(CompareEntry {
+        rel_path: key.to_string(),
+        status,
+        kind,
+        left_path: l.map(|f| f.abs_path.clone()).unwrap_or_default(),
+        right_path: r.map(|f| f.abs_path.clone()).unwrap_or_default(),
+        left_size: l.map(|f| f.size),
+        right_size: r.map(|f| f.size),
+        left_hash,
+        right_hash,
+        left_modified: l.and_then(ScannedFile::modified_millis),
+        right_modified: r.and_then(ScannedFile::modified_millis),
+        binary_diff,
+        renamed_from: None,
+        // Only meaningful when there are two sides to compare.
+        similarity: similarity.filter(|_| l.is_some() && r.is_some()),
+        line_stats,
+    })
+}
+
+/// Too large if either side exceeds `max_file_size`, else binary if either side looks binary.
+pub(crate) fn entry_kind<'a>(
+    mut sides: impl Iterator<Item = (&'a Path, u64)> + Clone,
+    max_file_size: u64,
+) -> EntryKind {
+    if sides.clone().any(|(_, size)| size > max_file_size) {
+        EntryKind::TooLarge
+    } else if sides.any(|(path, _)| is_binary(path).unwrap_or(false)) {
+        EntryKind::Binary
+    } else {
+        EntryKind::Text
+    }
+}
+
+struct Verdict {
+    identical: bool,
+    left_hash: Option<String>,
+    right_hash: Option<String>,
+}
+
+/// Staged comparison, cheapest check first: size, then (optionally) mtime, then a
+/// streaming hash, then (in paranoid mode) the bytes themselves. Unreadable files
+/// are reported as different. Files are only hashed when the size and mtime checks cannot
+/// decide, so neither is opened when their sizes differ.
+fn compare_files(left: &ScannedFile, right: &ScannedFile, options: &CompareOptions) -> Verdict {
+    let verdict = |identical, left_hash, right_hash| Verdict {
+        identical,
+        left_hash,
+        right_hash,
+    };
+
+    if left.size != right.size {
+        return verdict(false, None, None);
+    }
+    if options.trust_mtime && left.modified.is_some() && left.modified == right.modified {
+        return verdict(true, None, None);
+    }
+
+    let (left_hash, right_hash) = (hash_scanned(left), hash_scanned(right));
+    let identical = match (&left_hash, &right_hash) {
+        (Some(lh), Some(rh)) if lh == rh => {
+            !options.paranoid
+                || same_contents(Path::new(&left.abs_path), Path::new(&right.abs_path))
+                    .unwrap_or(false)
+        }
+        _ => false,
+    };
+    verdict(identical, left_hash, right_hash)
+}
+
+/// Line diff of a text entry; a one-sided entry only has its lines counted, as all added or
+/// all removed. `None` if a side is unreadable.
+fn text_diff(l: Option<&ScannedFile>, r: Option<&ScannedFile>) -> Option<(LineStats, u8)> {
+    let read = |f: &ScannedFile| fs::read(&f.abs_path).ok().map(|b| String::from_utf8_lossy(&b).into_owned());
+    let lines = |f: &ScannedFile| fs::File::open(&f.abs_path).and_then(count_lines).ok();
+    let one_sided = |stats: LineStats, lines: usize| (stats, if lines == 0 { 100 } else { 0 });
+    match (l, r) {
+        (Some(lf), Some(rf)) => Some(line_diff(&read(lf)?, &read(rf)?)),
+        (Some(lf), None) => lines(lf).map(|n| one_sided(LineStats { removed: n, ..Default::default() }, n)),
+        (None, Some(rf)) => lines(rf).map(|n| one_sided(LineStats { added: n, ..Default::default() }, n)),
+        (None, None) => None,
+    }
+}
+
+fn hash_scanned(file: &ScannedFile) -> Option<String> {
+    hash_file(Path::new(&file.abs_path)).ok()
+}
+
+/// Loads both sides of one entry for display, found at `from` on the left and `to` on the
+/// right (the same path unless the entry was renamed). A side where the file does not exist
+/// is empty.
+pub fn entry_contents(
+    left: &str,
+    right: &str,
+    from: &str,
+    to: &str,
+    max_file_size: u64,
+) -> Result<EntryContents, DivergeError> {
+    let left_path = Path::new(left).join(relative(from)?);
+    let right_path = Path::new(right).join(relative(to)?);
+    let sides: Vec<(&Path, u64)> = [&left_path, &right_path]
+        .into_iter()
+        .filter_map(|p| fs::metadata(p).ok().filter(|m| m.is_file()).map(|m| (p.as_path(), m.len())))
+        .collect();
+    let kind = entry_kind(sides.into_iter(), max_file_size);
+    if kind != EntryKind::Text {
+        return Ok(EntryContents {
+            left_content: String::new(),
+            right_content: String::new(),
+            kind,
+        });
     }
+
+    Ok(EntryContents {
+        left_content: read_side(&left_path)?,
+        right_content: read_side(&right_path)?,
+        kind,
+    })
+}
+
+pub(crate) fn relative(rel_path: &str) -> Result<&Path, DivergeError> {
+    let rel = Path::new(rel_path);
+    if rel_path.is_empty() || !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(DivergeError::invalid(format!("Invalid relative path: {}", rel_path)));
+    }
+    Ok(rel)
+}
+
+fn read_side(path: &Path) -> Result<String, DivergeError> {
+    if !path.is_file() {
+        return Ok(String::new());
+    }
+    let bytes = fs::read(path).map_err(|e| DivergeError::io(path, e))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::DEFAULT_MAX_FILE_SIZE;
     use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
 
     fn setup_test_dirs() -> (tempfile::TempDir, tempfile::TempDir) {
         let left = tempfile::tempdir().unwrap();
@@ -96,12 +357,12 @@ mod tests {
         fs::write(left.path().join("file.txt"), "hello").unwrap();
         fs::write(right.path().join("file.txt"), "hello").unwrap();
 
-        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &vec![]);
+        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &CompareOptions::default());
 
         assert_eq!(result.total, 1);
         assert_eq!(result.identical, 1);
         assert_eq!(result.different, 0);
-        assert_eq!(result.entries[0].status, "identical");
+        assert_eq!(result.entries[0].status, EntryStatus::Identical);
     }
 
     #[test]
@@ -110,13 +371,428 @@ mod tests {
         fs::write(left.path().join("file.txt"), "hello").unwrap();
         fs::write(right.path().join("file.txt"), "world").unwrap();
 
-        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &vec![]);
+        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &CompareOptions::default());
 
         assert_eq!(result.total, 1);
         assert_eq!(result.different, 1);
-        assert_eq!(result.entries[0].status, "different");
-        assert_eq!(result.entries[0].left_content, "hello");
-        assert_eq!(result.entries[0].right_content, "world");
+        assert_eq!(result.entries[0].status, EntryStatus::Different);
+        assert_eq!(result.entries[0].left_size, Some(5));
+        assert_eq!(result.entries[0].right_size, Some(5));
+    }
+
+    #[test]
+    fn different_sizes_are_different() {
+        let (left, right) = setup_test_dirs();
+        fs::write(left.path().join("file.txt"), "hello").unwrap();
+        fs::write(right.path().join("file.txt"), "hello!").unwrap();
+
+        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &CompareOptions::default());
+
+        assert_eq!(result.different, 1);
+    }
+
+    #[test]
+    fn large_files_compared_past_first_chunk() {
+        let (left, right) = setup_test_dirs();
+        let mut data = vec![b'a'; crate::hash::CHUNK_SIZE * 3];
+        fs::write(left.path().join("big.txt"), &data).unwrap();
+        fs::write(right.path().join("same.txt"), &data).unwrap();
+        fs::write(left.path().join("same.txt"), &data).unwrap();
+        data[crate::hash::CHUNK_SIZE * 2 + 7] = b'b';
+        fs::write(right.path().join("big.txt"), &data).unwrap();
+
+        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &CompareOptions::default());
+
+        assert_eq!(result.entries[0].rel_path, "big.txt");
+        assert_eq!(result.entries[0].status, EntryStatus::Different);
+        assert_eq!(result.entries[1].status, EntryStatus::Identical);
+    }
+
+    #[test]
+    fn entries_carry_hashes() {
+        let (left, right) = setup_test_dirs();
+        fs::write(left.path().join("same.txt"), "same").unwrap();
+        fs::write(right.path().join("same.txt"), "same").unwrap();
+        fs::write(left.path().join("diff.txt"), "aaaa").unwrap();
+        fs::write(right.path().join("diff.txt"), "bbbb").unwrap();
+        fs::write(right.path().join("new.txt"), "new").unwrap();
+
+        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &CompareOptions::default());
+
+        let same_hash = blake3::hash(b"same").to_hex().to_string();
+        let diff = &result.entries[0];
+        assert_eq!(diff.status, EntryStatus::Different);
+        assert_eq!(diff.left_hash, Some(blake3::hash(b"aaaa").to_hex().to_string()));
+        assert_eq!(diff.right_hash, Some(blake3::hash(b"bbbb").to_hex().to_string()));
+        assert_eq!(result.entries[1].left_hash, None);
+        assert_eq!(result.entries[1].right_hash, Some(blake3::hash(b"new").to_hex().to_string()));
+        assert_eq!(result.entries[2].left_hash.as_ref(), Some(&same_hash));
+        assert_eq!(result.entries[2].right_hash.as_ref(), Some(&same_hash));
+    }
+
+    #[test]
+    fn size_mismatch_skips_hashing() {
+        let (left, right) = setup_test_dirs();
+        fs::write(left.path().join("file.txt"), "short").unwrap();
+        fs::write(right.path().join("file.txt"), "much longer").unwrap();
+
+        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &CompareOptions::default());
+
+        assert_eq!(result.entries[0].status, EntryStatus::Different);
+        assert_eq!((&result.entries[0].left_hash, &result.entries[0].right_hash), (&None, &None));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn size_mismatch_never_opens_the_files() {
+        let dir = tempfile::tempdir().unwrap();
+        // Opening a FIFO for reading blocks until something writes to it.
+        let fifo = dir.path().join("fifo");
+        assert!(std::process::Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+        let file = |size| ScannedFile {
+            abs_path: fifo.to_string_lossy().into_owned(),
+            size,
+            modified: None,
+        };
+        let (left, right) = (file(1), file(2));
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || tx.send(compare_files(&left, &right, &CompareOptions::default()).identical));
+        let identical = rx.recv_timeout(std::time::Duration::from_secs(5)).expect("a file was opened");
+        assert!(!identical);
+    }
+
+    #[test]
+    fn trust_mtime_skips_content_check() {
+        let (left, right) = setup_test_dirs();
+        let (lp, rp) = (left.path().join("file.txt"), right.path().join("file.txt"));
+        fs::write(&lp, "aaaa").unwrap();
+        fs::write(&rp, "bbbb").unwrap();
+        let mtime = fs::metadata(&lp).unwrap().modified().unwrap();
+        fs::File::options().write(true).open(&rp).unwrap().set_modified(mtime).unwrap();
+
+        let (l, r) = (left.path().to_str().unwrap(), right.path().to_str().unwrap());
+        let trusting = CompareOptions {
+            trust_mtime: true,
+            ..Default::default()
+        };
+        assert_eq!(compare(l, r, &trusting).entries[0].status, EntryStatus::Identical);
+        assert_eq!(compare(l, r, &CompareOptions::default()).entries[0].status, EntryStatus::Different);
+    }
+
+    #[test]
+    fn paranoid_mode_confirms_matches() {
+        let (left, right) = setup_test_dirs();
+        fs::write(left.path().join("file.txt"), "same").unwrap();
+        fs::write(right.path().join("file.txt"), "same").unwrap();
+
+        let options = CompareOptions {
+            paranoid: true,
+            ..Default::default()
+        };
+        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &options);
+
+        assert_eq!(result.identical, 1);
+        assert!(result.entries[0].left_hash.is_some());
+    }
+
+    #[test]
+    fn one_sided_entries_have_single_size() {
+        let (left, right) = setup_test_dirs();
+        fs::write(left.path().join("only-here.txt"), "content").unwrap();
+
+        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &CompareOptions::default());
+
+        assert_eq!(result.entries[0].left_size, Some(7));
+        assert_eq!(result.entries[0].right_size, None);
+        assert!(result.entries[0].right_path.is_empty());
+    }
+
+    #[test]
+    fn entry_contents_loads_both_sides() {
+        let (left, right) = setup_test_dirs();
+        fs::create_dir_all(left.path().join("sub")).unwrap();
+        fs::write(left.path().join("sub/a.txt"), "left").unwrap();
+
+        let contents = entry_contents(
+            left.path().to_str().unwrap(),
+            right.path().to_str().unwrap(),
+            "sub/a.txt",
+            "sub/a.txt",
+            DEFAULT_MAX_FILE_SIZE,
+        )
+        .unwrap();
+
+        assert_eq!(contents.left_content, "left");
+        assert_eq!(contents.right_content, "");
+        assert_eq!(contents.kind, EntryKind::Text);
+    }
+
+    #[test]
+    fn binary_files_are_reported() {
+        let (left, right) = setup_test_dirs();
+        fs::write(left.path().join("same.bin"), [0u8, 1, 2]).unwrap();
+        fs::write(right.path().join("same.bin"), [0u8, 1, 2]).unwrap();
+        fs::write(left.path().join("diff.bin"), [0u8, 1, 2, 3]).unwrap();
+        fs::write(right.path().join("diff.bin"), [0u8, 1, 9, 3]).unwrap();
+        fs::write(left.path().join("latin1.txt"), b"caf\xe9 au lait").unwrap();
+
+        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &CompareOptions::default());
+
+        assert_eq!(result.total, 3);
+        let diff = &result.entries[0];
+        assert_eq!(diff.rel_path, "diff.bin");
+        assert_eq!(diff.kind, EntryKind::Binary);
+        assert_eq!(diff.status, EntryStatus::Different);
+        let summary = diff.binary_diff.as_ref().unwrap();
+        assert_eq!(summary.offset, 2);
+        assert_eq!(summary.left_bytes, "02 03");
+        assert_eq!(summary.right_bytes, "09 03");
+
+        assert_eq!(result.entries[1].rel_path, "latin1.txt");
+        assert_eq!(result.entries[1].kind, EntryKind::Binary);
+        assert_eq!(result.entries[1].status, EntryStatus::OnlyLeft);
+
+        assert_eq!(result.entries[2].kind, EntryKind::Binary);
+        assert_eq!(result.entries[2].status, EntryStatus::Identical);
+        assert!(result.entries[2].binary_diff.is_none());
+    }
+
+    #[test]
+    fn text_files_have_text_kind() {
+        let (left, right) = setup_test_dirs();
+        fs::write(left.path().join("a.txt"), "hello").unwrap();
+        fs::write(right.path().join("a.txt"), "world").unwrap();
+
+        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &CompareOptions::default());
+
+        assert_eq!(result.entries[0].kind, EntryKind::Text);
+        assert!(result.entries[0].binary_diff.is_none());
+    }
+
+    #[test]
+    fn large_files_are_listed_and_hashed() {
+        let (left, right) = setup_test_dirs();
+        fs::write(left.path().join("dump.sql"), "x".repeat(100)).unwrap();
+        fs::write(right.path().join("dump.sql"), "y".repeat(100)).unwrap();
+        fs::write(left.path().join("small.txt"), "ok").unwrap();
+        fs::write(right.path().join("small.txt"), "ok").unwrap();
+        let options = CompareOptions {
+            max_file_size: 10,
+            ..Default::default()
+        };
+
+        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &options);
+
+        assert_eq!(result.total, 2);
+        assert_eq!(result.too_large, 1);
+        let dump = &result.entries[0];
+        assert_eq!(dump.kind, EntryKind::TooLarge);
+        assert_eq!(dump.status, EntryStatus::Different);
+        assert_eq!(dump.left_size, Some(100));
+        assert_eq!(dump.right_size, Some(100));
+        assert!(dump.left_hash.is_some());
+        assert_eq!(result.entries[1].kind, EntryKind::Text);
+
+        let contents = entry_contents(
+            left.path().to_str().unwrap(),
+            right.path().to_str().unwrap(),
+            "dump.sql",
+            "dump.sql",
+            10,
+        )
+        .unwrap();
+        assert_eq!(contents.kind, EntryKind::TooLarge);
+        assert!(contents.left_content.is_empty());
+    }
+
+    #[test]
+    fn divergeignore_from_either_side_applies_to_both() {
+        let (left, right) = setup_test_dirs();
+        fs::write(left.path().join("a.txt"), "a").unwrap();
+        fs::write(left.path().join("gen.out"), "1").unwrap();
+        fs::write(right.path().join("gen.out"), "2").unwrap();
+        fs::write(right.path().join(".divergeignore"), "*.out\n").unwrap();
+
+        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &CompareOptions::default());
+
+        let paths: Vec<&str> = result.entries.iter().map(|e| e.rel_path.as_str()).collect();
+        assert_eq!(paths, vec![".divergeignore", "a.txt"]);
+        assert_eq!(result.ignored_dirs.len(), 2);
+    }
+
+    #[test]
+    fn respect_gitignore_is_optional() {
+        let (left, right) = setup_test_dirs();
+        for dir in [&left, &right] {
+            fs::write(dir.path().join(".gitignore"), "build/\n").unwrap();
+            fs::create_dir(dir.path().join("build")).unwrap();
+        }
+        fs::write(left.path().join("build/out.js"), "x").unwrap();
+
+        let (l, r) = (left.path().to_str().unwrap(), right.path().to_str().unwrap());
+        let result = compare(l, r, &CompareOptions::default());
+        assert_eq!(result.only_left, 1);
+
+        let options = CompareOptions {
+            respect_gitignore: true,
+            ..Default::default()
+        };
+        let result = compare(l, r, &options);
+        assert_eq!(result.only_left, 0);
+        assert_eq!(result.ignored_dirs, vec!["build (.gitignore: build/)"]);
+    }
+
+    #[test]
+    fn entry_order_does_not_depend_on_thread_count() {
+        let (left, right) = setup_test_dirs();
+        for i in 0..50 {
+            let name = format!("d{}/f{:02}.txt", i % 7, i);
+            fs::create_dir_all(left.path().join(&name).parent().unwrap()).unwrap();
+            fs::create_dir_all(right.path().join(&name).parent().unwrap()).unwrap();
+            fs::write(left.path().join(&name), format!("{}", i)).unwrap();
+            if i % 3 != 0 {
+                fs::write(right.path().join(&name), format!("{}", i * (i % 2))).unwrap();
+            }
+        }
+        let (l, r) = (left.path().to_str().unwrap(), right.path().to_str().unwrap());
+        let run = |threads| {
+            let result = compare(l, r, &CompareOptions { threads, ..Default::default() });
+            let listing: Vec<(String, EntryStatus)> =
+                result.entries.into_iter().map(|e| (e.rel_path, e.status)).collect();
+            (listing, result.identical, result.different, result.only_left)
+        };
+
+        let single = run(1);
+        assert_eq!(single.0.len(), 50);
+        assert!(single.0.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(run(8), single);
+        assert_eq!(run(0), single);
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        discovered: AtomicUsize,
+        total: AtomicUsize,
+        compared: Mutex<Vec<String>>,
+        cancel_after: Option<usize>,
+    }
+
+    impl Progress for Recorder {
+        fn discovered(&self, _path: &Path) {
+            self.discovered.fetch_add(1, Ordering::Relaxed);
+        }
+        fn scanned(&self, total: usize) {
+            self.total.store(total, Ordering::Relaxed);
+        }
+        fn compared(&self, entry: &CompareEntry) {
+            self.compared.lock().unwrap().push(entry.rel_path.clone());
+        }
+        fn cancelled(&self) -> bool {
+            self.cancel_after
+                .is_some_and(|n| self.discovered.load(Ordering::Relaxed) >= n)
+        }
+    }
+
+    #[test]
+    fn progress_sees_every_file_and_entry() {
+        let (left, right) = setup_test_dirs();
+        fs::write(left.path().join("a.txt"), "a").unwrap();
+        fs::write(left.path().join("b.txt"), "b").unwrap();
+        fs::write(right.path().join("b.txt"), "b").unwrap();
+        let recorder = Recorder::default();
+
+        let result = compare_with_progress(
+            left.path().to_str().unwrap(),
+            right.path().to_str().unwrap(),
+            &CompareOptions::default(),
+            &recorder,
+        )
+        .unwrap();
+
+        assert_eq!(result.total, 2);
+        assert_eq!(recorder.discovered.into_inner(), 3);
+        assert_eq!(recorder.total.into_inner(), 2);
+        let mut compared = recorder.compared.into_inner().unwrap();
+        compared.sort();
+        assert_eq!(compared, vec!["a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn cancelled_compare_returns_none() {
+        let (left, right) = setup_test_dirs();
+        for i in 0..20 {
+            fs::write(left.path().join(format!("{}.txt", i)), "x").unwrap();
+        }
+        let recorder = Recorder {
+            cancel_after: Some(1),
+            ..Default::default()
+        };
+
+        let result = compare_with_progress(
+            left.path().to_str().unwrap(),
+            right.path().to_str().unwrap(),
+            &CompareOptions::default(),
+            &recorder,
+        );
+
+        assert!(result.is_none());
+        assert!(recorder.discovered.into_inner() < 20);
+        assert!(recorder.compared.into_inner().unwrap().is_empty());
+    }
+
+    #[test]
+    fn entry_contents_skips_binary() {
+        let (left, right) = setup_test_dirs();
+        fs::write(left.path().join("a.bin"), [0u8, 1, 2]).unwrap();
+        fs::write(right.path().join("a.bin"), "text").unwrap();
+
+        let contents = entry_contents(
+            left.path().to_str().unwrap(),
+            right.path().to_str().unwrap(),
+            "a.bin",
+            "a.bin",
+            DEFAULT_MAX_FILE_SIZE,
+        )
+        .unwrap();
+
+        assert_eq!(contents.kind, EntryKind::Binary);
+        assert!(contents.left_content.is_empty());
+        assert!(contents.right_content.is_empty());
+    }
+
+    #[test]
+    fn entry_contents_rejects_escaping_paths() {
+        let (left, right) = setup_test_dirs();
+        let (l, r) = (left.path().to_str().unwrap(), right.path().to_str().unwrap());
+
+        assert!(entry_contents(l, r, "../etc/passwd", "a.txt", DEFAULT_MAX_FILE_SIZE).is_err());
+        assert!(entry_contents(l, r, "a.txt", "/etc/passwd", DEFAULT_MAX_FILE_SIZE).is_err());
+        assert!(entry_contents(l, r, "", "", DEFAULT_MAX_FILE_SIZE).is_err());
+    }
+
+    #[test]
+    fn line_stats_for_text_entries() {
+        let (left, right) = setup_test_dirs();
+        fs::write(left.path().join("same.txt"), "x\n").unwrap();
+        fs::write(right.path().join("same.txt"), "x\n").unwrap();
+        fs::write(left.path().join("edit.txt"), "a\nb\nc\n").unwrap();
+        fs::write(right.path().join("edit.txt"), "a\nB\nc\nd\n").unwrap();
+        fs::write(right.path().join("new.txt"), "1\n2\n").unwrap();
+        fs::write(left.path().join("data.bin"), b"\0\x01").unwrap();
+        fs::write(right.path().join("data.bin"), b"\0\x02").unwrap();
+
+        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &CompareOptions::default());
+        let entry = |name: &str| result.entries.iter().find(|e| e.rel_path == name).unwrap();
+
+        let edit = entry("edit.txt");
+        assert_eq!(edit.line_stats, Some(LineStats { added: 1, removed: 0, changed: 1 }));
+        assert_eq!(edit.similarity, Some(57));
+        assert_eq!(entry("new.txt").line_stats.map(|s| s.added), Some(2));
+        assert_eq!(entry("new.txt").similarity, None);
+        assert_eq!(entry("same.txt").line_stats, None);
+        assert_eq!(entry("data.bin").line_stats, None);
+        assert_eq!(result.line_totals, LineStats { added: 3, removed: 0, changed: 1 });
     }
 
     #[test]
@@ -124,11 +800,11 @@ mod tests {
         let (left, right) = setup_test_dirs();
         fs::write(left.path().join("only-here.txt"), "content").unwrap();
 
-        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &vec![]);
+        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &CompareOptions::default());
 
         assert_eq!(result.total, 1);
         assert_eq!(result.only_left, 1);
-        assert_eq!(result.entries[0].status, "only_left");
+        assert_eq!(result.entries[0].status, EntryStatus::OnlyLeft);
     }
 
     #[test]
@@ -136,18 +812,18 @@ mod tests {
         let (left, right) = setup_test_dirs();
         fs::write(right.path().join("only-here.txt"), "content").unwrap();
 
-        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &vec![]);
+        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &CompareOptions::default());
 
         assert_eq!(result.total, 1);
         assert_eq!(result.only_right, 1);
-        assert_eq!(result.entries[0].status, "only_right");
+        assert_eq!(result.entries[0].status, EntryStatus::OnlyRight);
     }
 
     #[test]
     fn empty_dirs() {
         let (left, right) = setup_test_dirs();
 
-        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &vec![]);
+        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &CompareOptions::default());
 
         assert_eq!(result.total, 0);
     }
@@ -160,7 +836,7 @@ mod tests {
         fs::write(left.path().join("sub/deep/file.yaml"), "key: a").unwrap();
         fs::write(right.path().join("sub/deep/file.yaml"), "key: b").unwrap();
 
-        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &vec![]);
+        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &CompareOptions::default());
 
         assert_eq!(result.total, 1);
         assert_eq!(result.different, 1);
@@ -177,7 +853,7 @@ mod tests {
         fs::write(left.path().join("left-only.txt"), "x").unwrap();
         fs::write(right.path().join("right-only.txt"), "x").unwrap();
 
-        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &vec![]);
+        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &CompareOptions::default());
 
         assert_eq!(result.total, 4);
         assert_eq!(result.identical, 1);
@@ -191,7 +867,7 @@ mod tests {
         let right = tempfile::tempdir().unwrap();
         fs::write(right.path().join("file.txt"), "content").unwrap();
 
-        let result = compare("/nonexistent/path", right.path().to_str().unwrap(), &vec![]);
+        let result = compare("/nonexistent/path", right.path().to_str().unwrap(), &CompareOptions::default());
 
         assert_eq!(result.total, 1);
         assert_eq!(result.only_right, 1);
@@ -207,8 +883,11 @@ mod tests {
         fs::write(left.path().join("app.js"), "hello").unwrap();
         fs::write(right.path().join("app.js"), "hello").unwrap();
 
-        let ignore = vec!["node_modules".to_string(), ".git".to_string()];
-        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &ignore);
+        let options = CompareOptions {
+            ignore_dirs: vec!["node_modules".to_string(), ".git".to_string()],
+            ..Default::default()
+        };
+        let result = compare(left.path().to_str().unwrap(), right.path().to_str().unwrap(), &options);
 
         assert_eq!(result.total, 1);
         assert_eq!(result.identical, 1);
@@ -0,0 +1,334 @@
+use std::path::Path;
+
+use crate::changes::{self, ApplyOptions, Change, ChangesOutcome};
+use crate::compare::{self, Progress};
+use crate::diff::{self, UnifiedDiff};
+use crate::error::DivergeError;
+use crate::merge::MergeOptions;
+use crate::models::{
+    CompareEntry, CompareOptions, CompareResult, EntryContents, Side, ThreeWayResult,
+};
+use crate::patch::{self, PatchOutcome};
+use crate::scanner;
+use crate::sync::{self, SyncDirection, SyncFilters, SyncItem, SyncOutcome, SyncPlan};
+use crate::threeway::{self, MergeOutcome};
+
+/// Builder for comparing two directory trees.
+///
+/// ```no_run
+/// use diverge_lib::{Comparer, EntryStatus};
+///
+/// let result = Comparer::new("old", "new")
+///     .ignore("target/")
+///     .ignore("*.log")
+///     .respect_gitignore(true)
+///     .run()?;
+/// for entry in result.entries.iter().filter(|e| e.status != EntryStatus::Identical) {
+///     println!("{} {}", entry.status, entry.rel_path);
+/// }
+/// # Ok::<(), diverge_lib::DivergeError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Comparer {
+    left: String,
+    right: String,
+    options: CompareOptions,
+}
+
+impl Comparer {
+    /// Compares `left` against `right` with the default options: no ignore patterns,
+    /// no `.gitignore` handling, every file hashed.
+    pub fn new(left: impl AsRef<Path>, right: impl AsRef<Path>) -> Self {
+        Self {
+            left: left.as_ref().to_string_lossy().into_owned(),
+            right: right.as_ref().to_string_lossy().into_owned(),
+            options: CompareOptions::default(),
+        }
+    }
+
+    /// Replaces all options at once, e.g. with `CompareOptions::from_config`.
+    pub fn options(mut self, options: CompareOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Adds an ignore pattern with `.gitignore` semantics (`*.log`, `build/`, `!keep.me`).
+    pub fn ignore(mut self, pattern: impl Into<String>) -> Self {
+        self.options.ignore_dirs.push(pattern.into());
+        self
+    }
+
+    /// Also skip what `.gitignore`, `.ignore` and the git excludes ignore.
+    pub fn respect_gitignore(mut self, yes: bool) -> Self {
+        self.options.respect_gitignore = yes;
+        self
+    }
+
+    /// Treat files with the same size and modification time as identical without hashing.
+    pub fn trust_mtime(mut self, yes: bool) -> Self {
+        self.options.trust_mtime = yes;
+        self
+    }
+
+    /// Confirm every hash match with a byte-by-byte comparison.
+    pub fn paranoid(mut self, yes: bool) -> Self {
+        self.options.paranoid = yes;
+        self
+    }
+
+    /// Files above `bytes` are reported as `EntryKind::TooLarge` and compared by hash only.
+    pub fn max_file_size(mut self, bytes: u64) -> Self {
+        self.options.max_file_size = bytes;
+        self
+    }
+
+    /// Pair files that moved as `renamed` entries, down to `similarity` percent (git's `-M`);
+    /// 100 pairs identical files only and `None` turns detection off.
+    pub fn find_renames(mut self, similarity: Option<u8>) -> Self {
+        self.options.renames = similarity.map(|s| s.min(100));
+        self
+    }
+
+    /// Worker threads used to scan and hash; 0 uses one per CPU core.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.options.threads = threads;
+        self
+    }
+
+    /// Runs the comparison. Fails if either side is not a directory or a pattern is invalid.
+    pub fn run(&self) -> Result<CompareResult, DivergeError> {
+        self.check()?;
+        Ok(compare::compare(&self.left, &self.right, &self.options))
+    }
+
+    /// Like `run`, reporting to `progress` as it goes. Returns `Ok(None)` if cancelled.
+    pub fn run_with_progress(
+        &self,
+        progress: &dyn Progress,
+    ) -> Result<Option<CompareResult>, DivergeError> {
+        self.check()?;
+        Ok(compare::compare_with_progress(
+            &self.left,
+            &self.right,
+            &self.options,
+            progress,
+        ))
+    }
+
+    /// Both sides of one entry of the result, read as text unless binary or too large.
+    pub fn contents(&self, entry: &CompareEntry) -> Result<EntryContents, DivergeError> {
+        let from = entry.renamed_from.as_deref().unwrap_or(&entry.rel_path);
+        let max = self.options.max_file_size;
+        compare::entry_contents(&self.left, &self.right, from, &entry.rel_path, max)
+    }
+
+    /// A patch turning the left tree into the right one for the entries of `result`, with
+    /// `context` unchanged lines around each hunk. It applies with `patch -p1` or `git apply`
+    /// from the left root.
+    pub fn diff(&self, result: &CompareResult, context: usize) -> Result<UnifiedDiff, DivergeError> {
+        diff::tree_diff(&self.left, &self.right, result, context)
+    }
+
+    /// Applies a unified diff or git patch to one side, with `strip` leading path components
+    /// removed as in `patch -p`, then compares again. Hunks that do not apply are returned as
+    /// conflicts in the outcome, along with the new result.
+    pub fn apply_patch(&self, side: Side, patch: &str, strip: usize) -> Result<PatchOutcome, DivergeError> {
+        self.check()?;
+        let root = match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+        };
+        let mut outcome = patch::apply_patch(root, patch, strip, false)?;
+        outcome.result = Some(compare::compare(&self.left, &self.right, &self.options));
+        Ok(outcome)
+    }
+
+    /// Copies and deletes files between the two sides as `items` say, then compares again.
+    /// Each item is reported on its own; one failing does not stop the rest.
+    pub fn sync(&self, items: &[SyncItem]) -> Result<SyncOutcome, DivergeError> {
+        self.check()?;
+        let mut outcome = sync::run(&self.left, &self.right, items, false);
+        outcome.result = Some(compare::compare(&self.left, &self.right, &self.options));
+        Ok(outcome)
+    }
+
+    /// Writes edited contents to either side as one batch, optionally rolled back if any
+    /// write fails, then compares just the changed entries again. Given `result`, the last
+    /// comparison, the outcome carries it updated with those entries instead of a rescan.
+    pub fn apply_changes(
+        &self,
+        changes: &[Change],
+        options: &ApplyOptions,
+        result: Option<&CompareResult>,
+    ) -> Result<ChangesOutcome, DivergeError> {
+        self.check()?;
+        let (left, right) = (&self.left, &self.right);
+        Ok(changes::apply_changes(left, right, changes, options, &self.options, result))
+    }
+
+    /// Plans a sync of `result`, a comparison this comparer ran: every copy, overwrite and
+    /// delete, with the targets that changed since flagged as conflicts.
+    pub fn plan_sync(
+        &self,
+        result: &CompareResult,
+        direction: SyncDirection,
+        filters: &SyncFilters,
+    ) -> SyncPlan {
+        sync::plan_sync(&self.left, &self.right, result, direction, filters)
+    }
+
+    /// Runs a plan from `plan_sync`, then compares again. Fails with a conflict, touching
+    /// nothing, if a target changed since the plan's comparison.
+    pub fn run_plan(&self, plan: &SyncPlan) -> Result<SyncOutcome, DivergeError> {
+        if plan.left != self.left || plan.right != self.right {
+            return Err(DivergeError::invalid(
+                "The sync plan was made for other directories",
+            ));
+        }
+        self.check()?;
+        let mut outcome = sync::execute_plan(plan)?;
+        outcome.result = Some(compare::compare(&self.left, &self.right, &self.options));
+        Ok(outcome)
+    }
+
+    /// Compares left ("ours") and right ("theirs") with their common ancestor `base`,
+    /// classifying each path by which side changed it.
+    pub fn three_way(&self, base: impl AsRef<Path>) -> Result<ThreeWayResult, DivergeError> {
+        let base = base.as_ref().to_string_lossy();
+        self.check()?;
+        DivergeError::check_dir(&base)?;
+        Ok(threeway::compare_three_way(&base, &self.left, &self.right, &self.options))
+    }
+
+    /// Merges the right side's changes since `base` into the left side, as found by
+    /// `three_way`, then compares again. Files with conflicts `options` do not resolve are
+    /// left alone and listed as conflicts.
+    pub fn auto_merge(
+        &self,
+        base: impl AsRef<Path>,
+        result: &ThreeWayResult,
+        options: &MergeOptions,
+    ) -> Result<MergeOutcome, DivergeError> {
+        let base = base.as_ref().to_string_lossy();
+        self.check()?;
+        DivergeError::check_dir(&base)?;
+        let mut outcome =
+            threeway::auto_merge(&base, &self.left, &self.right, result, options, false)?;
+        outcome.result = Some(self.three_way(&*base)?);
+        Ok(outcome)
+    }
+
+    fn check(&self) -> Result<(), DivergeError> {
+        DivergeError::check_dir(&self.left)?;
+        DivergeError::check_dir(&self.right)?;
+        scanner::check_patterns(&self.options.ignore_dirs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{EntryKind, EntryStatus};
+    use std::fs;
+
+    fn trees() -> (tempfile::TempDir, tempfile::TempDir) {
+        let (left, right) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        fs::write(left.path().join("same.txt"), "same").unwrap();
+        fs::write(right.path().join("same.txt"), "same").unwrap();
+        fs::write(left.path().join("changed.txt"), "one").unwrap();
+        fs::write(right.path().join("changed.txt"), "two").unwrap();
+        fs::write(left.path().join("debug.log"), "noise").unwrap();
+        (left, right)
+    }
+
+    #[test]
+    fn builder_applies_options() {
+        let (left, right) = trees();
+
+        let result = Comparer::new(left.path(), right.path()).run().unwrap();
+        assert_eq!((result.total, result.only_left), (3, 1));
+
+        let result = Comparer::new(left.path(), right.path())
+            .ignore("*.log")
+            .max_file_size(2)
+            .run()
+            .unwrap();
+        assert_eq!(result.total, 2);
+        assert_eq!(result.entries[0].rel_path, "changed.txt");
+        assert_eq!(result.entries[0].status, EntryStatus::Different);
+        assert_eq!(result.entries[0].kind, EntryKind::TooLarge);
+    }
+
+    #[test]
+    fn run_validates_inputs() {
+        let (left, right) = trees();
+        let missing = left.path().join("missing");
+
+        let err = Comparer::new(&missing, right.path()).run().unwrap_err();
+        assert_eq!(err.kind(), "not_found");
+        let err = Comparer::new(left.path(), right.path())
+            .ignore("src/{a,b")
+            .run()
+            .unwrap_err();
+        assert_eq!(err.kind(), "config");
+
+        let comparer = Comparer::new(left.path(), right.path());
+        let result = comparer.three_way(left.path()).unwrap();
+        let err = comparer.auto_merge(&missing, &result, &MergeOptions::default()).unwrap_err();
+        assert_eq!(err.kind(), "not_found");
+    }
+
+    #[test]
+    fn contents_reads_both_sides() {
+        let (left, right) = trees();
+        fs::rename(right.path().join("changed.txt"), right.path().join("moved.txt")).unwrap();
+        fs::write(right.path().join("moved.txt"), "one\n").unwrap();
+        fs::write(left.path().join("changed.txt"), "one\n").unwrap();
+
+        let comparer = Comparer::new(left.path(), right.path()).find_renames(Some(100));
+        let result = comparer.run().unwrap();
+        let moved = result.entries.iter().find(|e| e.rel_path == "moved.txt").unwrap();
+        assert_eq!(moved.status, EntryStatus::Renamed);
+        assert_eq!(moved.renamed_from.as_deref(), Some("changed.txt"));
+
+        let contents = comparer.contents(moved).unwrap();
+        assert_eq!(contents.left_content, "one\n");
+        assert_eq!(contents.right_content, "one\n");
+        let same = result.entries.iter().find(|e| e.rel_path == "same.txt").unwrap();
+        assert_eq!(comparer.contents(same).unwrap().right_content, "same");
+    }
+
+    #[test]
+    fn apply_patch_compares_again() {
+        let (left, right) = trees();
+        let comparer = Comparer::new(left.path(), right.path()).ignore("*.log");
+        let patch = comparer.diff(&comparer.run().unwrap(), 3).unwrap().patch;
+
+        let outcome = comparer.apply_patch(Side::Left, &patch, 1).unwrap();
+        assert_eq!(outcome.patched, vec!["changed.txt"]);
+        let result = outcome.result.unwrap();
+        assert_eq!(result.identical, result.total);
+    }
+
+    #[test]
+    fn apply_changes_updates_the_result() {
+        let (left, right) = trees();
+        let comparer = Comparer::new(left.path(), right.path()).ignore("*.log");
+        let result = comparer.run().unwrap();
+        let change = Change {
+            rel_path: "changed.txt".to_string(),
+            renamed_from: None,
+            side: Side::Right,
+            content: "one".to_string(),
+            expected: None,
+            write: None,
+        };
+
+        let options = ApplyOptions { force: true, ..Default::default() };
+        let outcome = comparer.apply_changes(&[change], &options, Some(&result)).unwrap();
+        assert_eq!(outcome.written, 1);
+        let updated = outcome.result.unwrap();
+        assert_eq!((updated.total, updated.identical, updated.different), (2, 2, 0));
+        assert_eq!(fs::read_to_string(right.path().join("changed.txt")).unwrap(), "one");
+    }
+}
@@ -1,10 +1,12 @@
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::models::AppConfig;
+use crate::error::DivergeError;
+use crate::models::{AppConfig, DEFAULT_MAX_FILE_SIZE};
 
-fn config_path() -> Result<PathBuf, String> {
-    let home = dirs::home_dir().ok_or("Could not determine home directory")?;
+pub fn config_path() -> Result<PathBuf, DivergeError> {
+    let home =
+        dirs::home_dir().ok_or_else(|| DivergeError::config("Could not determine home directory"))?;
     Ok(home.join(".diverge").join("config.yaml"))
 }
 
@@ -37,36 +39,39 @@ pub fn default_config() -> AppConfig {
         .collect(),
         editor_preferences: Default::default(),
         recent_comparisons: vec![],
+        trust_mtime: false,
+        paranoid: false,
+        max_file_size: DEFAULT_MAX_FILE_SIZE,
+        respect_gitignore: false,
+        threads: 0,
+        detect_renames: false,
+        rename_similarity: 50,
+        history: Default::default(),
     }
 }
 
-pub fn load_config() -> Result<AppConfig, String> {
-    let path = config_path()?;
-
+pub fn load_config(path: &Path) -> Result<AppConfig, DivergeError> {
     if !path.exists() {
         let config = default_config();
-        save_config(&config)?;
+        save_config(path, &config)?;
         return Ok(config);
     }
 
-    let contents =
-        fs::read_to_string(&path).map_err(|e| format!("Failed to read config: {}", e))?;
-    let config: AppConfig =
-        serde_yaml::from_str(&contents).map_err(|e| format!("Failed to parse config: {}", e))?;
+    let contents = fs::read_to_string(path).map_err(|e| DivergeError::io(path, e))?;
+    let config: AppConfig = serde_yaml::from_str(&contents).map_err(|e| {
+        DivergeError::config(format!("Failed to parse config {}: {}", path.display(), e))
+    })?;
     Ok(config)
 }
 
-pub fn save_config(config: &AppConfig) -> Result<(), String> {
-    let path = config_path()?;
-
+pub fn save_config(path: &Path, config: &AppConfig) -> Result<(), DivergeError> {
     if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)
-            .map_err(|e| format!("Failed to create config directory: {}", e))?;
+        fs::create_dir_all(parent).map_err(|e| DivergeError::io(parent, e))?;
     }
 
-    let yaml =
-        serde_yaml::to_string(config).map_err(|e| format!("Failed to serialize config: {}", e))?;
-    fs::write(&path, yaml).map_err(|e| format!("Failed to write config: {}", e))?;
+    let yaml = serde_yaml::to_string(config)
+        .map_err(|e| DivergeError::config(format!("Failed to serialize config: {}", e)))?;
+    fs::write(path, yaml).map_err(|e| DivergeError::io(path, e))?;
     Ok(())
 }
 
@@ -101,6 +106,14 @@ mod tests {
                 sidebar_width: 280,
             },
             recent_comparisons: vec![],
+            trust_mtime: false,
+            paranoid: false,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            respect_gitignore: false,
+            threads: 0,
+            detect_renames: false,
+            rename_similarity: 50,
+            history: Default::default(),
         };
         let yaml = serde_yaml::to_string(&cfg).unwrap();
         let parsed: AppConfig = serde_yaml::from_str(&yaml).unwrap();
@@ -124,6 +137,32 @@ mod tests {
         let yaml = "ignore_dirs: []\n";
         let parsed: AppConfig = serde_yaml::from_str(yaml).unwrap();
         assert!(parsed.ignore_dirs.is_empty());
+        assert!(!parsed.trust_mtime);
+        assert!(!parsed.paranoid);
+        assert_eq!(parsed.max_file_size, DEFAULT_MAX_FILE_SIZE);
+        assert!(!parsed.respect_gitignore);
+    }
+
+    #[test]
+    fn load_config_creates_default_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested/config.yaml");
+
+        let cfg = load_config(&path).unwrap();
+        assert!(path.exists());
+        assert_eq!(cfg.ignore_dirs, default_config().ignore_dirs);
+    }
+
+    #[test]
+    fn save_and_load_custom_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("custom.yaml");
+        let mut cfg = default_config();
+        cfg.ignore_dirs = vec!["only-this".to_string()];
+
+        save_config(&path, &cfg).unwrap();
+        let loaded = load_config(&path).unwrap();
+        assert_eq!(loaded.ignore_dirs, vec!["only-this"]);
     }
 
     #[test]
@@ -136,6 +175,14 @@ mod tests {
                 sidebar_width: 280,
             },
             recent_comparisons: vec![],
+            trust_mtime: false,
+            paranoid: false,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            respect_gitignore: false,
+            threads: 0,
+            detect_renames: false,
+            rename_similarity: 50,
+            history: Default::default(),
         };
         let yaml = serde_yaml::to_string(&cfg).unwrap();
         assert!(yaml.contains("test"));
@@ -0,0 +1,267 @@
+use serde::Serialize;
+use similar::{Algorithm, TextDiff};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::binary::is_binary;
+use crate::compare::relative;
+use crate::error::DivergeError;
+use crate::models::{CompareEntry, CompareResult, EntryKind, EntryStatus};
+use crate::stats::line_diff;
+
+/// Lines of unchanged context around each hunk, as in `diff -u`.
+pub const DEFAULT_CONTEXT: usize = 3;
+
+/// A git-style patch turning the left tree into the right one, applicable with
+/// `patch -p1` or `git apply` from the left root.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UnifiedDiff {
+    pub patch: String,
+    /// Binary and too large files that differ but have no textual patch.
+    pub skipped: Vec<String>,
+}
+
+/// Patch for every entry of `result` that is not identical, in path order.
+pub fn tree_diff(
+    left: &str,
+    right: &str,
+    result: &CompareResult,
+    context: usize,
+) -> Result<UnifiedDiff, DivergeError> {
+    let mut diff = UnifiedDiff::default();
+    for entry in &result.entries {
+        match entry_diff(left, right, entry, context)? {
+            Some(patch) => diff.patch.push_str(&patch),
+            None => diff.skipped.push(entry.rel_path.clone()),
+        }
+    }
+    Ok(diff)
+}
+
+/// Patch for one entry; empty if it is identical, `None` if it cannot be shown as text.
+pub fn entry_diff(
+    left: &str,
+    right: &str,
+    entry: &CompareEntry,
+    context: usize,
+) -> Result<Option<String>, DivergeError> {
+    let pure_rename = entry.status == EntryStatus::Renamed && entry.similarity == Some(100);
+    if entry.status == EntryStatus::Identical {
+        return Ok(Some(String::new()));
+    }
+    if entry.kind != EntryKind::Text && !pure_rename {
+        return Ok(None);
+    }
+    let from = entry.renamed_from.as_deref().unwrap_or(&entry.rel_path);
+    file_diff(left, right, from, &entry.rel_path, context).map(Some)
+}
+
+/// Patch from `from` under `left` to `to` under `right`. A side that does not exist is
+/// diffed as a new or deleted file; the result is empty when both sides are the same.
+pub fn file_diff(
+    left: &str,
+    right: &str,
+    from: &str,
+    to: &str,
+    context: usize,
+) -> Result<String, DivergeError> {
+    let old = existing(Path::new(left).join(relative(from)?));
+    let new = existing(Path::new(right).join(relative(to)?));
+    if old.is_none() && new.is_none() {
+        return Err(DivergeError::NotFound {
+            path: to.to_string(),
+        });
+    }
+    let (old_bytes, new_bytes) = (read(old.as_deref())?, read(new.as_deref())?);
+    // A binary file can still be renamed or change mode, as long as its contents do not change.
+    for path in [&old, &new].into_iter().flatten() {
+        if old_bytes != new_bytes && is_binary(path).map_err(|e| DivergeError::io(path, e))? {
+            return Err(DivergeError::invalid(format!(
+                "{} is binary and has no textual diff",
+                path.display()
+            )));
+        }
+    }
+    let old_text = String::from_utf8_lossy(&old_bytes);
+    let new_text = String::from_utf8_lossy(&new_bytes);
+
+    let mut header = format!("diff --git a/{} b/{}\n", from, to);
+    match (&old, &new) {
+        (None, None) => {}
+        (None, Some(new)) => header.push_str(&format!("new file mode {}\n", mode(new))),
+        (Some(old), None) => header.push_str(&format!("deleted file mode {}\n", mode(old))),
+        (Some(old), Some(new)) => {
+            let (old_mode, new_mode) = (mode(old), mode(new));
+            if old_mode != new_mode {
+                header.push_str(&format!("old mode {}\nnew mode {}\n", old_mode, new_mode));
+            }
+            if from != to {
+                let similarity = line_diff(&old_text, &new_text).1;
+                header.push_str(&format!(
+                    "similarity index {}%\nrename from {}\nrename to {}\n",
+                    similarity, from, to
+                ));
+            }
+        }
+    }
+
+    let hunks = TextDiff::configure()
+        .algorithm(Algorithm::Myers)
+        .diff_lines(&old_text, &new_text)
+        .unified_diff()
+        .context_radius(context)
+        .missing_newline_hint(true)
+        .header(
+            &old.as_ref().map_or("/dev/null".to_string(), |_| format!("a/{}", from)),
+            &new.as_ref().map_or("/dev/null".to_string(), |_| format!("b/{}", to)),
+        )
+        .to_string();
+
+    // Same path, same mode and no hunks: the two sides are identical.
+    if hunks.is_empty() && header.lines().count() == 1 {
+        return Ok(String::new());
+    }
+    header.push_str(&hunks);
+    Ok(header)
+}
+
+fn existing(path: PathBuf) -> Option<PathBuf> {
+    path.is_file().then_some(path)
+}
+
+/// Contents of a side, empty if it does not exist.
+fn read(path: Option<&Path>) -> Result<Vec<u8>, DivergeError> {
+    match path {
+        Some(path) => fs::read(path).map_err(|e| DivergeError::io(path, e)),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Git's file mode: executable or regular.
+#[cfg(unix)]
+fn mode(path: &Path) -> &'static str {
+    use std::os::unix::fs::PermissionsExt;
+    match fs::metadata(path) {
+        Ok(meta) if meta.permissions().mode() & 0o111 != 0 => "100755",
+        _ => "100644",
+    }
+}
+
+#[cfg(not(unix))]
+fn mode(_path: &Path) -> &'static str {
+    "100644"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compare::compare;
+    use crate::models::CompareOptions;
+    use std::process::Command;
+
+    /// Left and right trees with a modified, a new, a deleted, a renamed and an
+    /// unterminated file.
+    fn trees() -> (tempfile::TempDir, tempfile::TempDir) {
+        let (left, right) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let write = |dir: &tempfile::TempDir, name: &str, text: &str| {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        };
+        let long: String = (1..=20).map(|i| format!("line {}\n", i)).collect();
+        write(&left, "src/main.rs", &long);
+        write(&right, "src/main.rs", &long.replace("line 10\n", "line ten\n"));
+        write(&right, "docs/new.md", "# New\n");
+        write(&left, "old.txt", "gone\n");
+        write(&left, "a/moved.txt", "same\ncontents\n");
+        write(&right, "b/moved.txt", "same\ncontents\n");
+        write(&left, "tail.txt", "no newline");
+        write(&right, "tail.txt", "no newline, changed");
+        write(&left, "same.txt", "same\n");
+        write(&right, "same.txt", "same\n");
+        (left, right)
+    }
+
+    fn tree_patch(left: &Path, right: &Path, context: usize) -> UnifiedDiff {
+        let (left, right) = (left.to_str().unwrap(), right.to_str().unwrap());
+        let options = CompareOptions {
+            renames: Some(100),
+            ..Default::default()
+        };
+        tree_diff(left, right, &compare(left, right, &options), context).unwrap()
+    }
+
+    #[test]
+    fn unified_diff_has_git_headers() {
+        let (left, right) = trees();
+        let patch = tree_patch(left.path(), right.path(), 1).patch;
+
+        assert!(patch.contains(
+            "diff --git a/src/main.rs b/src/main.rs\n--- a/src/main.rs\n+++ b/src/main.rs\n\
+             @@ -9,3 +9,3 @@\n line 9\n-line 10\n+line ten\n line 11\n"
+        ));
+        assert!(patch.contains(
+            "diff --git a/docs/new.md b/docs/new.md\nnew file mode 100644\n--- /dev/null\n\
+             +++ b/docs/new.md\n@@ -0,0 +1 @@\n+# New\n"
+        ));
+        assert!(patch.contains(
+            "diff --git a/old.txt b/old.txt\ndeleted file mode 100644\n--- a/old.txt\n\
+             +++ /dev/null\n@@ -1 +0,0 @@\n-gone\n"
+        ));
+        assert!(patch.contains(
+            "diff --git a/a/moved.txt b/b/moved.txt\nsimilarity index 100%\n\
+             rename from a/moved.txt\nrename to b/moved.txt\n"
+        ));
+        assert!(patch.contains("-no newline\n\\ No newline at end of file\n"));
+        assert!(!patch.contains("same.txt"));
+    }
+
+    #[test]
+    fn binary_files_are_skipped() {
+        let (left, right) = trees();
+        fs::write(left.path().join("logo.png"), b"\x89PNG\0\x01").unwrap();
+        fs::write(right.path().join("logo.png"), b"\x89PNG\0\x02").unwrap();
+
+        let diff = tree_patch(left.path(), right.path(), 3);
+        assert_eq!(diff.skipped, vec!["logo.png"]);
+        assert!(!diff.patch.contains("logo.png"));
+
+        let (l, r) = (left.path().to_str().unwrap(), right.path().to_str().unwrap());
+        assert_eq!(file_diff(l, r, "logo.png", "logo.png", 3).unwrap_err().kind(), "invalid_input");
+        assert_eq!(file_diff(l, r, "same.txt", "same.txt", 3).unwrap(), "");
+        assert_eq!(file_diff(l, r, "nope", "nope", 3).unwrap_err().kind(), "not_found");
+    }
+
+    /// Applies the patch to a copy of the left tree with `tool` and checks it now matches the
+    /// right tree. Fails when the tool is not installed.
+    fn applies_with(tool: &[&str]) {
+        let (left, right) = trees();
+        let patch = tree_patch(left.path(), right.path(), DEFAULT_CONTEXT).patch;
+        let scratch = tempfile::tempdir().unwrap();
+        let patch_file = scratch.path().join("changes.patch");
+        fs::write(&patch_file, &patch).unwrap();
+
+        let status = Command::new(tool[0])
+            .args(&tool[1..])
+            .arg(&patch_file)
+            .current_dir(left.path())
+            .output()
+            .unwrap_or_else(|e| panic!("{} must be installed to run this test: {}", tool[0], e))
+            .status;
+        assert!(status.success(), "{} failed on:\n{}", tool[0], patch);
+
+        let (l, r) = (left.path().to_str().unwrap(), right.path().to_str().unwrap());
+        let after = compare(l, r, &CompareOptions::default());
+        assert_eq!(after.identical, after.total, "{:?}", after.entries);
+    }
+
+    #[test]
+    fn patch_applies_with_gnu_patch() {
+        applies_with(&["patch", "-p1", "--quiet", "-i"]);
+    }
+
+    #[test]
+    fn patch_applies_with_git_apply() {
+        applies_with(&["git", "apply"]);
+    }
+}
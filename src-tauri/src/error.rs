@@ -0,0 +1,175 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+/// Errors returned by commands and the library. Serialized for the frontend as
+/// `{ "kind": "not_found", "path": "/a/b" | null, "message": "Not found: /a/b" }`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DivergeError {
+    NotFound { path: String },
+    PermissionDenied { path: String },
+    NotADirectory { path: String },
+    Io { path: Option<String>, message: String },
+    Config { message: String },
+    /// The file changed on disk since it was last read or compared.
+    Conflict { path: String, message: String },
+    /// A malformed argument, such as a relative path escaping the compared directory.
+    InvalidInput { message: String },
+}
+
+impl DivergeError {
+    /// Classifies an I/O error on `path` by its kind.
+    pub fn io(path: &Path, err: io::Error) -> Self {
+        let path = path.display().to_string();
+        match err.kind() {
+            io::ErrorKind::NotFound => Self::NotFound { path },
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied { path },
+            io::ErrorKind::NotADirectory => Self::NotADirectory { path },
+            _ => Self::Io {
+                path: Some(path),
+                message: err.to_string(),
+            },
+        }
+    }
+
+    pub fn config(message: impl Into<String>) -> Self {
+        Self::Config {
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid(message: impl Into<String>) -> Self {
+        Self::InvalidInput {
+            message: message.into(),
+        }
+    }
+
+    /// The application state mutex was poisoned by a panicking command.
+    pub fn state() -> Self {
+        Self::Io {
+            path: None,
+            message: "Failed to read application state".to_string(),
+        }
+    }
+
+    /// Errors for a path that must be an existing directory.
+    pub fn check_dir(path: &str) -> Result<(), Self> {
+        match std::fs::metadata(path) {
+            Ok(meta) if meta.is_dir() => Ok(()),
+            Ok(_) => Err(Self::NotADirectory {
+                path: path.to_string(),
+            }),
+            Err(e) => Err(Self::io(Path::new(path), e)),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::NotFound { .. } => "not_found",
+            Self::PermissionDenied { .. } => "permission_denied",
+            Self::NotADirectory { .. } => "not_a_directory",
+            Self::Io { .. } => "io",
+            Self::Config { .. } => "config",
+            Self::Conflict { .. } => "conflict",
+            Self::InvalidInput { .. } => "invalid_input",
+        }
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Self::NotFound { path }
+            | Self::PermissionDenied { path }
+            | Self::NotADirectory { path }
+            | Self::Conflict { path, .. } => Some(path),
+            Self::Io { path, .. } => path.as_deref(),
+            Self::Config { .. } | Self::InvalidInput { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for DivergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound { path } => write!(f, "Not found: {}", path),
+            Self::PermissionDenied { path } => write!(f, "Permission denied: {}", path),
+            Self::NotADirectory { path } => write!(f, "Not a directory: {}", path),
+            Self::Io {
+                path: Some(path),
+                message,
+            } => write!(f, "{}: {}", path, message),
+            Self::Io { path: None, message }
+            | Self::Config { message }
+            | Self::InvalidInput { message } => f.write_str(message),
+            Self::Conflict { path, message } => write!(f, "Conflict on {}: {}", path, message),
+        }
+    }
+}
+
+impl std::error::Error for DivergeError {}
+
+impl Serialize for DivergeError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("DivergeError", 3)?;
+        s.serialize_field("kind", self.kind())?;
+        s.serialize_field("path", &self.path())?;
+        s.serialize_field("message", &self.to_string())?;
+        s.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_errors_are_classified() {
+        let path = Path::new("/x/y");
+        let not_found = io::Error::new(io::ErrorKind::NotFound, "gone");
+        assert_eq!(
+            DivergeError::io(path, not_found),
+            DivergeError::NotFound {
+                path: "/x/y".to_string()
+            }
+        );
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied, "no");
+        assert_eq!(DivergeError::io(path, denied).kind(), "permission_denied");
+        let other = io::Error::other("disk on fire");
+        assert_eq!(DivergeError::io(path, other).to_string(), "/x/y: disk on fire");
+    }
+
+    #[test]
+    fn check_dir_distinguishes_missing_and_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("f.txt");
+        std::fs::write(&file, "x").unwrap();
+
+        assert!(DivergeError::check_dir(dir.path().to_str().unwrap()).is_ok());
+        assert_eq!(
+            DivergeError::check_dir(file.to_str().unwrap()).unwrap_err().kind(),
+            "not_a_directory"
+        );
+        let missing = dir.path().join("missing");
+        assert_eq!(
+            DivergeError::check_dir(missing.to_str().unwrap()).unwrap_err().kind(),
+            "not_found"
+        );
+    }
+
+    #[test]
+    fn serializes_as_structured_object() {
+        let err = DivergeError::PermissionDenied {
+            path: "/etc/shadow".to_string(),
+        };
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["kind"], "permission_denied");
+        assert_eq!(json["path"], "/etc/shadow");
+        assert_eq!(json["message"], "Permission denied: /etc/shadow");
+
+        let json = serde_json::to_value(DivergeError::config("bad yaml")).unwrap();
+        assert_eq!(json["kind"], "config");
+        assert!(json["path"].is_null());
+    }
+}
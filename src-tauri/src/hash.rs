@@ -0,0 +1,120 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Streams a file through BLAKE3 in fixed-size chunks and returns the hex digest.
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Byte-by-byte comparison, used in paranoid mode to confirm a hash match.
+pub fn same_contents(left: &Path, right: &Path) -> io::Result<bool> {
+    Ok(first_difference(left, right)?.is_none())
+}
+
+/// Offset of the first byte at which the two files differ, or `None` if they are identical.
+/// When one file is a prefix of the other, the offset is the length of the shorter one.
+pub fn first_difference(left: &Path, right: &Path) -> io::Result<Option<u64>> {
+    let mut lf = File::open(left)?;
+    let mut rf = File::open(right)?;
+    let mut lbuf = vec![0u8; CHUNK_SIZE];
+    let mut rbuf = vec![0u8; CHUNK_SIZE];
+    let mut offset = 0u64;
+
+    loop {
+        let ln = read_chunk(&mut lf, &mut lbuf)?;
+        let rn = read_chunk(&mut rf, &mut rbuf)?;
+        let common = ln.min(rn);
+        if let Some(i) = (0..common).find(|&i| lbuf[i] != rbuf[i]) {
+            return Ok(Some(offset + i as u64));
+        }
+        if ln != rn {
+            return Ok(Some(offset + common as u64));
+        }
+        if ln == 0 {
+            return Ok(None);
+        }
+        offset += ln as u64;
+    }
+}
+
+/// Fills `buf` as far as possible, so two readers stay aligned even on short reads.
+fn read_chunk(file: &mut File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn hash_matches_one_shot_blake3() {
+        let dir = tempfile::tempdir().unwrap();
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 17).map(|i| (i % 251) as u8).collect();
+        fs::write(dir.path().join("f"), &data).unwrap();
+
+        let hash = hash_file(&dir.path().join("f")).unwrap();
+        assert_eq!(hash, blake3::hash(&data).to_hex().to_string());
+    }
+
+    #[test]
+    fn hash_of_empty_file() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("empty"), "").unwrap();
+
+        let hash = hash_file(&dir.path().join("empty")).unwrap();
+        assert_eq!(hash, blake3::hash(b"").to_hex().to_string());
+    }
+
+    #[test]
+    fn hash_missing_file_is_error() {
+        assert!(hash_file(Path::new("/nonexistent/file")).is_err());
+    }
+
+    #[test]
+    fn same_contents_detects_late_difference() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut data = vec![b'a'; CHUNK_SIZE * 3];
+        fs::write(dir.path().join("a"), &data).unwrap();
+        fs::write(dir.path().join("b"), &data).unwrap();
+        assert!(same_contents(&dir.path().join("a"), &dir.path().join("b")).unwrap());
+
+        data[CHUNK_SIZE * 2 + 7] = b'b';
+        fs::write(dir.path().join("b"), &data).unwrap();
+        assert!(!same_contents(&dir.path().join("a"), &dir.path().join("b")).unwrap());
+        assert_eq!(
+            first_difference(&dir.path().join("a"), &dir.path().join("b")).unwrap(),
+            Some((CHUNK_SIZE * 2 + 7) as u64)
+        );
+    }
+
+    #[test]
+    fn first_difference_of_prefix_is_shorter_length() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), "abc").unwrap();
+        fs::write(dir.path().join("b"), "abcdef").unwrap();
+
+        let offset = first_difference(&dir.path().join("a"), &dir.path().join("b")).unwrap();
+        assert_eq!(offset, Some(3));
+    }
+}
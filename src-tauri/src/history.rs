@@ -0,0 +1,379 @@
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::DivergeError;
+#[cfg(feature = "gui")]
+use crate::models::CompareResult;
+use crate::models::HistoryConfig;
+use crate::scanner::epoch_millis;
+use crate::write;
+
+const MANIFEST: &str = "operation.json";
+
+/// One step that can be undone: the files a command wrote or deleted, with where their
+/// previous versions were saved.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Operation {
+    pub session: String,
+    /// Position in the session, from 1.
+    pub id: u32,
+    /// What the operation did, such as `sync /a to /b`.
+    pub label: String,
+    /// When it started, in milliseconds since the Unix epoch.
+    pub time: u64,
+    pub files: Vec<JournaledFile>,
+    pub undone: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournaledFile {
+    /// Absolute path of the file written or deleted.
+    pub path: String,
+    /// File name of its previous version in the operation's directory; `None` if the
+    /// operation created it.
+    pub backup: Option<String>,
+}
+
+/// The operations of one app launch or command, in order.
+#[derive(Debug, Clone, Serialize)]
+pub struct Session {
+    /// `YYYYMMDD-HHMMSS-pid`, with the UTC time the session started.
+    pub id: String,
+    pub operations: Vec<Operation>,
+}
+
+/// Operations undone, newest first, with the comparison redone after them.
+#[cfg(feature = "gui")]
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreOutcome {
+    pub restored: Vec<Operation>,
+    pub result: Option<CompareResult>,
+}
+
+struct Journal {
+    dir: PathBuf,
+    id: String,
+    next: u32,
+}
+
+struct Pending {
+    dir: PathBuf,
+    op: Operation,
+}
+
+/// Where this process journals its writes, once `start_session` ran.
+static SESSION: Mutex<Option<Journal>> = Mutex::new(None);
+
+thread_local! {
+    /// The operation the writes of this thread belong to.
+    static CURRENT: RefCell<Option<Pending>> = const { RefCell::new(None) };
+    /// Set while undoing, whose writes are not journaled.
+    static PAUSED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// The history kept next to the config file: `~/.diverge/history` by default.
+pub fn history_dir(config_path: &Path) -> PathBuf {
+    config_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("history")
+}
+
+/// Journals the writes of this process from now on in a new session under `root`, after
+/// removing the sessions `config` no longer keeps. Does nothing if history is disabled.
+pub(crate) fn start_session(root: &Path, config: &HistoryConfig) -> Result<(), DivergeError> {
+    if !config.enabled {
+        return Ok(());
+    }
+    prune(root, config)?;
+    let id = format!("{}-{}", stamp(SystemTime::now()), std::process::id());
+    let journal = Journal { dir: root.join(&id), id, next: 1 };
+    *SESSION.lock().map_err(|_| DivergeError::state())? = Some(journal);
+    Ok(())
+}
+
+/// Runs `f` as one operation named `label`, so that undoing it restores every file `f`
+/// wrote or deleted. An operation started inside another one joins it.
+pub(crate) fn operation<T>(label: impl Into<String>, f: impl FnOnce() -> T) -> T {
+    if CURRENT.with(|c| c.borrow().is_some()) {
+        return f();
+    }
+    let pending = SESSION
+        .lock()
+        .ok()
+        .and_then(|mut s| s.as_mut().map(|journal| journal.open(label.into())));
+    if pending.is_none() {
+        return f();
+    }
+
+    /// Ends the operation even if `f` panics, so the thread's next writes are not added to it.
+    struct Close;
+    impl Drop for Close {
+        fn drop(&mut self) {
+            CURRENT.with(|c| c.borrow_mut().take());
+        }
+    }
+    CURRENT.with(|c| *c.borrow_mut() = pending);
+    let _close = Close;
+    f()
+}
+
+/// Saves the current version of `path` before it is overwritten or deleted, in the
+/// operation running on this thread or else in one of its own, named `action` and the
+/// path. Only the first write of a file in an operation saves it, so undoing restores the
+/// version from before the operation.
+pub(crate) fn backup(path: &Path, action: &str) -> Result<(), DivergeError> {
+    if PAUSED.with(Cell::get) {
+        return Ok(());
+    }
+    if CURRENT.with(|c| c.borrow().is_none()) {
+        let active = SESSION.lock().map(|s| s.is_some()).unwrap_or(false);
+        if !active {
+            return Ok(());
+        }
+        return operation(format!("{} {}", action, path.display()), || backup(path, action));
+    }
+    CURRENT.with(|c| match c.borrow_mut().as_mut() {
+        Some(pending) => pending.save(path),
+        None => Ok(()),
+    })
+}
+
+impl Journal {
+    fn open(&mut self, label: String) -> Pending {
+        let id = self.next;
+        self.next += 1;
+        Pending {
+            dir: self.dir.join(format!("{:04}", id)),
+            op: Operation {
+                session: self.id.clone(),
+                id,
+                label,
+                time: epoch_millis(SystemTime::now()).unwrap_or(0),
+                files: vec![],
+                undone: false,
+            },
+        }
+    }
+}
+
+impl Pending {
+    fn save(&mut self, path: &Path) -> Result<(), DivergeError> {
+        let path = std::path::absolute(path).map_err(|e| DivergeError::io(path, e))?;
+        let key = path.to_string_lossy().into_owned();
+        if self.op.files.iter().any(|f| f.path == key) {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir).map_err(|e| DivergeError::io(&self.dir, e))?;
+        let backup = match fs::metadata(&path) {
+            Ok(meta) if meta.is_file() => {
+                let name = self.op.files.len().to_string();
+                fs::copy(&path, self.dir.join(&name)).map_err(|e| DivergeError::io(&path, e))?;
+                Some(name)
+            }
+            Ok(_) => return Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(DivergeError::io(&path, e)),
+        };
+        self.op.files.push(JournaledFile { path: key, backup });
+        // Saved after every file, so a crash midway still leaves the operation undoable.
+        save_manifest(&self.dir, &self.op)
+    }
+}
+
+/// The sessions under `root` that wrote anything, oldest first.
+pub fn list(root: &Path) -> Result<Vec<Session>, DivergeError> {
+    let mut sessions = Vec::new();
+    for dir in subdirs(root)? {
+        let operations: Vec<Operation> = subdirs(&dir)?
+            .iter()
+            .filter_map(|op| fs::read(op.join(MANIFEST)).ok())
+            .filter_map(|json| serde_json::from_slice(&json).ok())
+            .collect();
+        if let (Some(id), false) = (dir.file_name(), operations.is_empty()) {
+            let id = id.to_string_lossy().into_owned();
+            sessions.push(Session { id, operations });
+        }
+    }
+    Ok(sessions)
+}
+
+/// Undoes the latest operation under `root` not undone yet, from any session, restoring
+/// the files it wrote or deleted. Returns `None` if there is nothing left to undo.
+pub fn undo_last(root: &Path) -> Result<Option<Operation>, DivergeError> {
+    let latest = list(root)?
+        .into_iter()
+        .flat_map(|s| s.operations)
+        .filter(|op| !op.undone)
+        .max_by_key(|op| (op.time, op.id));
+    latest.map(|op| undo(root, op)).transpose()
+}
+
+/// Undoes every operation of `session` not undone yet, newest first, bringing the files it
+/// touched back to how they were before it started.
+pub fn restore_session(root: &Path, session: &str) -> Result<Vec<Operation>, DivergeError> {
+    let found = list(root)?.into_iter().find(|s| s.id == session);
+    let found =
+        found.ok_or_else(|| DivergeError::invalid(format!("No history session {}", session)))?;
+    found
+        .operations
+        .into_iter()
+        .rev()
+        .filter(|op| !op.undone)
+        .map(|op| undo(root, op))
+        .collect()
+}
+
+fn undo(root: &Path, mut op: Operation) -> Result<Operation, DivergeError> {
+    let dir = root.join(&op.session).join(format!("{:04}", op.id));
+    PAUSED.with(|p| p.set(true));
+    let restored = op.files.iter().try_for_each(|file| {
+        let path = Path::new(&file.path);
+        match &file.backup {
+            Some(name) => write::copy_file(&dir.join(name), path),
+            None if fs::symlink_metadata(path).is_ok() => write::remove_file(path),
+            None => Ok(()),
+        }
+    });
+    PAUSED.with(|p| p.set(false));
+    restored?;
+    op.undone = true;
+    save_manifest(&dir, &op)?;
+    Ok(op)
+}
+
+fn save_manifest(dir: &Path, op: &Operation) -> Result<(), DivergeError> {
+    let path = dir.join(MANIFEST);
+    let json = serde_json::to_vec_pretty(op).map_err(|e| DivergeError::Io {
+        path: Some(path.display().to_string()),
+        message: format!("Failed to serialize the history: {}", e),
+    })?;
+    fs::write(&path, json).map_err(|e| DivergeError::io(&path, e))
+}
+
+/// Removes the oldest sessions beyond `max_sessions` (leaving room for a new one) and
+/// those not written to in `max_age_days`; 0 lifts either limit.
+fn prune(root: &Path, config: &HistoryConfig) -> Result<(), DivergeError> {
+    let sessions = subdirs(root)?;
+    let excess = match config.max_sessions {
+        0 => 0,
+        max => (sessions.len() + 1).saturating_sub(max),
+    };
+    let cutoff = match config.max_age_days {
+        0 => None,
+        days => SystemTime::now().checked_sub(Duration::from_secs(days * 24 * 60 * 60)),
+    };
+    for (i, dir) in sessions.iter().enumerate() {
+        let modified = fs::metadata(dir).and_then(|m| m.modified()).ok();
+        let expired = matches!((modified, cutoff), (Some(m), Some(c)) if m < c);
+        if i < excess || expired {
+            fs::remove_dir_all(dir).map_err(|e| DivergeError::io(dir, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// The directories in `dir` by name, none if it does not exist.
+fn subdirs(dir: &Path) -> Result<Vec<PathBuf>, DivergeError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(DivergeError::io(dir, e)),
+    };
+    let mut dirs: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|e| e.path())
+        .collect();
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// `time` in UTC as `YYYYMMDD-HHMMSS`.
+pub(crate) fn stamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rest) = ((secs / 86_400) as i64, secs % 86_400);
+    // Days since 1970-01-01 to a civil date, after Howard Hinnant's `civil_from_days`.
+    let z = days + 719_468;
+    let (era, doe) = (z.div_euclid(146_097), z.rem_euclid(146_097));
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        rest / 3_600,
+        rest % 3_600 / 60,
+        rest % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stamps_are_utc_dates() {
+        assert_eq!(stamp(UNIX_EPOCH), "19700101-000000");
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_217_045);
+        assert_eq!(stamp(time), "20240229-143045");
+    }
+
+    #[test]
+    fn undo_restores_files_and_sessions() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+        fs::write(&a, "one").unwrap();
+        let mut journal = Journal { dir: root.path().join("s1"), id: "s1".to_string(), next: 1 };
+        let record = |pending: &mut Pending, path: &Path| pending.save(path).unwrap();
+
+        let mut first = journal.open("first".to_string());
+        record(&mut first, &a);
+        fs::write(&a, "two").unwrap();
+        record(&mut first, &a);
+        fs::write(&a, "three").unwrap();
+        record(&mut first, &b);
+        fs::write(&b, "new").unwrap();
+        let mut second = journal.open("second".to_string());
+        record(&mut second, &a);
+        fs::remove_file(&a).unwrap();
+
+        let sessions = list(root.path()).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].operations.len(), 2);
+        assert_eq!(sessions[0].operations[0].files.len(), 2);
+
+        let undone = undo_last(root.path()).unwrap().unwrap();
+        assert_eq!(undone.label, "second");
+        assert_eq!(fs::read_to_string(&a).unwrap(), "three");
+
+        let restored = restore_session(root.path(), "s1").unwrap();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(fs::read_to_string(&a).unwrap(), "one");
+        assert!(!b.exists());
+        assert_eq!(undo_last(root.path()).unwrap(), None);
+        assert_eq!(restore_session(root.path(), "s2").unwrap_err().kind(), "invalid_input");
+    }
+
+    #[test]
+    fn prune_keeps_the_newest_sessions() {
+        let root = tempfile::tempdir().unwrap();
+        for id in ["1", "2", "3"] {
+            fs::create_dir(root.path().join(id)).unwrap();
+        }
+        let config = HistoryConfig { enabled: true, max_sessions: 2, max_age_days: 30 };
+        prune(root.path(), &config).unwrap();
+        assert_eq!(subdirs(root.path()).unwrap(), vec![root.path().join("3")]);
+    }
+}
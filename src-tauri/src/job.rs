@@ -0,0 +1,187 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+use crate::compare::Progress;
+use crate::models::{CompareEntry, CompareResult};
+
+pub const PROGRESS_EVENT: &str = "compare-progress";
+pub const ENTRIES_EVENT: &str = "compare-entries";
+pub const FINISHED_EVENT: &str = "compare-finished";
+
+/// Progress and partial entries are sent at most this often.
+const EMIT_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompareProgress {
+    pub job_id: u64,
+    /// Files found so far on both sides.
+    pub discovered: usize,
+    /// Entries compared so far, out of `total` once scanning is done.
+    pub compared: usize,
+    pub total: Option<usize>,
+    pub current_path: Option<String>,
+    pub eta_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompareEntries {
+    pub job_id: u64,
+    pub entries: Vec<CompareEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum CompareOutcome {
+    Done { result: CompareResult },
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompareFinished {
+    pub job_id: u64,
+    #[serde(flatten)]
+    pub outcome: CompareOutcome,
+}
+
+struct Pending {
+    entries: Vec<CompareEntry>,
+    current_path: Option<String>,
+    last_emit: Instant,
+}
+
+/// One running comparison: turns `Progress` callbacks into throttled Tauri events.
+pub struct CompareJob {
+    app: AppHandle,
+    job_id: u64,
+    cancel: Arc<AtomicBool>,
+    started: Instant,
+    discovered: AtomicUsize,
+    compared: AtomicUsize,
+    total: Mutex<Option<usize>>,
+    pending: Mutex<Pending>,
+}
+
+impl CompareJob {
+    pub fn new(app: AppHandle, job_id: u64, cancel: Arc<AtomicBool>) -> Self {
+        let now = Instant::now();
+        Self {
+            app,
+            job_id,
+            cancel,
+            started: now,
+            discovered: AtomicUsize::new(0),
+            compared: AtomicUsize::new(0),
+            total: Mutex::new(None),
+            pending: Mutex::new(Pending {
+                entries: Vec::new(),
+                current_path: None,
+                last_emit: now,
+            }),
+        }
+    }
+
+    /// Sends whatever is still pending, then the outcome.
+    pub fn finish(&self, result: Option<CompareResult>) {
+        if let Ok(mut pending) = self.pending.lock() {
+            self.flush(&mut pending);
+        }
+        let outcome = match result {
+            Some(result) => CompareOutcome::Done { result },
+            None => CompareOutcome::Cancelled,
+        };
+        let _ = self.app.emit(
+            FINISHED_EVENT,
+            CompareFinished {
+                job_id: self.job_id,
+                outcome,
+            },
+        );
+    }
+
+    fn record(&self, path: &Path, entry: Option<&CompareEntry>) {
+        let Ok(mut pending) = self.pending.lock() else {
+            return;
+        };
+        pending.current_path = Some(path.to_string_lossy().into_owned());
+        pending.entries.extend(entry.cloned());
+        if pending.last_emit.elapsed() >= EMIT_INTERVAL {
+            self.flush(&mut pending);
+        }
+    }
+
+    fn flush(&self, pending: &mut Pending) {
+        if !pending.entries.is_empty() {
+            let _ = self.app.emit(
+                ENTRIES_EVENT,
+                CompareEntries {
+                    job_id: self.job_id,
+                    entries: std::mem::take(&mut pending.entries),
+                },
+            );
+        }
+        let total = self.total.lock().ok().and_then(|t| *t);
+        let compared = self.compared.load(Ordering::Relaxed);
+        let _ = self.app.emit(
+            PROGRESS_EVENT,
+            CompareProgress {
+                job_id: self.job_id,
+                discovered: self.discovered.load(Ordering::Relaxed),
+                compared,
+                total,
+                current_path: pending.current_path.clone(),
+                eta_ms: total
+                    .and_then(|total| eta(self.started.elapsed(), compared, total))
+                    .map(|d| d.as_millis() as u64),
+            },
+        );
+        pending.last_emit = Instant::now();
+    }
+}
+
+impl Progress for CompareJob {
+    fn discovered(&self, path: &Path) {
+        self.discovered.fetch_add(1, Ordering::Relaxed);
+        self.record(path, None);
+    }
+
+    fn scanned(&self, total: usize) {
+        if let Ok(mut t) = self.total.lock() {
+            *t = Some(total);
+        }
+    }
+
+    fn compared(&self, entry: &CompareEntry) {
+        self.compared.fetch_add(1, Ordering::Relaxed);
+        self.record(Path::new(&entry.rel_path), Some(entry));
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+}
+
+/// Remaining time, assuming the rest goes at the average pace so far.
+fn eta(elapsed: Duration, done: usize, total: usize) -> Option<Duration> {
+    if done == 0 {
+        return None;
+    }
+    let remaining = total.saturating_sub(done);
+    Some(elapsed.mul_f64(remaining as f64 / done as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eta_extrapolates_average_pace() {
+        assert_eq!(eta(Duration::from_secs(10), 0, 100), None);
+        assert_eq!(eta(Duration::from_secs(10), 25, 100), Some(Duration::from_secs(30)));
+        assert_eq!(eta(Duration::from_secs(10), 100, 100), Some(Duration::ZERO));
+    }
+}
@@ -1,23 +1,93 @@
+//! Directory comparison engine behind the Diverge desktop app.
+//!
+//! [`Comparer`] scans two trees and returns a typed [`CompareResult`]. The library
+//! does not need Tauri: build with `default-features = false` to leave out the GUI.
+//!
+//! ```no_run
+//! let result = diverge_lib::Comparer::new("left", "right").ignore("node_modules/").run()?;
+//! println!("{} of {} files differ", result.different, result.total);
+//! # Ok::<(), diverge_lib::DivergeError>(())
+//! ```
+
+mod binary;
+mod changes;
+pub mod cli;
+#[cfg(feature = "gui")]
 mod commands;
 mod compare;
+mod comparer;
 mod config;
+mod diff;
+mod error;
+mod hash;
+mod history;
+#[cfg(feature = "gui")]
+mod job;
+mod merge;
 mod models;
+mod patch;
+mod rename;
+mod report;
 mod scanner;
+mod stats;
+mod sync;
+mod threeway;
+mod write;
+
+pub use changes::{ApplyOptions, Change, ChangeResult, ChangesOutcome};
+pub use compare::Progress;
+pub use comparer::Comparer;
+pub use diff::{UnifiedDiff, DEFAULT_CONTEXT};
+pub use error::DivergeError;
+pub use history::{
+    history_dir, list as list_sessions, restore_session, undo_last as undo_last_operation,
+    Operation, Session,
+};
+pub use merge::{merge_text, ConflictHunk, Favor, MergeOptions, TextMerge};
+pub use models::{
+    BinaryDiff, CompareEntry, CompareOptions, CompareResult, EntryContents, EntryKind, EntryStatus,
+    HistoryConfig, Side, ThreeWayEntry, ThreeWayResult, ThreeWayStatus,
+};
+pub use patch::{PatchConflict, PatchOutcome};
+pub use stats::LineStats;
+pub use sync::{
+    OperationKind, PlannedOperation, SyncAction, SyncDirection, SyncFilters, SyncItem,
+    SyncItemResult, SyncOutcome, SyncPlan,
+};
+pub use write::{write_file_checked, write_file_with, FileState, WriteOptions};
+pub use threeway::MergeOutcome;
 
+#[cfg(feature = "gui")]
 use models::{AppState, CliArgs};
+#[cfg(feature = "gui")]
+use std::path::PathBuf;
+#[cfg(feature = "gui")]
 use std::sync::Mutex;
 
+#[cfg(feature = "gui")]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    run_with_args(String::new(), String::new(), String::new());
+    run_with_args(String::new(), String::new(), String::new(), None);
 }
 
-pub fn run_with_args(left_dir: String, right_dir: String, cwd: String) {
-    let cfg = config::load_config().unwrap_or_else(|_| config::default_config());
+#[cfg(feature = "gui")]
+pub fn run_with_args(left_dir: String, right_dir: String, cwd: String, config_path: Option<PathBuf>) {
+    let config_path = config_path
+        .or_else(|| config::config_path().ok())
+        .unwrap_or_else(|| PathBuf::from("config.yaml"));
+    let cfg = config::load_config(&config_path).unwrap_or_else(|_| config::default_config());
+    if let Err(e) = history::start_session(&history::history_dir(&config_path), &cfg.history) {
+        eprintln!("diverge: undo history is off: {}", e);
+    }
 
     let state = AppState {
         cli_args: CliArgs { left_dir, right_dir, cwd },
         config: cfg,
+        config_path,
+        compared_dirs: None,
+        last_result: None,
+        jobs: Default::default(),
+        next_job_id: 1,
     };
 
     tauri::Builder::default()
@@ -27,7 +97,22 @@ pub fn run_with_args(left_dir: String, right_dir: String, cwd: String) {
         .plugin(tauri_plugin_fs::init())
         .invoke_handler(tauri::generate_handler![
             commands::compare_directories,
+            commands::start_compare,
+            commands::cancel_compare,
+            commands::get_entry_contents,
+            commands::unified_diff,
+            commands::apply_patch,
+            commands::compare_three_way,
+            commands::auto_merge,
+            commands::merge_file,
+            commands::sync_entries,
+            commands::plan_sync,
+            commands::execute_sync_plan,
             commands::write_file,
+            commands::apply_changes,
+            commands::list_history,
+            commands::undo_last_operation,
+            commands::restore_session,
             commands::read_file,
             commands::get_cli_args,
             commands::get_config,
@@ -1,73 +1,41 @@
-#![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+#![cfg_attr(all(feature = "gui", not(debug_assertions)), windows_subsystem = "windows")]
 
-use std::path::PathBuf;
+use diverge_lib::cli::{self, Cli, Command, CompareArgs, GuiArgs};
 
-fn resolve_path(p: &str) -> String {
-    let expanded = if p.starts_with("~/") {
-        dirs::home_dir()
-            .map(|h| h.join(&p[2..]))
-            .unwrap_or_else(|| PathBuf::from(p))
-    } else {
-        PathBuf::from(p)
-    };
+fn main() {
+    let cli = Cli::parse_args();
 
-    let absolute = if expanded.is_absolute() {
-        expanded
-    } else {
-        std::env::current_dir()
-            .map(|cwd| cwd.join(&expanded))
-            .unwrap_or(expanded)
+    let args = match cli.command {
+        Some(Command::Gui(args)) => args,
+        Some(command) => {
+            cli::start_history(&command, cli.config.as_deref());
+            std::process::exit(cli::execute(command, cli.config.as_deref()));
+        }
+        None if cli.headless => {
+            let GuiArgs { left, right, .. } = cli.gui;
+            let command = Command::Compare(CompareArgs {
+                left: left.unwrap_or_default(),
+                right: right.unwrap_or_default(),
+                format: cli.format,
+                scan: cli.scan,
+            });
+            std::process::exit(cli::execute(command, cli.config.as_deref()));
+        }
+        None => cli.gui,
     };
 
-    absolute
-        .canonicalize()
-        .unwrap_or(absolute)
-        .to_string_lossy()
-        .to_string()
+    launch_gui(args, cli.config);
 }
 
-fn print_help() {
-    println!("diverge {}", env!("CARGO_PKG_VERSION"));
-    println!("A visual directory diff tool");
-    println!();
-    println!("Usage: diverge [OPTIONS] [LEFT] [RIGHT]");
-    println!();
-    println!("Arguments:");
-    println!("  [LEFT]   Left directory to compare");
-    println!("  [RIGHT]  Right directory to compare");
-    println!();
-    println!("Options:");
-    println!("  -h, --help     Print help");
-    println!("  -V, --version  Print version");
-    println!("  -w, --wait     Wait for the window to be closed before returning");
-    println!();
-    println!("Examples:");
-    println!("  diverge                    Open with empty comparison");
-    println!("  diverge /path/to/dir       Open with left side pre-filled");
-    println!("  diverge dir1 dir2          Compare two directories");
-    println!("  diverge dir1 dir2 --wait   Block until the window is closed");
+#[cfg(not(feature = "gui"))]
+fn launch_gui(_args: GuiArgs, _config: Option<std::path::PathBuf>) {
+    eprintln!("diverge: built without the gui feature; use `diverge compare`, `report`, `diff` or `sync`");
+    std::process::exit(2);
 }
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
-
-    let has_flag = |short: &str, long: &str| -> bool {
-        args.iter().any(|a| a == short || a == long)
-    };
-
-    if has_flag("-V", "--version") {
-        println!("diverge {}", env!("CARGO_PKG_VERSION"));
-        return;
-    }
-
-    if has_flag("-h", "--help") {
-        print_help();
-        return;
-    }
-
-    let wait = has_flag("-w", "--wait");
-
-    if !wait && std::env::var("_DIVERGE_DETACHED").is_err() {
+#[cfg(feature = "gui")]
+fn launch_gui(args: GuiArgs, config: Option<std::path::PathBuf>) {
+    if !args.wait && std::env::var("_DIVERGE_DETACHED").is_err() {
         #[cfg(all(target_os = "linux", not(debug_assertions)))]
         {
             let exe = std::fs::read_link("/proc/self/exe")
@@ -103,17 +71,9 @@ fn main() {
         .map(|p| p.to_string_lossy().to_string())
         .unwrap_or_default();
 
-    let positional: Vec<&str> = args[1..]
-        .iter()
-        .filter(|a| !a.starts_with('-'))
-        .map(|a| a.as_str())
-        .collect();
-
-    let (left, right) = match positional.as_slice() {
-        [l, r, ..] => (resolve_path(l), resolve_path(r)),
-        [l] => (resolve_path(l), String::new()),
-        [] => (String::new(), String::new()),
-    };
+    let left = args.left.as_deref().map(cli::resolve_path).unwrap_or_default();
+    let right = args.right.as_deref().map(cli::resolve_path).unwrap_or_default();
+    let config = config.and_then(|p| cli::config_path(Some(&p)).ok());
 
-    diverge_lib::run_with_args(left, right, cwd);
+    diverge_lib::run_with_args(left, right, cwd, config);
 }
@@ -0,0 +1,416 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use similar::{capture_diff_slices_deadline, Algorithm, DiffTag};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::time::Instant;
+
+use crate::stats::DIFF_TIMEOUT;
+
+/// How a conflict is resolved instead of being marked, as `git merge-file --ours`,
+/// `--theirs` and `--union` do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Favor {
+    Ours,
+    Theirs,
+    /// Both sides, ours first.
+    Union,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MergeOptions {
+    /// Treat lines that differ only in the amount of whitespace as equal, like git's
+    /// `-Xignore-space-change`. Where only whitespace changed, the other side's change wins,
+    /// and our whitespace is kept elsewhere.
+    pub ignore_whitespace: bool,
+    /// Resolves every conflict this way.
+    pub favor: Option<Favor>,
+    /// Resolutions of single conflicts, by their index in `TextMerge::conflicts`. They take
+    /// precedence over `favor`.
+    pub resolutions: HashMap<usize, Favor>,
+    /// Names written after the ours, base and theirs markers.
+    pub labels: [String; 3],
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        Self {
+            ignore_whitespace: false,
+            favor: None,
+            resolutions: HashMap::new(),
+            labels: ["ours", "base", "theirs"].map(String::from),
+        }
+    }
+}
+
+/// The result of a three-way text merge.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TextMerge {
+    /// The merged text, with diff3-style markers around the conflicts left unresolved.
+    pub text: String,
+    /// Every region both sides changed differently, resolved or not.
+    pub conflicts: Vec<ConflictHunk>,
+}
+
+impl TextMerge {
+    /// Conflicts marked in the text rather than resolved.
+    pub fn unresolved(&self) -> usize {
+        self.conflicts.iter().filter(|c| c.resolution.is_none()).count()
+    }
+}
+
+/// A region both sides changed differently.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConflictHunk {
+    /// 1-based line where the region starts in the base, ours and theirs.
+    pub base_line: usize,
+    pub ours_line: usize,
+    pub theirs_line: usize,
+    /// 1-based line in the merged text of the `<<<<<<<` marker, or of the resolution.
+    pub merged_line: usize,
+    pub base: String,
+    pub ours: String,
+    pub theirs: String,
+    pub resolution: Option<Favor>,
+}
+
+/// A run of base lines one side replaced, and the lines that replaced them.
+#[derive(Debug, Clone)]
+struct Change {
+    base: Range<usize>,
+    side: Range<usize>,
+}
+
+/// A stretch of the merge: lines of ours or of theirs, or a conflict between them.
+#[derive(Debug, PartialEq, Eq)]
+enum Chunk {
+    Ours(Range<usize>),
+    Theirs(Range<usize>),
+    Conflict {
+        base: Range<usize>,
+        ours: Range<usize>,
+        theirs: Range<usize>,
+    },
+}
+
+/// Merges the changes `ours` and `theirs` each made to `base`, line by line like
+/// `git merge-file --diff3`. Changes that overlap or touch conflict unless identical.
+pub fn merge_text(base: &str, ours: &str, theirs: &str, options: &MergeOptions) -> TextMerge {
+    let (base, ours, theirs) = (lines(base), lines(ours), lines(theirs));
+    let key = |lines: &[&'_ str]| -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| match options.ignore_whitespace {
+                true => line.split_whitespace().collect::<Vec<_>>().join(" "),
+                false => line.to_string(),
+            })
+            .collect()
+    };
+    let chunks = chunks(&key(&base), &key(&ours), &key(&theirs));
+
+    let [ours_label, base_label, theirs_label] = &options.labels;
+    let mut merge = TextMerge::default();
+    // 1-based line of the merged text the next chunk starts at.
+    let mut line = 1;
+    for chunk in chunks {
+        let (base_range, ours_range, theirs_range) = match chunk {
+            Chunk::Ours(range) => {
+                push(&mut merge.text, &mut line, &ours[range].concat());
+                continue;
+            }
+            Chunk::Theirs(range) => {
+                push(&mut merge.text, &mut line, &theirs[range].concat());
+                continue;
+            }
+            Chunk::Conflict { base, ours, theirs } => (base, ours, theirs),
+        };
+
+        let index = merge.conflicts.len();
+        let resolution = options.resolutions.get(&index).copied().or(options.favor);
+        let (base_text, ours_text, theirs_text) = (
+            base[base_range.clone()].concat(),
+            ours[ours_range.clone()].concat(),
+            theirs[theirs_range.clone()].concat(),
+        );
+        merge.conflicts.push(ConflictHunk {
+            base_line: base_range.start + 1,
+            ours_line: ours_range.start + 1,
+            theirs_line: theirs_range.start + 1,
+            merged_line: line,
+            base: base_text.clone(),
+            ours: ours_text.clone(),
+            theirs: theirs_text.clone(),
+            resolution,
+        });
+        let text = match resolution {
+            Some(Favor::Ours) => ours_text,
+            Some(Favor::Theirs) => theirs_text,
+            Some(Favor::Union) => terminated(ours_text) + &theirs_text,
+            None => format!(
+                "<<<<<<< {}\n{}||||||| {}\n{}=======\n{}>>>>>>> {}\n",
+                ours_label,
+                terminated(ours_text),
+                base_label,
+                terminated(base_text),
+                terminated(theirs_text),
+                theirs_label
+            ),
+        };
+        push(&mut merge.text, &mut line, &text);
+    }
+    merge
+}
+
+/// The merged text if nothing conflicts.
+pub fn merge_lines(base: &str, ours: &str, theirs: &str) -> Option<String> {
+    let merge = merge_text(base, ours, theirs, &MergeOptions::default());
+    merge.conflicts.is_empty().then_some(merge.text)
+}
+
+fn push(out: &mut String, line: &mut usize, text: &str) {
+    *line += text.matches('\n').count();
+    out.push_str(text);
+}
+
+fn lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// Ends non-empty text with a newline, so a marker after it starts on its own line.
+fn terminated(mut text: String) -> String {
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text
+}
+
+/// Base ranges each side changed, in order. Adjacent edits form a single change.
+fn changes(base: &[String], side: &[String]) -> Vec<Change> {
+    let deadline = Some(Instant::now() + DIFF_TIMEOUT);
+    let mut changes: Vec<Change> = Vec::new();
+    for op in capture_diff_slices_deadline(Algorithm::Myers, base, side, deadline) {
+        let (tag, base_range, side_range) = op.as_tag_tuple();
+        if tag == DiffTag::Equal {
+            continue;
+        }
+        match changes.last_mut() {
+            Some(last) if last.base.end == base_range.start && last.side.end == side_range.start => {
+                last.base.end = base_range.end;
+                last.side.end = side_range.end;
+            }
+            _ => changes.push(Change {
+                base: base_range,
+                side: side_range,
+            }),
+        }
+    }
+    changes
+}
+
+/// Splits the merge into chunks (the diff3 algorithm). Changes from both sides that overlap
+/// or touch are grouped; a group changed by one side, or changed alike by both, is taken
+/// from that side, and anything else conflicts. Unchanged stretches are taken from ours.
+fn chunks(base: &[String], ours: &[String], theirs: &[String]) -> Vec<Chunk> {
+    let (ours_changes, theirs_changes) = (changes(base, ours), changes(base, theirs));
+    let (mut o, mut t) = (0, 0);
+    // Base line `n` outside our changes is our line `n + shift`.
+    let (mut pos, mut shift) = (0, 0isize);
+    let mut chunks = Vec::new();
+    let ours_at = |base_line: usize, shift: isize| base_line.saturating_add_signed(shift);
+
+    loop {
+        let first = match (ours_changes.get(o), theirs_changes.get(t)) {
+            (Some(a), Some(b)) if b.base.start < a.base.start => b,
+            (Some(a), _) => a,
+            (None, Some(b)) => b,
+            (None, None) => break,
+        };
+        let region_start = first.base.start;
+        let mut region_end = first.base.end;
+        let (o_start, t_start) = (o, t);
+        loop {
+            if let Some(c) = ours_changes.get(o).filter(|c| c.base.start <= region_end) {
+                region_end = region_end.max(c.base.end);
+                o += 1;
+            } else if let Some(c) = theirs_changes.get(t).filter(|c| c.base.start <= region_end) {
+                region_end = region_end.max(c.base.end);
+                t += 1;
+            } else {
+                break;
+            }
+        }
+
+        if pos < region_start {
+            chunks.push(Chunk::Ours(ours_at(pos, shift)..ours_at(region_start, shift)));
+        }
+        let region = region_start..region_end;
+        let ours_part = match &ours_changes[o_start..o] {
+            [] => ours_at(region.start, shift)..ours_at(region.end, shift),
+            changed => side_part(changed, &region),
+        };
+        shift = ours_part.end as isize - region_end as isize;
+        chunks.push(match &theirs_changes[t_start..t] {
+            [] => Chunk::Ours(ours_part),
+            changed => {
+                let theirs_part = side_part(changed, &region);
+                if o == o_start {
+                    Chunk::Theirs(theirs_part)
+                } else if ours[ours_part.clone()] == theirs[theirs_part.clone()] {
+                    Chunk::Ours(ours_part)
+                } else {
+                    Chunk::Conflict {
+                        base: region,
+                        ours: ours_part,
+                        theirs: theirs_part,
+                    }
+                }
+            }
+        });
+        pos = region_end;
+    }
+    if pos < base.len() || ours_at(pos, shift) < ours.len() {
+        chunks.push(Chunk::Ours(ours_at(pos, shift)..ours.len()));
+    }
+    chunks
+}
+
+/// Where a side has the base `region`, given its (non-empty) changes within it. Base lines
+/// around the changes are unchanged on this side, so they map one to one.
+fn side_part(changes: &[Change], region: &Range<usize>) -> Range<usize> {
+    let (first, last) = (&changes[0], &changes[changes.len() - 1]);
+    let start = first.side.start - (first.base.start - region.start);
+    let end = last.side.end + (region.end - last.base.end);
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::process::Command;
+
+    #[test]
+    fn merges_changes_to_different_lines() {
+        let base = "a\nb\nc\nd\ne\n";
+        let ours = "A\nb\nc\nd\ne\n";
+        let theirs = "a\nb\nc\nd\nE\nf\n";
+        assert_eq!(merge_lines(base, ours, theirs).as_deref(), Some("A\nb\nc\nd\nE\nf\n"));
+
+        // The same change on both sides is taken once.
+        assert_eq!(merge_lines(base, ours, ours).as_deref(), Some(ours));
+        assert_eq!(merge_lines(base, base, theirs).as_deref(), Some(theirs));
+    }
+
+    #[test]
+    fn overlapping_and_adjacent_changes_conflict() {
+        let base = "a\nb\nc\n";
+        assert_eq!(merge_lines(base, "a\nB\nc\n", "a\nX\nc\n"), None);
+        // Changes to neighbouring lines conflict too, as in git.
+        assert_eq!(merge_lines(base, "A\nb\nc\n", "a\nB\nc\n"), None);
+        // Both sides appending different lines at the end.
+        assert_eq!(merge_lines(base, "a\nb\nc\nours\n", "a\nb\nc\ntheirs\n"), None);
+    }
+
+    #[test]
+    fn conflicts_are_marked_and_listed() {
+        let merge = merge_text(
+            "1\n2\n3\n4\n",
+            "1\nx\n3\n4\n",
+            "1\ny\n3\n4\nfive\n",
+            &MergeOptions::default(),
+        );
+        assert_eq!(
+            merge.text,
+            "1\n<<<<<<< ours\nx\n||||||| base\n2\n=======\ny\n>>>>>>> theirs\n3\n4\nfive\n"
+        );
+        assert_eq!(
+            merge.conflicts,
+            vec![ConflictHunk {
+                base_line: 2,
+                ours_line: 2,
+                theirs_line: 2,
+                merged_line: 2,
+                base: "2\n".to_string(),
+                ours: "x\n".to_string(),
+                theirs: "y\n".to_string(),
+                resolution: None,
+            }]
+        );
+        assert_eq!(merge.unresolved(), 1);
+    }
+
+    #[test]
+    fn favor_resolves_conflicts() {
+        let (base, ours, theirs) = ("a\nb\nc\nd\ne\n", "a\nB\nc\nD\ne\n", "a\nX\nc\nY\ne\n");
+        let with = |favor, resolutions: &[(usize, Favor)]| {
+            let options = MergeOptions {
+                favor,
+                resolutions: resolutions.iter().copied().collect(),
+                ..Default::default()
+            };
+            merge_text(base, ours, theirs, &options)
+        };
+
+        assert_eq!(with(Some(Favor::Ours), &[]).text, ours);
+        assert_eq!(with(Some(Favor::Theirs), &[]).text, theirs);
+        assert_eq!(with(Some(Favor::Union), &[]).text, "a\nB\nX\nc\nD\nY\ne\n");
+
+        // Per hunk: the first conflict takes theirs, the second stays marked.
+        let merge = with(None, &[(0, Favor::Theirs)]);
+        assert!(merge.text.starts_with("a\nX\nc\n<<<<<<< ours\nD\n"));
+        assert_eq!(merge.conflicts[0].resolution, Some(Favor::Theirs));
+        assert_eq!((merge.conflicts[1].merged_line, merge.unresolved()), (4, 1));
+    }
+
+    #[test]
+    fn ignore_whitespace_keeps_the_substantial_change() {
+        let base = "fn a() {\n    one();\n}\nfn b() {}\n";
+        let ours = "fn a() {\n\tone();\n}\nfn b() {}\n";
+        let theirs = "fn a() {\n    two();\n}\nfn b() {}\n";
+        let options = MergeOptions {
+            ignore_whitespace: true,
+            ..Default::default()
+        };
+        assert_eq!(merge_text(base, ours, theirs, &options).text, theirs);
+        assert!(merge_text(base, ours, theirs, &MergeOptions::default()).unresolved() > 0);
+
+        // Our reindentation is kept where theirs did not change anything.
+        let theirs = "fn a() {\n    one();\n}\nfn b() { three(); }\n";
+        let merged = merge_text(base, ours, theirs, &options).text;
+        assert_eq!(merged, "fn a() {\n\tone();\n}\nfn b() { three(); }\n");
+    }
+
+    /// Each case is (base, ours, theirs); the output must match `git merge-file -p --diff3`.
+    #[test]
+    fn matches_git_merge_file() {
+        let cases = [
+            ("a\nb\nc\nd\ne\nf\ng\n", "a\nB\nc\nd\ne\nf\ng\n", "a\nb\nc\nd\ne\nF\ng\n"),
+            ("a\nb\nc\n", "a\nours\nc\n", "a\ntheirs\nc\n"),
+            ("a\nb\nc\n", "A\nb\nc\n", "a\nB\nc\n"),
+            ("a\nb\nc\n", "a\nb\nc\nours\n", "a\nb\nc\ntheirs\n"),
+            ("a\nb\nc\n", "a\nc\n", "a\nb2\nc\n"),
+            ("a\nb\n", "a\nb\nno newline", "a\nb\nother"),
+            ("", "ours\n", "theirs\n"),
+            ("1\n2\n3\n4\n5\n6\n7\n8\n9\n", "1\nx\n3\n4\n5\n6\n7\ny\n9\n", "1\nx\n3\n4\nz\n6\n7\n8\n9\n"),
+            ("x\ny\n", "x\ny\n", "x\ny\n"),
+        ];
+        let dir = tempfile::tempdir().unwrap();
+        for (i, (base, ours, theirs)) in cases.into_iter().enumerate() {
+            for (name, text) in [("base", base), ("ours", ours), ("theirs", theirs)] {
+                fs::write(dir.path().join(name), text).unwrap();
+            }
+            let output = Command::new("git")
+                .args(["merge-file", "-p", "--diff3", "-L", "ours", "-L", "base", "-L", "theirs"])
+                .args(["ours", "base", "theirs"])
+                .current_dir(dir.path())
+                .output()
+                .expect("git must be installed to run this test");
+            let merge = merge_text(base, ours, theirs, &MergeOptions::default());
+            assert_eq!(merge.text, String::from_utf8_lossy(&output.stdout), "case {}", i);
+            // git exits with the number of conflicts.
+            assert_eq!(output.status.code(), Some(merge.unresolved() as i32), "case {}", i);
+        }
+    }
+}
@@ -1,5 +1,11 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
+use crate::stats::LineStats;
+#[cfg(feature = "gui")]
+use std::{collections::HashMap, path::PathBuf, sync::atomic::AtomicBool, sync::Arc};
+
+#[cfg(feature = "gui")]
 #[derive(Debug, Clone, Serialize)]
 pub struct CliArgs {
     pub left_dir: String,
@@ -25,6 +31,17 @@ fn default_sidebar_width() -> u32 {
     280
 }
 
+/// Files larger than this (in bytes) are compared by hash only and never loaded for display.
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+fn default_max_file_size() -> u64 {
+    DEFAULT_MAX_FILE_SIZE
+}
+
+fn default_rename_similarity() -> u8 {
+    50
+}
+
 impl Default for EditorPreferences {
     fn default() -> Self {
         Self {
@@ -35,6 +52,28 @@ impl Default for EditorPreferences {
     }
 }
 
+/// How much of the undo history kept next to the config file is kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// Save the previous version of every file Diverge overwrites or deletes.
+    pub enabled: bool,
+    /// Sessions (one per app launch or writing command) kept, oldest removed first; 0 keeps all.
+    pub max_sessions: usize,
+    /// Sessions not written to in this many days are removed; 0 keeps them regardless of age.
+    pub max_age_days: u64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_sessions: 50,
+            max_age_days: 30,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RecentComparison {
     pub left_dir: String,
@@ -43,30 +82,251 @@ pub struct RecentComparison {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
+    /// Ignore patterns with `.gitignore` semantics (`*.log`, `!keep.me`, `/docs/generated`, ...).
     #[serde(default)]
     pub ignore_dirs: Vec<String>,
     #[serde(default)]
     pub editor_preferences: EditorPreferences,
     #[serde(default)]
     pub recent_comparisons: Vec<RecentComparison>,
+    /// Treat files with the same size and modification time as identical without hashing them.
+    #[serde(default)]
+    pub trust_mtime: bool,
+    /// Confirm every hash match with a byte-by-byte comparison.
+    #[serde(default)]
+    pub paranoid: bool,
+    /// Files above this size (in bytes) are listed as `too_large` and not diffed textually.
+    #[serde(default = "default_max_file_size")]
+    pub max_file_size: u64,
+    /// Also skip what `.gitignore`, `.ignore`, `.git/info/exclude` and the global git excludes ignore.
+    #[serde(default)]
+    pub respect_gitignore: bool,
+    /// Worker threads used to scan and hash; 0 uses one per CPU core.
+    #[serde(default)]
+    pub threads: usize,
+    /// Pair files that only exist on one side into `renamed` entries.
+    #[serde(default)]
+    pub detect_renames: bool,
+    /// Minimum similarity (percent) for pairing files whose contents changed; 100 pairs
+    /// identical files only.
+    #[serde(default = "default_rename_similarity")]
+    pub rename_similarity: u8,
+    /// Retention of the undo history.
+    #[serde(default)]
+    pub history: HistoryConfig,
+}
+
+/// Settings for one comparison; `Comparer` has a builder method for each.
+#[derive(Debug, Clone)]
+pub struct CompareOptions {
+    pub ignore_dirs: Vec<String>,
+    pub trust_mtime: bool,
+    pub paranoid: bool,
+    pub max_file_size: u64,
+    pub respect_gitignore: bool,
+    pub threads: usize,
+    /// Minimum similarity (percent) for rename detection; `None` turns it off.
+    pub renames: Option<u8>,
+}
+
+impl Default for CompareOptions {
+    fn default() -> Self {
+        Self {
+            ignore_dirs: vec![],
+            trust_mtime: false,
+            paranoid: false,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            respect_gitignore: false,
+            threads: 0,
+            renames: None,
+        }
+    }
 }
 
+impl CompareOptions {
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            ignore_dirs: config.ignore_dirs.clone(),
+            trust_mtime: config.trust_mtime,
+            paranoid: config.paranoid,
+            max_file_size: config.max_file_size,
+            respect_gitignore: config.respect_gitignore,
+            threads: config.threads,
+            renames: config
+                .detect_renames
+                .then_some(config.rename_similarity.min(100)),
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
 pub struct AppState {
     pub cli_args: CliArgs,
     pub config: AppConfig,
+    pub config_path: PathBuf,
+    pub compared_dirs: Option<(String, String)>,
+    /// The latest complete comparison of `compared_dirs`, which sync plans are made from.
+    pub last_result: Option<CompareResult>,
+    /// Cancellation flags of the comparisons still running, by job id.
+    pub jobs: HashMap<u64, Arc<AtomicBool>>,
+    pub next_job_id: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryStatus {
+    /// Present on both sides with the same contents.
+    Identical,
+    /// Present on both sides with different contents.
+    Different,
+    OnlyLeft,
+    OnlyRight,
+    /// A left-only and a right-only file paired as the same file under a new path.
+    Renamed,
 }
 
+impl EntryStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Identical => "identical",
+            Self::Different => "different",
+            Self::OnlyLeft => "only_left",
+            Self::OnlyRight => "only_right",
+            Self::Renamed => "renamed",
+        }
+    }
+}
+
+impl fmt::Display for EntryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// Ordered so that the kind of a pair of files is the `max` of both sides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    Text,
+    Binary,
+    /// Larger than `max_file_size` on at least one side: compared by hash, never loaded.
+    TooLarge,
+}
+
+/// Where two binary files first differ, with up to 16 bytes from each side in hex.
+#[derive(Debug, Clone, Serialize)]
+pub struct BinaryDiff {
+    pub offset: u64,
+    pub left_bytes: String,
+    pub right_bytes: String,
+}
+
+/// One file present on either side, keyed by its `/`-separated path relative to the roots.
 #[derive(Debug, Clone, Serialize)]
 pub struct CompareEntry {
     pub rel_path: String,
-    pub status: String,
-    pub left_content: String,
-    pub right_content: String,
+    pub status: EntryStatus,
+    pub kind: EntryKind,
     pub left_path: String,
     pub right_path: String,
+    /// Sizes and BLAKE3 hashes are `None` on the side the file is missing from; hashes
+    /// are also `None` when the sizes or, when trusted, the mtimes decided the comparison.
+    pub left_size: Option<u64>,
+    pub right_size: Option<u64>,
+    pub left_hash: Option<String>,
+    pub right_hash: Option<String>,
+    /// Modification times in milliseconds since the Unix epoch, when the filesystem has them.
+    pub left_modified: Option<u64>,
+    pub right_modified: Option<u64>,
+    pub binary_diff: Option<BinaryDiff>,
+    /// For `renamed` entries, the left path the file was moved from; `rel_path` is the right one.
+    pub renamed_from: Option<String>,
+    /// How alike both sides are, in percent of lines; set for differing text files and renames.
+    pub similarity: Option<u8>,
+    /// Line diff counts for text files that are not identical. A file on one side only
+    /// counts as entirely added or removed.
+    pub line_stats: Option<LineStats>,
 }
 
+/// How a path changed on each side of a three-way comparison, relative to the base.
+/// Adding or deleting a file counts as changing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThreeWayStatus {
+    Unchanged,
+    ChangedOurs,
+    ChangedTheirs,
+    /// Both sides made the same change.
+    ChangedBothIdentically,
+    /// Both sides changed the file differently, or it could not be read.
+    Conflicting,
+}
+
+impl ThreeWayStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Unchanged => "unchanged",
+            Self::ChangedOurs => "changed_ours",
+            Self::ChangedTheirs => "changed_theirs",
+            Self::ChangedBothIdentically => "changed_both_identically",
+            Self::Conflicting => "conflicting",
+        }
+    }
+}
+
+impl fmt::Display for ThreeWayStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// One path of a three-way comparison. A hash is `None` where the file does not exist.
+#[derive(Debug, Clone, Serialize)]
+pub struct ThreeWayEntry {
+    pub rel_path: String,
+    pub status: ThreeWayStatus,
+    pub kind: EntryKind,
+    pub base_hash: Option<String>,
+    pub ours_hash: Option<String>,
+    pub theirs_hash: Option<String>,
+    /// A conflicting text file whose changes do not touch the same lines, so a line-level
+    /// merge resolves it.
+    pub mergeable: bool,
+}
+
+/// Entries of a three-way comparison sorted by path, with counts per status.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ThreeWayResult {
+    pub entries: Vec<ThreeWayEntry>,
+    pub total: usize,
+    pub unchanged: usize,
+    pub changed_ours: usize,
+    pub changed_theirs: usize,
+    pub changed_both_identically: usize,
+    pub conflicting: usize,
+    /// Conflicting entries a line-level merge resolves.
+    pub mergeable: usize,
+    pub ignored_dirs: Vec<String>,
+}
+
+/// One of the two compared directories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// Both sides of one entry. Only text entries are loaded; for other kinds both contents are empty.
 #[derive(Debug, Clone, Serialize)]
+pub struct EntryContents {
+    pub left_content: String,
+    pub right_content: String,
+    pub kind: EntryKind,
+}
+
+/// Entries sorted by path, with counts per status.
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct CompareResult {
     pub entries: Vec<CompareEntry>,
     pub total: usize,
@@ -74,5 +334,10 @@ pub struct CompareResult {
     pub different: usize,
     pub only_left: usize,
     pub only_right: usize,
+    pub renamed: usize,
+    pub too_large: usize,
+    /// Sum of every entry's `line_stats`.
+    pub line_totals: LineStats,
+    /// What the ignore rules skipped, for display.
     pub ignored_dirs: Vec<String>,
 }
@@ -0,0 +1,491 @@
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::iter::Peekable;
+use std::path::Path;
+
+use crate::compare::relative;
+use crate::error::DivergeError;
+use crate::history;
+use crate::models::CompareResult;
+use crate::write;
+
+/// What applying a patch did to a directory.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PatchOutcome {
+    /// Files written, created or deleted, by their path after the patch.
+    pub patched: Vec<String>,
+    pub conflicts: Vec<PatchConflict>,
+    /// The comparison run again once the patch is applied, when applied to a compared side.
+    pub result: Option<CompareResult>,
+}
+
+/// A hunk, or a whole file, that could not be applied.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PatchConflict {
+    pub path: String,
+    /// 1-based index of the rejected hunk in its file; `None` when the whole file was rejected.
+    pub hunk: Option<usize>,
+    /// The `@@ -a,b +c,d @@` line of the hunk, or empty for a whole file.
+    pub header: String,
+    pub reason: String,
+}
+
+/// The changes to one file.
+#[derive(Debug, Default)]
+struct FilePatch {
+    /// `None` for a new file.
+    old_path: Option<String>,
+    /// `None` for a deleted file.
+    new_path: Option<String>,
+    /// Git's `100755` or `100644`, when the patch sets it.
+    mode: Option<u32>,
+    binary: bool,
+    hunks: Vec<Hunk>,
+    /// Still reading git's extended header lines (`rename from`, `new file mode`, ...).
+    in_header: bool,
+}
+
+#[derive(Debug)]
+struct Hunk {
+    header: String,
+    old_start: usize,
+    /// Lines with their line ending, as they are before and after the hunk.
+    old: Vec<String>,
+    new: Vec<String>,
+}
+
+/// Applies a unified diff or git patch to the tree at `root`, hunk by hunk, after removing
+/// `strip` leading components from its paths like `patch -p`. Hunks are found at their line
+/// number or, if the file moved on, at the nearest place where their context matches. Hunks
+/// that match nowhere are reported as conflicts and the rest of the file is still patched.
+/// A file that cannot be read or written is reported as a conflict too, and the other files
+/// are still patched. With `dry_run`, nothing is written.
+pub fn apply_patch(
+    root: &str,
+    patch: &str,
+    strip: usize,
+    dry_run: bool,
+) -> Result<PatchOutcome, DivergeError> {
+    let files = parse(patch, strip)?;
+    let mut outcome = PatchOutcome::default();
+    history::operation(format!("patch {}", root), || {
+        for file in &files {
+            if let Err(e) = apply_file(Path::new(root), file, dry_run, &mut outcome) {
+                outcome.conflicts.push(PatchConflict {
+                    path: file.path().to_string(),
+                    hunk: None,
+                    header: String::new(),
+                    reason: e.to_string(),
+                });
+            }
+        }
+    });
+    Ok(outcome)
+}
+
+fn parse(text: &str, strip: usize) -> Result<Vec<FilePatch>, DivergeError> {
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut lines = text.split_inclusive('\n').peekable();
+    while let Some(line) = lines.next() {
+        if let Some(paths) = line.strip_prefix("diff --git ") {
+            let (old, new) = paths
+                .trim_end()
+                .split_once(" b/")
+                .map(|(a, b)| (a.to_string(), format!("b/{}", b)))
+                .ok_or_else(|| DivergeError::invalid(format!("Malformed patch line: {}", line.trim_end())))?;
+            files.push(FilePatch {
+                old_path: Some(strip_path(&old, strip)?),
+                new_path: Some(strip_path(&new, strip)?),
+                in_header: true,
+                ..Default::default()
+            });
+        } else if let Some(old) = line.strip_prefix("--- ") {
+            let new = lines
+                .next()
+                .and_then(|l| l.strip_prefix("+++ "))
+                .ok_or_else(|| DivergeError::invalid("Malformed patch: `---` line without `+++`"))?;
+            // A git patch has already started this file with its `diff --git` line.
+            if !files.last().is_some_and(|f| f.in_header) {
+                files.push(FilePatch::default());
+            }
+            let file = files.last_mut().expect("pushed above");
+            file.old_path = header_path(old, strip)?;
+            file.new_path = header_path(new, strip)?;
+            file.in_header = false;
+        } else if line.starts_with("@@ ") {
+            let file = files
+                .last_mut()
+                .ok_or_else(|| DivergeError::invalid("Malformed patch: hunk before any file header"))?;
+            file.in_header = false;
+            file.hunks.push(parse_hunk(line, &mut lines)?);
+        } else if let Some(file) = files.last_mut().filter(|f| f.in_header) {
+            file.extended_header(line.trim_end())?;
+        }
+        // Anything else, like a commit message, is not part of the patch.
+    }
+    if files.is_empty() {
+        return Err(DivergeError::invalid("No patch found in the input"));
+    }
+    Ok(files)
+}
+
+impl FilePatch {
+    fn extended_header(&mut self, line: &str) -> Result<(), DivergeError> {
+        let mode = |m: &str| u32::from_str_radix(m, 8).ok();
+        if let Some(m) = line.strip_prefix("new file mode ") {
+            self.old_path = None;
+            self.mode = mode(m);
+        } else if line.starts_with("deleted file mode ") {
+            self.new_path = None;
+        } else if let Some(m) = line.strip_prefix("new mode ") {
+            self.mode = mode(m);
+        } else if let Some(path) = line.strip_prefix("rename from ") {
+            self.old_path = Some(checked(path)?);
+        } else if let Some(path) = line.strip_prefix("rename to ") {
+            self.new_path = Some(checked(path)?);
+        } else if line.starts_with("GIT binary patch") || line.starts_with("Binary files ") {
+            self.binary = true;
+        }
+        Ok(())
+    }
+
+    /// The path a conflict or a patched file is reported under.
+    fn path(&self) -> &str {
+        self.new_path.as_deref().or(self.old_path.as_deref()).unwrap_or_default()
+    }
+}
+
+/// Path of a `---` or `+++` line, `None` for `/dev/null`. A tab ends the path, as
+/// `diff -u` puts the modification time after it.
+fn header_path(line: &str, strip: usize) -> Result<Option<String>, DivergeError> {
+    let path = line.split('\t').next().unwrap_or_default().trim_end();
+    if path == "/dev/null" {
+        return Ok(None);
+    }
+    strip_path(path, strip).map(Some)
+}
+
+fn strip_path(path: &str, strip: usize) -> Result<String, DivergeError> {
+    let stripped = path.split('/').skip(strip).collect::<Vec<_>>().join("/");
+    if stripped.is_empty() {
+        return Err(DivergeError::invalid(format!(
+            "Cannot strip {} components from {}",
+            strip, path
+        )));
+    }
+    checked(&stripped)
+}
+
+/// Rejects paths that would escape the directory the patch applies to.
+fn checked(path: &str) -> Result<String, DivergeError> {
+    relative(path).map(|_| path.to_string())
+}
+
+fn parse_hunk<'a>(
+    header: &str,
+    lines: &mut Peekable<impl Iterator<Item = &'a str>>,
+) -> Result<Hunk, DivergeError> {
+    let malformed = || DivergeError::invalid(format!("Malformed hunk header: {}", header.trim_end()));
+    let ranges = header
+        .strip_prefix("@@ -")
+        .and_then(|h| h.split_once(" @@"))
+        .map(|(ranges, _)| ranges)
+        .ok_or_else(malformed)?;
+    let (old, new) = ranges.split_once(" +").ok_or_else(malformed)?;
+    let range = |r: &str| -> Option<(usize, usize)> {
+        match r.split_once(',') {
+            Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+            None => Some((r.parse().ok()?, 1)),
+        }
+    };
+    let ((old_start, mut old_left), (_, mut new_left)) =
+        (range(old).ok_or_else(malformed)?, range(new).ok_or_else(malformed)?);
+
+    let mut hunk = Hunk {
+        header: header.trim_end().to_string(),
+        old_start,
+        old: Vec::new(),
+        new: Vec::new(),
+    };
+    while old_left > 0 || new_left > 0 {
+        let line = lines
+            .next()
+            .ok_or_else(|| DivergeError::invalid(format!("Truncated hunk: {}", hunk.header)))?;
+        let (sign, body) = match line.as_bytes()[0] {
+            b'-' | b'+' | b' ' => (line.as_bytes()[0], &line[1..]),
+            // Some editors strip the space of an empty context line.
+            b'\n' | b'\r' => (b' ', line),
+            _ => return Err(DivergeError::invalid(format!("Malformed hunk line: {}", line.trim_end()))),
+        };
+        let (old_side, new_side) = (sign != b'+', sign != b'-');
+        if old_side {
+            old_left = old_left.checked_sub(1).ok_or_else(malformed)?;
+            hunk.old.push(body.to_string());
+        }
+        if new_side {
+            new_left = new_left.checked_sub(1).ok_or_else(malformed)?;
+            hunk.new.push(body.to_string());
+        }
+        if lines.next_if(|l| l.starts_with('\\')).is_some() {
+            // `\ No newline at end of file` applies to the line just before it.
+            for (side, lines) in [(old_side, &mut hunk.old), (new_side, &mut hunk.new)] {
+                if let Some(last) = lines.last_mut().filter(|l| side && l.ends_with('\n')) {
+                    last.pop();
+                }
+            }
+        }
+    }
+    Ok(hunk)
+}
+
+fn apply_file(
+    root: &Path,
+    file: &FilePatch,
+    dry_run: bool,
+    outcome: &mut PatchOutcome,
+) -> Result<(), DivergeError> {
+    let reject = |outcome: &mut PatchOutcome, reason: &str| {
+        outcome.conflicts.push(PatchConflict {
+            path: file.path().to_string(),
+            hunk: None,
+            header: String::new(),
+            reason: reason.to_string(),
+        })
+    };
+    if file.binary {
+        reject(outcome, "binary patches are not supported");
+        return Ok(());
+    }
+
+    let text = match &file.old_path {
+        Some(old) => {
+            let path = root.join(old);
+            match fs::read(&path) {
+                Ok(bytes) => match String::from_utf8(bytes) {
+                    Ok(text) => text,
+                    Err(_) => {
+                        reject(outcome, "not a text file");
+                        return Ok(());
+                    }
+                },
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    reject(outcome, "file does not exist");
+                    return Ok(());
+                }
+                Err(e) => return Err(DivergeError::io(&path, e)),
+            }
+        }
+        None => {
+            if file.new_path.as_ref().is_some_and(|new| root.join(new).exists()) {
+                reject(outcome, "file already exists");
+                return Ok(());
+            }
+            String::new()
+        }
+    };
+
+    let before = outcome.conflicts.len();
+    let patched = apply_hunks(&text, file, &mut outcome.conflicts);
+    let rejected = outcome.conflicts.len() - before;
+    // A deletion is all or nothing, and a file none of whose hunks apply is left alone.
+    if rejected > 0 && (file.new_path.is_none() || rejected == file.hunks.len()) {
+        return Ok(());
+    }
+
+    match &file.new_path {
+        None if !patched.is_empty() => {
+            reject(outcome, "file has contents the patch does not delete");
+            return Ok(());
+        }
+        None if !dry_run => write::remove_file(&root.join(file.old_path.as_deref().unwrap_or_default()))?,
+        Some(new) if !dry_run => {
+            let target = root.join(new);
+            write::write_file(&target, patched.as_bytes())?;
+            if let Some(old) = file.old_path.as_deref().filter(|old| old != new) {
+                let old = root.join(old);
+                // A renamed file keeps its mode unless the patch changes it.
+                if let Ok(meta) = fs::metadata(&old) {
+                    fs::set_permissions(&target, meta.permissions())
+                        .map_err(|e| DivergeError::io(&target, e))?;
+                }
+                write::remove_file(&old)?;
+            }
+            if let Some(mode) = file.mode {
+                set_mode(&target, mode)?;
+            }
+        }
+        _ => {}
+    }
+    outcome.patched.push(file.path().to_string());
+    Ok(())
+}
+
+/// Applies the hunks of `file` to `text` in order, recording those that do not match.
+fn apply_hunks(text: &str, file: &FilePatch, conflicts: &mut Vec<PatchConflict>) -> String {
+    let mut lines: Vec<String> = text.split_inclusive('\n').map(String::from).collect();
+    // How far the lines have moved from the numbering of the patch, and where the last
+    // applied hunk ended: hunks are in file order, so the next one cannot start before.
+    let (mut offset, mut floor) = (0isize, 0usize);
+    for (i, hunk) in file.hunks.iter().enumerate() {
+        // A hunk without old lines inserts after line `old_start` rather than at it.
+        let start = hunk.old_start.saturating_sub(usize::from(!hunk.old.is_empty()));
+        let expected = (start as isize + offset).max(floor as isize) as usize;
+        match find(&lines, &hunk.old, expected, floor) {
+            Some(at) => {
+                lines.splice(at..at + hunk.old.len(), hunk.new.iter().cloned());
+                offset = at as isize - start as isize + hunk.new.len() as isize - hunk.old.len() as isize;
+                floor = at + hunk.new.len();
+            }
+            None => conflicts.push(PatchConflict {
+                path: file.path().to_string(),
+                hunk: Some(i + 1),
+                header: hunk.header.clone(),
+                reason: "context does not match".to_string(),
+            }),
+        }
+    }
+    lines.concat()
+}
+
+/// The position at or after `floor` nearest to `expected` where `old` appears.
+fn find(lines: &[String], old: &[String], expected: usize, floor: usize) -> Option<usize> {
+    let matches = |at: usize| at >= floor && lines.get(at..at + old.len()) == Some(old);
+    if old.is_empty() {
+        return (expected <= lines.len()).then_some(expected);
+    }
+    (0..=lines.len()).find_map(|distance| {
+        [expected + distance, expected.wrapping_sub(distance)]
+            .into_iter()
+            .find(|&at| at <= lines.len() && matches(at))
+    })
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<(), DivergeError> {
+    use std::os::unix::fs::PermissionsExt;
+    let permissions = fs::Permissions::from_mode(if mode & 0o111 != 0 { 0o755 } else { 0o644 });
+    fs::set_permissions(path, permissions).map_err(|e| DivergeError::io(path, e))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<(), DivergeError> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compare::compare;
+    use crate::diff::tree_diff;
+    use crate::models::CompareOptions;
+
+    fn write(dir: &Path, name: &str, text: &str) {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+
+    fn numbered(n: usize) -> String {
+        (1..=n).map(|i| format!("line {}\n", i)).collect()
+    }
+
+    #[test]
+    fn applies_a_generated_tree_diff() {
+        let (left, right) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        write(left.path(), "src/main.rs", &numbered(20));
+        write(right.path(), "src/main.rs", &numbered(20).replace("line 4\n", "four\n"));
+        write(right.path(), "new/file.txt", "hello");
+        write(left.path(), "gone.txt", "bye\n");
+        write(left.path(), "a/moved.txt", "same\n");
+        write(right.path(), "b/moved.txt", "same\n");
+        let (l, r) = (left.path().to_str().unwrap(), right.path().to_str().unwrap());
+        let options = CompareOptions {
+            renames: Some(100),
+            ..Default::default()
+        };
+        let patch = tree_diff(l, r, &compare(l, r, &options), 3).unwrap().patch;
+
+        let dry = apply_patch(l, &patch, 1, true).unwrap();
+        assert_eq!(dry.patched.len(), 4);
+        assert!(left.path().join("gone.txt").exists());
+
+        let outcome = apply_patch(l, &patch, 1, false).unwrap();
+        assert!(outcome.conflicts.is_empty(), "{:?}", outcome.conflicts);
+        let after = compare(l, r, &CompareOptions::default());
+        assert_eq!(after.identical, after.total, "{:?}", after.entries);
+    }
+
+    #[test]
+    fn hunks_follow_moved_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        // Two lines were inserted at the top since the patch was made.
+        write(dir.path(), "f.txt", &format!("new a\nnew b\n{}", numbered(10)));
+        let patch = "--- f.txt\t2024-01-01 00:00:00\n+++ f.txt\n@@ -4,3 +4,3 @@\n line 4\n-line 5\n+five\n line 6\n";
+
+        let outcome = apply_patch(dir.path().to_str().unwrap(), patch, 0, false).unwrap();
+        assert_eq!(outcome.patched, vec!["f.txt"]);
+        let text = fs::read_to_string(dir.path().join("f.txt")).unwrap();
+        assert!(text.contains("line 4\nfive\nline 6\n"));
+        assert!(text.starts_with("new a\n"));
+    }
+
+    #[test]
+    fn mismatched_hunks_are_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "f.txt", &numbered(30).replace("line 25\n", "edited\n"));
+        let patch = "diff --git a/f.txt b/f.txt\n--- a/f.txt\n+++ b/f.txt\n\
+                     @@ -2,3 +2,3 @@\n line 2\n-line 3\n+three\n line 4\n\
+                     @@ -24,3 +24,3 @@ fn context\n line 24\n-line 25\n+twenty-five\n line 26\n\
+                     diff --git a/missing.txt b/missing.txt\n--- a/missing.txt\n+++ b/missing.txt\n\
+                     @@ -1 +1 @@\n-x\n+y\n";
+
+        let outcome = apply_patch(dir.path().to_str().unwrap(), patch, 1, false).unwrap();
+        assert_eq!(outcome.patched, vec!["f.txt"]);
+        assert_eq!(
+            outcome.conflicts,
+            vec![
+                PatchConflict {
+                    path: "f.txt".to_string(),
+                    hunk: Some(2),
+                    header: "@@ -24,3 +24,3 @@ fn context".to_string(),
+                    reason: "context does not match".to_string(),
+                },
+                PatchConflict {
+                    path: "missing.txt".to_string(),
+                    hunk: None,
+                    header: String::new(),
+                    reason: "file does not exist".to_string(),
+                },
+            ]
+        );
+        let text = fs::read_to_string(dir.path().join("f.txt")).unwrap();
+        assert!(text.contains("three\n") && text.contains("edited\n"));
+    }
+
+    #[test]
+    fn unreadable_files_are_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("dir.txt")).unwrap();
+        write(dir.path(), "f.txt", "x\n");
+        let patch = "--- a/dir.txt\n+++ b/dir.txt\n@@ -1 +1 @@\n-x\n+y\n\
+                     --- a/f.txt\n+++ b/f.txt\n@@ -1 +1 @@\n-x\n+y\n";
+
+        let outcome = apply_patch(dir.path().to_str().unwrap(), patch, 1, false).unwrap();
+        assert_eq!(outcome.patched, vec!["f.txt"]);
+        assert_eq!(outcome.conflicts.len(), 1);
+        assert_eq!((outcome.conflicts[0].path.as_str(), outcome.conflicts[0].hunk), ("dir.txt", None));
+        assert_eq!(fs::read_to_string(dir.path().join("f.txt")).unwrap(), "y\n");
+    }
+
+    #[test]
+    fn rejects_malformed_and_escaping_patches() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        let apply = |patch: &str| apply_patch(root, patch, 1, false).unwrap_err().kind();
+
+        assert_eq!(apply("just some text\n"), "invalid_input");
+        assert_eq!(apply("--- a/x\n+++ b/x\n@@ -1 +1 @@\n-x\n"), "invalid_input");
+        assert_eq!(apply("--- a/../etc/passwd\n+++ b/../etc/passwd\n@@ -1 +1 @@\n-x\n+y\n"), "invalid_input");
+    }
+}
@@ -0,0 +1,287 @@
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::models::{CompareEntry, EntryKind, EntryStatus};
+use crate::stats::{line_diff, LineStats};
+
+/// Indices of a left-only and a right-only entry, their similarity and line diff.
+type Pair = (usize, usize, u8, LineStats);
+
+/// Above this many files on either side, only identical files are paired (like git's
+/// `diff.renameLimit`), since scoring compares every left-only file with every right-only one.
+const RENAME_LIMIT: usize = 1000;
+
+/// Pairs `only_left` with `only_right` entries into `renamed` ones, like git's `-M<threshold>%`.
+/// Files with the same hash pair first, at 100%. With `threshold` below 100, the remaining
+/// text files then pair with their most similar counterpart scoring at least `threshold`.
+/// A counterpart with the same file name wins ties. Returned entries are sorted by path.
+pub fn detect_renames(entries: Vec<CompareEntry>, threshold: u8) -> Vec<CompareEntry> {
+    let (mut gone, rest): (Vec<_>, Vec<_>) = entries
+        .into_iter()
+        .partition(|e| e.status == EntryStatus::OnlyLeft);
+    let (mut added, mut rest): (Vec<_>, Vec<_>) = rest
+        .into_iter()
+        .partition(|e| e.status == EntryStatus::OnlyRight);
+
+    let mut pairs = exact_pairs(&gone, &added);
+    if threshold < 100 && gone.len() <= RENAME_LIMIT && added.len() <= RENAME_LIMIT {
+        pairs.extend(similar_pairs(&gone, &added, &pairs, threshold));
+    }
+
+    // Take paired entries out back to front so the remaining indices stay valid.
+    pairs.sort_by_key(|p| std::cmp::Reverse(p.0));
+    let mut renamed: Vec<(CompareEntry, usize, u8, LineStats)> = pairs
+        .iter()
+        .map(|&(g, a, score, stats)| (gone.remove(g), a, score, stats))
+        .collect();
+    renamed.sort_by_key(|r| std::cmp::Reverse(r.1));
+    for (from, a, score, stats) in renamed {
+        rest.push(rename(from, added.remove(a), score, stats));
+    }
+
+    rest.append(&mut gone);
+    rest.append(&mut added);
+    rest.sort_by(|a, b| a.rel_path.cmp(&b.rel_path));
+    rest
+}
+
+/// Index pairs (gone, added) of files with the same hash, each used at most once.
+fn exact_pairs(gone: &[CompareEntry], added: &[CompareEntry]) -> Vec<Pair> {
+    let mut by_hash: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, entry) in added.iter().enumerate() {
+        if let Some(hash) = &entry.right_hash {
+            by_hash.entry(hash).or_default().push(i);
+        }
+    }
+
+    let mut pairs = Vec::new();
+    for (g, entry) in gone.iter().enumerate() {
+        let Some(candidates) = entry.left_hash.as_deref().and_then(|h| by_hash.get_mut(h)) else {
+            continue;
+        };
+        if candidates.is_empty() {
+            continue;
+        }
+        let pick = candidates
+            .iter()
+            .position(|&a| same_name(entry, &added[a]))
+            .unwrap_or(0);
+        pairs.push((g, candidates.remove(pick), 100, LineStats::default()));
+    }
+    pairs
+}
+
+/// Best-first pairs of text files scoring at least `threshold`, skipping those already paired.
+/// Only files with a counterpart of a size that could reach `threshold` are read.
+fn similar_pairs(
+    gone: &[CompareEntry],
+    added: &[CompareEntry],
+    taken: &[Pair],
+    threshold: u8,
+) -> Vec<Pair> {
+    // Unpaired text files and their sizes.
+    let unpaired = |entries: &[CompareEntry], used: HashSet<usize>, size: fn(&CompareEntry) -> Option<u64>| {
+        (0..entries.len())
+            .filter(|i| entries[*i].kind == EntryKind::Text && !used.contains(i))
+            .filter_map(|i| Some((i, size(&entries[i])?)))
+            .collect::<Vec<_>>()
+    };
+    let gone_sizes = unpaired(gone, taken.iter().map(|p| p.0).collect(), |e| e.left_size);
+    let added_sizes = unpaired(added, taken.iter().map(|p| p.1).collect(), |e| e.right_size);
+
+    let load = |entries: &[CompareEntry],
+                sizes: &[(usize, u64)],
+                others: &[(usize, u64)],
+                path: fn(&CompareEntry) -> &str| {
+        let mut others: Vec<u64> = others.iter().map(|&(_, size)| size).collect();
+        others.sort_unstable();
+        sizes
+            .par_iter()
+            .filter(|&&(_, size)| reachable(size, &others, threshold))
+            .filter_map(|&(i, _)| Some((i, fs::read_to_string(Path::new(path(&entries[i]))).ok()?)))
+            .collect::<Vec<_>>()
+    };
+    let gone_text = load(gone, &gone_sizes, &added_sizes, |e| &e.left_path);
+    let added_text = load(added, &added_sizes, &gone_sizes, |e| &e.right_path);
+
+    let mut candidates: Vec<(u8, bool, usize, usize, LineStats)> = gone_text
+        .par_iter()
+        .flat_map_iter(|(g, old)| {
+            added_text.iter().filter_map(move |(a, new)| {
+                // Files of very different sizes cannot reach the threshold; skip the diff.
+                if !close_in_size(old.len() as u64, new.len() as u64, threshold) {
+                    return None;
+                }
+                let (stats, score) = line_diff(old, new);
+                (score >= threshold)
+                    .then(|| (score, same_name(&gone[*g], &added[*a]), *g, *a, stats))
+            })
+        })
+        .collect();
+    // Highest score first, then same file name, then path order for a stable result.
+    candidates.sort_by(|x, y| {
+        (y.0, y.1).cmp(&(x.0, x.1)).then((x.2, x.3).cmp(&(y.2, y.3)))
+    });
+
+    let (mut used_gone, mut used_added) = (HashSet::new(), HashSet::new());
+    let mut pairs = Vec::new();
+    for (score, _, g, a, stats) in candidates {
+        if used_gone.contains(&g) || used_added.contains(&a) {
+            continue;
+        }
+        used_gone.insert(g);
+        used_added.insert(a);
+        pairs.push((g, a, score, stats));
+    }
+    pairs
+}
+
+/// Whether files of sizes `a` and `b` could still be `threshold`% similar.
+fn close_in_size(a: u64, b: u64, threshold: u8) -> bool {
+    a.min(b) * 100 >= a.max(b) * threshold as u64
+}
+
+/// Whether any of the `others`, sorted, is close enough in size to `size`. The smallest one
+/// not too small is the closest candidate.
+fn reachable(size: u64, others: &[u64], threshold: u8) -> bool {
+    let first = others.partition_point(|&other| other * 100 < size * threshold as u64);
+    others.get(first).is_some_and(|&other| close_in_size(size, other, threshold))
+}
+
+fn same_name(from: &CompareEntry, to: &CompareEntry) -> bool {
+    Path::new(&from.rel_path).file_name() == Path::new(&to.rel_path).file_name()
+}
+
+fn rename(from: CompareEntry, to: CompareEntry, similarity: u8, stats: LineStats) -> CompareEntry {
+    CompareEntry {
+        status: EntryStatus::Renamed,
+        kind: from.kind.max(to.kind),
+        left_path: from.left_path,
+        left_size: from.left_size,
+        left_hash: from.left_hash,
+        left_modified: from.left_modified,
+        renamed_from: Some(from.rel_path),
+        similarity: Some(similarity),
+        line_stats: (from.kind == EntryKind::Text && to.kind == EntryKind::Text).then_some(stats),
+        ..to
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(rel_path: &str, status: EntryStatus, hash: &str) -> CompareEntry {
+        let left = status == EntryStatus::OnlyLeft;
+        CompareEntry {
+            rel_path: rel_path.to_string(),
+            status,
+            kind: EntryKind::Text,
+            left_path: if left { format!("/l/{}", rel_path) } else { String::new() },
+            right_path: if left { String::new() } else { format!("/r/{}", rel_path) },
+            left_size: left.then_some(1),
+            right_size: (!left).then_some(1),
+            left_hash: left.then(|| hash.to_string()),
+            right_hash: (!left).then(|| hash.to_string()),
+            left_modified: None,
+            right_modified: None,
+            binary_diff: None,
+            renamed_from: None,
+            similarity: None,
+            line_stats: None,
+        }
+    }
+
+    #[test]
+    fn identical_hashes_pair_as_renames() {
+        let entries = vec![
+            entry("src/a/foo.rs", EntryStatus::OnlyLeft, "h1"),
+            entry("src/b/foo.rs", EntryStatus::OnlyRight, "h1"),
+            entry("old.txt", EntryStatus::OnlyLeft, "h2"),
+            entry("new.txt", EntryStatus::OnlyRight, "h3"),
+        ];
+        let result = detect_renames(entries, 100);
+
+        let paths: Vec<_> = result.iter().map(|e| (e.rel_path.as_str(), e.status)).collect();
+        assert_eq!(
+            paths,
+            vec![
+                ("new.txt", EntryStatus::OnlyRight),
+                ("old.txt", EntryStatus::OnlyLeft),
+                ("src/b/foo.rs", EntryStatus::Renamed),
+            ]
+        );
+        let renamed = &result[2];
+        assert_eq!(renamed.renamed_from.as_deref(), Some("src/a/foo.rs"));
+        assert_eq!(renamed.similarity, Some(100));
+        assert_eq!(renamed.left_path, "/l/src/a/foo.rs");
+        assert_eq!(renamed.right_path, "/r/src/b/foo.rs");
+        assert_eq!(renamed.left_hash, renamed.right_hash);
+    }
+
+    #[test]
+    fn duplicate_contents_prefer_same_file_name() {
+        let entries = vec![
+            entry("a/x.txt", EntryStatus::OnlyLeft, "h"),
+            entry("b/other.txt", EntryStatus::OnlyRight, "h"),
+            entry("b/x.txt", EntryStatus::OnlyRight, "h"),
+        ];
+        let result = detect_renames(entries, 100);
+        let renamed = result.iter().find(|e| e.status == EntryStatus::Renamed).unwrap();
+        assert_eq!(renamed.rel_path, "b/x.txt");
+        assert_eq!(result[0].status, EntryStatus::OnlyRight);
+    }
+
+    #[test]
+    fn similar_files_pair_above_threshold() {
+        let (left, right) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let text: String = (0..10).map(|i| format!("line {}\n", i)).collect();
+        fs::write(left.path().join("a.txt"), &text).unwrap();
+        fs::write(right.path().join("b.txt"), text.replace("line 9", "line nine")).unwrap();
+        fs::write(right.path().join("c.txt"), "something else entirely\n").unwrap();
+
+        let side = |dir: &tempfile::TempDir, name: &str, status| {
+            let mut e = entry(name, status, name);
+            let path = dir.path().join(name);
+            let size = fs::metadata(&path).ok().map(|m| m.len());
+            let path = path.to_string_lossy().into_owned();
+            if status == EntryStatus::OnlyLeft {
+                (e.left_path, e.left_size) = (path, size);
+            } else {
+                (e.right_path, e.right_size) = (path, size);
+            }
+            e
+        };
+        let entries = || {
+            vec![
+                side(&left, "a.txt", EntryStatus::OnlyLeft),
+                side(&right, "b.txt", EntryStatus::OnlyRight),
+                side(&right, "c.txt", EntryStatus::OnlyRight),
+            ]
+        };
+
+        let result = detect_renames(entries(), 50);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].status, EntryStatus::Renamed);
+        assert_eq!(result[0].renamed_from.as_deref(), Some("a.txt"));
+        assert_eq!(result[0].similarity, Some(90));
+        assert_eq!(result[0].line_stats.map(|s| s.changed), Some(1));
+        assert_eq!(result[1].status, EntryStatus::OnlyRight);
+
+        let result = detect_renames(entries(), 95);
+        assert!(result.iter().all(|e| e.status != EntryStatus::Renamed));
+    }
+
+    #[test]
+    fn only_files_close_in_size_are_candidates() {
+        let others = [10, 40, 200];
+        assert!(reachable(50, &others, 80));
+        assert!(reachable(160, &others, 80));
+        assert!(!reachable(100, &others, 80));
+        assert!(!reachable(5, &others, 80));
+        assert!(reachable(1000, &others, 0));
+        assert!(!reachable(1, &[], 0));
+    }
+}
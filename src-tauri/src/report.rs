@@ -0,0 +1,513 @@
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::error::DivergeError;
+use crate::models::{CompareResult, EntryKind, ThreeWayResult};
+use crate::stats::LineStats;
+use crate::sync::{SyncDirection, SyncPlan};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One line per file followed by a summary
+    Text,
+    /// A single JSON document with the summary and all entries
+    Json,
+    /// One JSON object per line, summary last
+    #[value(alias = "json-lines")]
+    Jsonl,
+}
+
+#[derive(Debug, Serialize)]
+struct Summary<'a> {
+    total: usize,
+    identical: usize,
+    different: usize,
+    only_left: usize,
+    only_right: usize,
+    renamed: usize,
+    too_large: usize,
+    lines: LineStats,
+    ignored_dirs: &'a [String],
+}
+
+#[derive(Debug, Serialize)]
+struct ThreeWaySummary<'a> {
+    total: usize,
+    unchanged: usize,
+    changed_ours: usize,
+    changed_theirs: usize,
+    changed_both_identically: usize,
+    conflicting: usize,
+    mergeable: usize,
+    ignored_dirs: &'a [String],
+}
+
+#[derive(Debug, Serialize)]
+struct SyncPlanSummary<'a> {
+    left: &'a str,
+    right: &'a str,
+    direction: SyncDirection,
+    copies: usize,
+    overwrites: usize,
+    deletes: usize,
+    conflicts: usize,
+    bytes_written: u64,
+    bytes_deleted: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct Report<'a, S, E> {
+    summary: S,
+    entries: &'a [E],
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Line<'a, S, E> {
+    Entry(&'a E),
+    Summary(S),
+}
+
+fn summary(result: &CompareResult) -> Summary<'_> {
+    Summary {
+        total: result.total,
+        identical: result.identical,
+        different: result.different,
+        only_left: result.only_left,
+        only_right: result.only_right,
+        renamed: result.renamed,
+        too_large: result.too_large,
+        lines: result.line_totals,
+        ignored_dirs: &result.ignored_dirs,
+    }
+}
+
+/// Whether the two trees differ, i.e. anything other than identical files was found.
+pub fn has_differences(result: &CompareResult) -> bool {
+    result.different + result.only_left + result.only_right + result.renamed > 0
+}
+
+/// Exit code in the style of `diff -r`: 0 when identical, 1 when the trees differ.
+pub fn exit_code(result: &CompareResult) -> i32 {
+    if has_differences(result) {
+        1
+    } else {
+        0
+    }
+}
+
+pub fn render(result: &CompareResult, format: OutputFormat) -> Result<String, DivergeError> {
+    match format {
+        OutputFormat::Text => Ok(render_text(result)),
+        OutputFormat::Json => render_json(summary(result), &result.entries),
+        OutputFormat::Jsonl => render_jsonl(summary(result), &result.entries),
+    }
+}
+
+pub fn render_three_way(result: &ThreeWayResult, format: OutputFormat) -> Result<String, DivergeError> {
+    let summary = ThreeWaySummary {
+        total: result.total,
+        unchanged: result.unchanged,
+        changed_ours: result.changed_ours,
+        changed_theirs: result.changed_theirs,
+        changed_both_identically: result.changed_both_identically,
+        conflicting: result.conflicting,
+        mergeable: result.mergeable,
+        ignored_dirs: &result.ignored_dirs,
+    };
+    match format {
+        OutputFormat::Text => Ok(render_three_way_text(result)),
+        OutputFormat::Json => render_json(summary, &result.entries),
+        OutputFormat::Jsonl => render_jsonl(summary, &result.entries),
+    }
+}
+
+/// The plan as text, or its operations with a summary in the JSON formats.
+pub fn render_sync_plan(plan: &SyncPlan, format: OutputFormat) -> Result<String, DivergeError> {
+    let summary = SyncPlanSummary {
+        left: &plan.left,
+        right: &plan.right,
+        direction: plan.direction,
+        copies: plan.copies,
+        overwrites: plan.overwrites,
+        deletes: plan.deletes,
+        conflicts: plan.conflicts,
+        bytes_written: plan.bytes_written,
+        bytes_deleted: plan.bytes_deleted,
+    };
+    match format {
+        OutputFormat::Text => Ok(render_sync_plan_text(plan)),
+        OutputFormat::Json => render_json(summary, &plan.operations),
+        OutputFormat::Jsonl => render_jsonl(summary, &plan.operations),
+    }
+}
+
+fn render_text(result: &CompareResult) -> String {
+    let mut out = String::new();
+    for entry in &result.entries {
+        out.push_str(&format!("{:<10}  {}", entry.status, entry.rel_path));
+        if let (Some(from), Some(similarity)) = (&entry.renamed_from, entry.similarity) {
+            out.push_str(&format!(" (from {}, {}%)", from, similarity));
+        }
+        match (entry.kind, &entry.binary_diff) {
+            (EntryKind::Binary, Some(d)) => {
+                out.push_str(&format!(" (binary, first difference at 0x{:x})", d.offset))
+            }
+            (EntryKind::Binary, None) => out.push_str(" (binary)"),
+            (EntryKind::TooLarge, _) => out.push_str(" (too large to diff)"),
+            (EntryKind::Text, _) => {}
+        }
+        if let Some(stats) = entry.line_stats.filter(|s| *s != LineStats::default()) {
+            out.push_str(&format!(" {}", format_lines(stats)));
+        }
+        out.push('\n');
+    }
+    if !result.entries.is_empty() {
+        out.push('\n');
+    }
+    out.push_str(&format!(
+        "{} files: {} identical, {} different, {} only in left, {} only in right\n",
+        result.total, result.identical, result.different, result.only_left, result.only_right
+    ));
+    if result.line_totals != LineStats::default() {
+        out.push_str(&format!("Lines: {}\n", format_lines(result.line_totals)));
+    }
+    if result.renamed > 0 {
+        out.push_str(&format!("Renamed: {}\n", result.renamed));
+    }
+    if result.too_large > 0 {
+        out.push_str(&format!("Too large to diff (compared by hash): {}\n", result.too_large));
+    }
+    if !result.ignored_dirs.is_empty() {
+        out.push_str(&format!("Ignored: {}\n", result.ignored_dirs.join(", ")));
+    }
+    out
+}
+
+fn render_three_way_text(result: &ThreeWayResult) -> String {
+    let mut out = String::new();
+    for entry in &result.entries {
+        out.push_str(&format!("{:<24}  {}", entry.status, entry.rel_path));
+        match entry.kind {
+            _ if entry.mergeable => out.push_str(" (mergeable)"),
+            EntryKind::Binary => out.push_str(" (binary)"),
+            EntryKind::TooLarge => out.push_str(" (too large to diff)"),
+            EntryKind::Text => {}
+        }
+        out.push('\n');
+    }
+    if !result.entries.is_empty() {
+        out.push('\n');
+    }
+    out.push_str(&format!(
+        "{} files: {} unchanged, {} changed in ours, {} changed in theirs, {} changed identically, {} conflicting ({} mergeable)\n",
+        result.total,
+        result.unchanged,
+        result.changed_ours,
+        result.changed_theirs,
+        result.changed_both_identically,
+        result.conflicting,
+        result.mergeable
+    ));
+    if !result.ignored_dirs.is_empty() {
+        out.push_str(&format!("Ignored: {}\n", result.ignored_dirs.join(", ")));
+    }
+    out
+}
+
+fn render_sync_plan_text(plan: &SyncPlan) -> String {
+    let mut out = String::new();
+    for op in &plan.operations {
+        out.push_str(&format!("{:<9}  {} ({})", op.kind.as_str(), op.rel_path, format_size(op.size)));
+        if let Some(conflict) = &op.conflict {
+            out.push_str(&format!(" conflict: {}", conflict));
+        }
+        out.push('\n');
+    }
+    if !plan.operations.is_empty() {
+        out.push('\n');
+    }
+    out.push_str(&format!(
+        "{} copies, {} overwrites, {} deletes: {} to write, {} to delete\n",
+        plan.copies,
+        plan.overwrites,
+        plan.deletes,
+        format_size(plan.bytes_written),
+        format_size(plan.bytes_deleted)
+    ));
+    if !plan.is_valid() {
+        out.push_str(&format!(
+            "Conflicts: {} files changed since the comparison; compare again before syncing\n",
+            plan.conflicts
+        ));
+    }
+    out
+}
+
+/// Bytes in binary units: `512 B`, `1.5 KiB`, `3.0 MiB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// `+added -removed ~changed`, like a compact `diff --stat`.
+fn format_lines(stats: LineStats) -> String {
+    format!("+{} -{} ~{}", stats.added, stats.removed, stats.changed)
+}
+
+fn serialize_error(e: serde_json::Error) -> DivergeError {
+    DivergeError::Io {
+        path: None,
+        message: format!("Failed to serialize result: {}", e),
+    }
+}
+
+fn render_json<S: Serialize, E: Serialize>(summary: S, entries: &[E]) -> Result<String, DivergeError> {
+    let report = Report { summary, entries };
+    let mut json = serde_json::to_string_pretty(&report).map_err(serialize_error)?;
+    json.push('\n');
+    Ok(json)
+}
+
+fn render_jsonl<S: Serialize, E: Serialize>(summary: S, entries: &[E]) -> Result<String, DivergeError> {
+    let mut out = String::new();
+    let lines = entries
+        .iter()
+        .map(Line::Entry)
+        .chain(std::iter::once(Line::Summary(summary)));
+    for line in lines {
+        let json = serde_json::to_string(&line).map_err(serialize_error)?;
+        out.push_str(&json);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CompareEntry, EntryStatus, ThreeWayEntry};
+
+    fn entry(rel_path: &str, status: EntryStatus) -> CompareEntry {
+        CompareEntry {
+            rel_path: rel_path.to_string(),
+            status,
+            kind: EntryKind::Text,
+            left_path: format!("/l/{}", rel_path),
+            right_path: format!("/r/{}", rel_path),
+            left_size: Some(3),
+            right_size: Some(4),
+            left_hash: Some("abc".to_string()),
+            right_hash: None,
+            left_modified: None,
+            right_modified: None,
+            binary_diff: None,
+            renamed_from: None,
+            similarity: None,
+            line_stats: None,
+        }
+    }
+
+    fn sample() -> CompareResult {
+        CompareResult {
+            entries: vec![
+                entry("a.txt", EntryStatus::Identical),
+                entry("b.txt", EntryStatus::Different),
+            ],
+            total: 2,
+            identical: 1,
+            different: 1,
+            only_left: 0,
+            only_right: 0,
+            renamed: 0,
+            too_large: 0,
+            line_totals: Default::default(),
+            ignored_dirs: vec!["node_modules".to_string()],
+        }
+    }
+
+    #[test]
+    fn exit_code_reflects_differences() {
+        let mut result = sample();
+        assert_eq!(exit_code(&result), 1);
+        result.different = 0;
+        assert_eq!(exit_code(&result), 0);
+        result.only_right = 1;
+        assert_eq!(exit_code(&result), 1);
+    }
+
+    #[test]
+    fn text_lists_entries_and_summary() {
+        let out = render(&sample(), OutputFormat::Text).unwrap();
+        assert!(out.contains("identical   a.txt"));
+        assert!(out.contains("different   b.txt"));
+        assert!(out.contains("2 files: 1 identical, 1 different"));
+        assert!(out.contains("Ignored: node_modules"));
+    }
+
+    #[test]
+    fn text_marks_binary_entries() {
+        let mut result = sample();
+        result.entries[1].kind = EntryKind::Binary;
+        result.entries[1].binary_diff = Some(crate::models::BinaryDiff {
+            offset: 255,
+            left_bytes: "00".to_string(),
+            right_bytes: "01".to_string(),
+        });
+
+        let out = render(&result, OutputFormat::Text).unwrap();
+        assert!(out.contains("b.txt (binary, first difference at 0xff)"));
+    }
+
+    #[test]
+    fn text_marks_too_large_entries() {
+        let mut result = sample();
+        result.entries[1].kind = EntryKind::TooLarge;
+        result.too_large = 1;
+
+        let out = render(&result, OutputFormat::Text).unwrap();
+        assert!(out.contains("b.txt (too large to diff)"));
+        assert!(out.contains("Too large to diff (compared by hash): 1"));
+    }
+
+    #[test]
+    fn text_shows_line_stats() {
+        let mut result = sample();
+        let stats = LineStats {
+            added: 120,
+            removed: 34,
+            changed: 2,
+        };
+        result.entries[1].line_stats = Some(stats);
+        result.line_totals = stats;
+
+        let out = render(&result, OutputFormat::Text).unwrap();
+        assert!(out.contains("different   b.txt +120 -34 ~2\n"));
+        assert!(out.contains("Lines: +120 -34 ~2"));
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&result, OutputFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["summary"]["lines"]["added"], 120);
+        assert_eq!(json["entries"][1]["line_stats"]["removed"], 34);
+    }
+
+    #[test]
+    fn text_shows_rename_source() {
+        let mut result = sample();
+        result.entries[1].status = EntryStatus::Renamed;
+        result.entries[1].renamed_from = Some("old/b.txt".to_string());
+        result.entries[1].similarity = Some(87);
+        result.different = 0;
+        result.renamed = 1;
+
+        let out = render(&result, OutputFormat::Text).unwrap();
+        assert!(out.contains("renamed     b.txt (from old/b.txt, 87%)"));
+        assert!(out.contains("Renamed: 1"));
+        assert_eq!(exit_code(&result), 1);
+    }
+
+    #[test]
+    fn json_has_summary_and_entries() {
+        let out = render(&sample(), OutputFormat::Json).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(parsed["summary"]["different"], 1);
+        assert_eq!(parsed["entries"][1]["status"], "different");
+        assert_eq!(parsed["entries"][1]["right_size"], 4);
+    }
+
+    #[test]
+    fn jsonl_has_one_line_per_entry_plus_summary() {
+        let out = render(&sample(), OutputFormat::Jsonl).unwrap();
+        let lines: Vec<serde_json::Value> = out
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["type"], "entry");
+        assert_eq!(lines[0]["rel_path"], "a.txt");
+        assert_eq!(lines[2]["type"], "summary");
+        assert_eq!(lines[2]["total"], 2);
+    }
+
+    #[test]
+    fn three_way_text_marks_mergeable_conflicts() {
+        use crate::models::ThreeWayStatus;
+        let entry = |rel_path: &str, status, mergeable| ThreeWayEntry {
+            rel_path: rel_path.to_string(),
+            status,
+            kind: EntryKind::Text,
+            base_hash: Some("b".to_string()),
+            ours_hash: Some("o".to_string()),
+            theirs_hash: Some("t".to_string()),
+            mergeable,
+        };
+        let result = ThreeWayResult {
+            entries: vec![
+                entry("a.txt", ThreeWayStatus::ChangedTheirs, false),
+                entry("b.txt", ThreeWayStatus::Conflicting, true),
+            ],
+            total: 2,
+            changed_theirs: 1,
+            conflicting: 1,
+            mergeable: 1,
+            ..Default::default()
+        };
+
+        let out = render_three_way(&result, OutputFormat::Text).unwrap();
+        assert!(out.contains("changed_theirs            a.txt\n"));
+        assert!(out.contains("conflicting               b.txt (mergeable)\n"));
+        assert!(out.contains("1 changed in theirs, 0 changed identically, 1 conflicting (1 mergeable)"));
+        let json: serde_json::Value =
+            serde_json::from_str(&render_three_way(&result, OutputFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["summary"]["mergeable"], 1);
+        assert_eq!(json["entries"][1]["status"], "conflicting");
+    }
+
+    #[test]
+    fn sync_plan_text_lists_operations_with_sizes() {
+        use crate::sync::{OperationKind, PlannedOperation};
+
+        let op = |rel_path: &str, kind, size, conflict: Option<&str>| PlannedOperation {
+            rel_path: rel_path.to_string(),
+            kind,
+            size,
+            target: None,
+            conflict: conflict.map(str::to_string),
+        };
+        let plan = SyncPlan {
+            left: "/l".to_string(),
+            right: "/r".to_string(),
+            direction: SyncDirection::LeftToRight,
+            operations: vec![
+                op("a.txt", OperationKind::Copy, 1536, None),
+                op("b.txt", OperationKind::Overwrite, 10, Some("deleted since the comparison")),
+                op("c.txt", OperationKind::Delete, 3 << 20, None),
+            ],
+            copies: 1,
+            overwrites: 1,
+            deletes: 1,
+            conflicts: 1,
+            bytes_written: 1546,
+            bytes_deleted: 3 << 20,
+        };
+
+        let out = render_sync_plan(&plan, OutputFormat::Text).unwrap();
+        assert!(out.contains("copy       a.txt (1.5 KiB)\n"));
+        assert!(out.contains("overwrite  b.txt (10 B) conflict: deleted since the comparison\n"));
+        assert!(out.contains("delete     c.txt (3.0 MiB)\n"));
+        assert!(out.contains("1 copies, 1 overwrites, 1 deletes: 1.5 KiB to write, 3.0 MiB to delete"));
+        assert!(out.contains("Conflicts: 1 files changed"));
+        let json: serde_json::Value =
+            serde_json::from_str(&render_sync_plan(&plan, OutputFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["summary"]["direction"], "left_to_right");
+        assert_eq!(json["entries"][2]["kind"], "delete");
+    }
+}
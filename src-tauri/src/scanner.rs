@@ -1,30 +1,248 @@
-use std::collections::BTreeMap;
-use std::fs;
-use std::path::Path;
+use ignore::gitignore::{Glob, Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 use walkdir::WalkDir;
 
-const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+use crate::error::DivergeError;
 
-pub fn scan_dir(root: &str, ignore_dirs: &[String]) -> (BTreeMap<String, (String, String)>, Vec<String>) {
+/// A file found during the scan. Only metadata is kept; contents are read on demand.
+#[derive(Debug, Clone)]
+pub struct ScannedFile {
+    pub abs_path: String,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl ScannedFile {
+    /// Modification time in milliseconds since the Unix epoch.
+    pub fn modified_millis(&self) -> Option<u64> {
+        epoch_millis(self.modified?)
+    }
+}
+
+pub(crate) fn epoch_millis(time: SystemTime) -> Option<u64> {
+    let since = time.duration_since(SystemTime::UNIX_EPOCH).ok()?;
+    u64::try_from(since.as_millis()).ok()
+}
+
+/// Checks that every ignore pattern is valid `.gitignore` syntax.
+pub fn check_patterns(patterns: &[String]) -> Result<(), DivergeError> {
+    let mut builder = GitignoreBuilder::new("");
+    for pattern in patterns {
+        builder.add_line(None, pattern).map_err(|e| {
+            DivergeError::config(format!("Invalid ignore pattern '{}': {}", pattern, e))
+        })?;
+    }
+    Ok(())
+}
+
+/// Builds a matcher for `patterns` rooted at `root`, with the same semantics as a
+/// `.gitignore` file placed there. Invalid patterns are skipped.
+fn matcher(root: &Path, patterns: &[String]) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(root);
+    for pattern in patterns {
+        let _ = builder.add_line(None, pattern);
+    }
+    builder.build().unwrap_or_else(|_| Gitignore::empty())
+}
+
+/// Project-local ignore file read from the root of either compared directory.
+pub const DIVERGE_IGNORE: &str = ".divergeignore";
+
+/// Per-directory ignore files honored when `IgnoreFiles::vcs` is set, highest precedence first.
+const VCS_IGNORE_FILES: [&str; 2] = [".ignore", ".gitignore"];
+
+/// Ignore files consulted on top of the configured patterns.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreFiles {
+    /// Honor `.ignore`/`.gitignore` files, `.git/info/exclude` and the global git excludes file.
+    pub vcs: bool,
+    /// Gitignore-style files applied from the scan root, e.g. the `.divergeignore` of both sides.
+    pub extra: Vec<PathBuf>,
+}
+
+enum Exclusion {
+    Keep,
+    /// Excluded by a configured pattern.
+    Config,
+    /// Excluded by a rule read from an ignore file.
+    File { source: PathBuf, pattern: String },
+}
+
+fn decide(m: Match<&Glob>) -> Option<Exclusion> {
+    match m {
+        Match::None => None,
+        Match::Whitelist(_) => Some(Exclusion::Keep),
+        Match::Ignore(glob) => Some(Exclusion::File {
+            source: glob.from().map(Path::to_path_buf).unwrap_or_default(),
+            pattern: glob.original().to_string(),
+        }),
+    }
+}
+
+/// Every ignore rule that applies to one scan, evaluated with `.gitignore` precedence:
+/// configured patterns, then `extra` files, then the nearest directory's ignore files
+/// outwards, then `.git/info/exclude` and finally the global excludes.
+struct Rules {
+    root: PathBuf,
+    config: Gitignore,
+    extra: Gitignore,
+    vcs: bool,
+    /// Lazily loaded ignore files of each directory inside the scanned tree.
+    per_dir: HashMap<PathBuf, Vec<Gitignore>>,
+    /// Canonical scan root, and the ignore files of its ancestors up to the repository root.
+    canonical_root: PathBuf,
+    outer: Vec<Gitignore>,
+    /// `.git/info/exclude` and the global excludes, matched relative to `repo_root`.
+    repo_root: PathBuf,
+    git: Vec<Gitignore>,
+}
+
+impl Rules {
+    fn new(root: &Path, patterns: &[String], files: &IgnoreFiles) -> Self {
+        let mut extra = GitignoreBuilder::new(root);
+        for path in files.extra.iter().filter(|p| p.is_file()) {
+            let _ = extra.add(path);
+        }
+
+        let canonical_root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+        let mut rules = Rules {
+            root: root.to_path_buf(),
+            config: matcher(root, patterns),
+            extra: extra.build().unwrap_or_else(|_| Gitignore::empty()),
+            vcs: files.vcs,
+            per_dir: HashMap::new(),
+            repo_root: canonical_root.clone(),
+            canonical_root,
+            outer: Vec::new(),
+            git: Vec::new(),
+        };
+        if rules.vcs {
+            rules.load_git_rules();
+        }
+        rules
+    }
+
+    fn load_git_rules(&mut self) {
+        if let Some(repo) = self.canonical_root.ancestors().find(|d| d.join(".git").exists()) {
+            for dir in self.canonical_root.ancestors().skip(1) {
+                if !dir.starts_with(repo) {
+                    break;
+                }
+                self.outer.extend(load_dir(dir));
+            }
+            let (exclude, _) = Gitignore::new(repo.join(".git/info/exclude"));
+            self.git.push(exclude);
+            self.repo_root = repo.to_path_buf();
+        }
+        let (global, _) = Gitignore::global();
+        self.git.push(global);
+    }
+
+    fn check(&mut self, path: &Path, is_dir: bool) -> Exclusion {
+        match self.config.matched(path, is_dir) {
+            Match::Ignore(_) => return Exclusion::Config,
+            Match::Whitelist(_) => return Exclusion::Keep,
+            Match::None => {}
+        }
+        if let Some(found) = decide(self.extra.matched(path, is_dir)) {
+            return found;
+        }
+        if !self.vcs {
+            return Exclusion::Keep;
+        }
+
+        let rel = path.strip_prefix(&self.root).unwrap_or(path);
+        for dir in path.ancestors().skip(1) {
+            if !dir.starts_with(&self.root) {
+                break;
+            }
+            let matchers = self.per_dir.entry(dir.to_path_buf()).or_insert_with(|| load_dir(dir));
+            if let Some(found) = matchers.iter().find_map(|m| decide(m.matched(path, is_dir))) {
+                return found;
+            }
+        }
+
+        let canonical = self.canonical_root.join(rel);
+        if let Some(found) = self.outer.iter().find_map(|m| decide(m.matched(&canonical, is_dir))) {
+            return found;
+        }
+        let from_repo = canonical.strip_prefix(&self.repo_root).unwrap_or(rel);
+        self.git
+            .iter()
+            .find_map(|m| decide(m.matched(from_repo, is_dir)))
+            .unwrap_or(Exclusion::Keep)
+    }
+
+    /// Where a rule came from, relative to the scan root when the file lives inside it.
+    fn describe(&self, source: &Path, pattern: &str) -> String {
+        let source = source
+            .strip_prefix(&self.root)
+            .or_else(|_| source.strip_prefix(&self.canonical_root))
+            .unwrap_or(source);
+        format!("{}: {}", source.display(), pattern)
+    }
+}
+
+fn load_dir(dir: &Path) -> Vec<Gitignore> {
+    VCS_IGNORE_FILES
+        .iter()
+        .map(|name| dir.join(name))
+        .filter(|path| path.is_file())
+        .map(|path| Gitignore::new(path).0)
+        .collect()
+}
+
+/// Walks `root`, skipping anything matched by the gitignore-style `ignore_patterns` or by
+/// the rules in `ignore_files`. Returns the files found and what was ignored, relative to
+/// `root`: each ignored directory, followed by the ignore-file rule that excluded it, and
+/// for rules that excluded files, the rule and how many files it matched.
+///
+/// `on_file` is called with every file found; returning false stops the walk early.
+pub fn scan_dir(
+    root: &str,
+    ignore_patterns: &[String],
+    ignore_files: &IgnoreFiles,
+    on_file: &(dyn Fn(&Path) -> bool + Sync),
+) -> (BTreeMap<String, ScannedFile>, Vec<String>) {
     let mut files = BTreeMap::new();
     let base = Path::new(root);
     if !base.exists() {
         return (files, vec![]);
     }
 
+    let mut rules = Rules::new(base, ignore_patterns, ignore_files);
     let mut ignored_found = Vec::new();
+    let mut ignored_files: BTreeMap<String, usize> = BTreeMap::new();
 
     for entry in WalkDir::new(base)
         .into_iter()
         .filter_entry(|e| {
-            let name = e.file_name().to_string_lossy();
-            let is_ignored = ignore_dirs.iter().any(|skip| skip == name.as_ref());
-            if is_ignored && e.file_type().is_dir() {
-                if let Ok(rel) = e.path().strip_prefix(base) {
-                    ignored_found.push(rel.to_string_lossy().to_string());
+            if e.depth() == 0 {
+                return true;
+            }
+            let is_dir = e.file_type().is_dir();
+            let rule = match rules.check(e.path(), is_dir) {
+                Exclusion::Keep => return true,
+                Exclusion::Config => None,
+                Exclusion::File { source, pattern } => Some(rules.describe(&source, &pattern)),
+            };
+            match (is_dir, rule) {
+                (true, rule) => {
+                    if let Ok(rel) = e.path().strip_prefix(base) {
+                        let rel = rel.to_string_lossy();
+                        ignored_found.push(match rule {
+                            Some(rule) => format!("{} ({})", rel, rule),
+                            None => rel.to_string(),
+                        });
+                    }
                 }
+                (false, Some(rule)) => *ignored_files.entry(rule).or_default() += 1,
+                (false, None) => {}
             }
-            !is_ignored
+            false
         })
         .filter_map(|e| e.ok())
     {
@@ -32,22 +250,24 @@ pub fn scan_dir(root: &str, ignore_dirs: &[String]) -> (BTreeMap<String, (String
             continue;
         }
 
-        if let Ok(meta) = entry.metadata() {
-            if meta.len() > MAX_FILE_SIZE {
-                continue;
-            }
-        }
+        let (size, modified) = match entry.metadata() {
+            Ok(meta) => (meta.len(), meta.modified().ok()),
+            Err(_) => continue,
+        };
 
         if let Ok(rel) = entry.path().strip_prefix(base) {
             let rel_str = rel.to_string_lossy().to_string();
-            let abs_str = entry.path().to_string_lossy().to_string();
-
-            if let Ok(content) = fs::read_to_string(entry.path()) {
-                files.insert(rel_str, (abs_str, content));
-            }
+            let abs_path = entry.path().to_string_lossy().to_string();
+            files.insert(rel_str, ScannedFile { abs_path, size, modified });
+        }
+        if !on_file(entry.path()) {
+            break;
         }
     }
 
+    ignored_found.extend(ignored_files.into_iter().map(|(rule, count)| {
+        format!("{} file{} ({})", count, if count == 1 { "" } else { "s" }, rule)
+    }));
     (files, ignored_found)
 }
 
@@ -59,7 +279,7 @@ mod tests {
     #[test]
     fn scan_empty_dir() {
         let dir = tempfile::tempdir().unwrap();
-        let (files, ignored) = scan_dir(dir.path().to_str().unwrap(), &[]);
+        let (files, ignored) = scan_dir(dir.path().to_str().unwrap(), &[], &IgnoreFiles::default(), &|_| true);
         assert!(files.is_empty());
         assert!(ignored.is_empty());
     }
@@ -70,11 +290,11 @@ mod tests {
         fs::write(dir.path().join("a.txt"), "aaa").unwrap();
         fs::write(dir.path().join("b.txt"), "bbb").unwrap();
 
-        let (files, _) = scan_dir(dir.path().to_str().unwrap(), &[]);
+        let (files, _) = scan_dir(dir.path().to_str().unwrap(), &[], &IgnoreFiles::default(), &|_| true);
         assert_eq!(files.len(), 2);
         assert!(files.contains_key("a.txt"));
         assert!(files.contains_key("b.txt"));
-        assert_eq!(files["a.txt"].1, "aaa");
+        assert_eq!(files["a.txt"].size, 3);
     }
 
     #[test]
@@ -83,7 +303,7 @@ mod tests {
         fs::create_dir_all(dir.path().join("sub/deep")).unwrap();
         fs::write(dir.path().join("sub/deep/file.txt"), "content").unwrap();
 
-        let (files, _) = scan_dir(dir.path().to_str().unwrap(), &[]);
+        let (files, _) = scan_dir(dir.path().to_str().unwrap(), &[], &IgnoreFiles::default(), &|_| true);
         assert_eq!(files.len(), 1);
         assert!(files.contains_key("sub/deep/file.txt"));
     }
@@ -96,7 +316,7 @@ mod tests {
         fs::write(dir.path().join("app.js"), "code").unwrap();
 
         let ignore = vec!["node_modules".to_string()];
-        let (files, ignored) = scan_dir(dir.path().to_str().unwrap(), &ignore);
+        let (files, ignored) = scan_dir(dir.path().to_str().unwrap(), &ignore, &IgnoreFiles::default(), &|_| true);
         assert_eq!(files.len(), 1);
         assert!(files.contains_key("app.js"));
         assert_eq!(ignored, vec!["node_modules"]);
@@ -110,7 +330,7 @@ mod tests {
         fs::write(dir.path().join("app.js"), "code").unwrap();
 
         let ignore = vec!["node_modules".to_string(), ".git".to_string()];
-        let (files, mut ignored) = scan_dir(dir.path().to_str().unwrap(), &ignore);
+        let (files, mut ignored) = scan_dir(dir.path().to_str().unwrap(), &ignore, &IgnoreFiles::default(), &|_| true);
         ignored.sort();
         assert_eq!(files.len(), 1);
         assert_eq!(ignored, vec![".git", "node_modules"]);
@@ -118,7 +338,7 @@ mod tests {
 
     #[test]
     fn scan_nonexistent_dir() {
-        let (files, ignored) = scan_dir("/nonexistent/path/xyz", &[]);
+        let (files, ignored) = scan_dir("/nonexistent/path/xyz", &[], &IgnoreFiles::default(), &|_| true);
         assert!(files.is_empty());
         assert!(ignored.is_empty());
     }
@@ -128,18 +348,174 @@ mod tests {
         let dir = tempfile::tempdir().unwrap();
         fs::write(dir.path().join("file.txt"), "content").unwrap();
 
-        let (files, _) = scan_dir(dir.path().to_str().unwrap(), &[]);
-        let (abs_path, _) = &files["file.txt"];
+        let (files, _) = scan_dir(dir.path().to_str().unwrap(), &[], &IgnoreFiles::default(), &|_| true);
+        let abs_path = &files["file.txt"].abs_path;
         assert!(abs_path.starts_with(dir.path().to_str().unwrap()));
     }
 
     #[test]
-    fn scan_reads_file_content() {
+    fn scan_records_size_without_reading_content() {
         let dir = tempfile::tempdir().unwrap();
         fs::write(dir.path().join("file.txt"), "hello world").unwrap();
+        fs::write(dir.path().join("data.bin"), [0xff, 0xfe, 0x00]).unwrap();
+
+        let (files, _) = scan_dir(dir.path().to_str().unwrap(), &[], &IgnoreFiles::default(), &|_| true);
+        assert_eq!(files["file.txt"].size, 11);
+        assert_eq!(files["data.bin"].size, 3);
+    }
+
+    #[test]
+    fn scan_keeps_large_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = fs::File::create(dir.path().join("dump.sql")).unwrap();
+        file.set_len(11 * 1024 * 1024).unwrap();
+
+        let (files, _) = scan_dir(dir.path().to_str().unwrap(), &[], &IgnoreFiles::default(), &|_| true);
+        assert_eq!(files["dump.sql"].size, 11 * 1024 * 1024);
+    }
+
+    fn write_all(root: &Path, paths: &[&str]) {
+        for path in paths {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "x").unwrap();
+        }
+    }
+
+    fn patterns(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn scan_ignores_file_globs() {
+        let dir = tempfile::tempdir().unwrap();
+        write_all(dir.path(), &["app.log", "logs/today.log", "app.js"]);
+
+        let (files, ignored) = scan_dir(dir.path().to_str().unwrap(), &patterns(&["*.log"]), &IgnoreFiles::default(), &|_| true);
+        assert_eq!(files.keys().collect::<Vec<_>>(), vec!["app.js"]);
+        assert!(ignored.is_empty());
+    }
+
+    #[test]
+    fn scan_ignores_double_star_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        write_all(dir.path(), &["build/a.map", "build/x/y/b.map", "build/x/b.js", "src/c.map"]);
+
+        let (files, _) = scan_dir(dir.path().to_str().unwrap(), &patterns(&["build/**/*.map"]), &IgnoreFiles::default(), &|_| true);
+        assert_eq!(files.keys().collect::<Vec<_>>(), vec!["build/x/b.js", "src/c.map"]);
+    }
+
+    #[test]
+    fn scan_honors_negation() {
+        let dir = tempfile::tempdir().unwrap();
+        write_all(dir.path(), &["a.me", "keep.me", "sub/keep.me"]);
+
+        let (files, _) = scan_dir(dir.path().to_str().unwrap(), &patterns(&["*.me", "!keep.me"]), &IgnoreFiles::default(), &|_| true);
+        assert_eq!(files.keys().collect::<Vec<_>>(), vec!["keep.me", "sub/keep.me"]);
+    }
+
+    #[test]
+    fn scan_anchored_patterns_match_from_root_only() {
+        let dir = tempfile::tempdir().unwrap();
+        write_all(dir.path(), &["docs/generated/api.md", "src/docs/generated/x.md"]);
+
+        let (files, ignored) =
+            scan_dir(dir.path().to_str().unwrap(), &patterns(&["/docs/generated"]), &IgnoreFiles::default(), &|_| true);
+        assert_eq!(files.keys().collect::<Vec<_>>(), vec!["src/docs/generated/x.md"]);
+        assert_eq!(ignored, vec!["docs/generated"]);
+    }
 
-        let (files, _) = scan_dir(dir.path().to_str().unwrap(), &[]);
-        assert_eq!(files["file.txt"].1, "hello world");
+    #[test]
+    fn scan_dir_only_patterns_keep_files() {
+        let dir = tempfile::tempdir().unwrap();
+        write_all(dir.path(), &["out/a.txt", "sub/out"]);
+
+        let (files, _) = scan_dir(dir.path().to_str().unwrap(), &patterns(&["out/"]), &IgnoreFiles::default(), &|_| true);
+        assert_eq!(files.keys().collect::<Vec<_>>(), vec!["sub/out"]);
+    }
+
+    #[test]
+    fn check_patterns_rejects_invalid_globs() {
+        assert!(check_patterns(&patterns(&["*.log", "!keep.me", "/docs"])).is_ok());
+        assert!(check_patterns(&patterns(&["src/{a,b"])).is_err());
+    }
+
+    #[test]
+    fn scan_honors_gitignore_files_when_asked() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        write_all(dir.path(), &["app.js", "dist/app.min.js", "sub/gen.ts", "sub/keep.ts", "a.tmp"]);
+        fs::write(dir.path().join(".gitignore"), "dist/\n*.tmp\n").unwrap();
+        fs::write(dir.path().join("sub/.gitignore"), "gen.ts\n").unwrap();
+        let root = dir.path().to_str().unwrap();
+        let config = patterns(&[".git"]);
+
+        let (files, _) = scan_dir(root, &config, &IgnoreFiles::default(), &|_| true);
+        assert!(files.contains_key("dist/app.min.js"));
+
+        let vcs = IgnoreFiles { vcs: true, extra: vec![] };
+        let (files, mut ignored) = scan_dir(root, &config, &vcs, &|_| true);
+        ignored.sort();
+        assert_eq!(
+            files.keys().collect::<Vec<_>>(),
+            vec![".gitignore", "app.js", "sub/.gitignore", "sub/keep.ts"]
+        );
+        assert_eq!(
+            ignored,
+            vec![
+                ".git",
+                "1 file (.gitignore: *.tmp)",
+                "1 file (sub/.gitignore: gen.ts)",
+                "dist (.gitignore: dist/)",
+            ]
+        );
+    }
+
+    #[test]
+    fn scan_honors_git_info_exclude() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join(".git/info")).unwrap();
+        fs::write(dir.path().join(".git/info/exclude"), "*.local\n").unwrap();
+        write_all(dir.path(), &["sub/a.local", "sub/b.txt"]);
+
+        let vcs = IgnoreFiles { vcs: true, extra: vec![] };
+        let sub = dir.path().join("sub");
+        let (files, ignored) = scan_dir(sub.to_str().unwrap(), &[], &vcs, &|_| true);
+        assert_eq!(files.keys().collect::<Vec<_>>(), vec!["b.txt"]);
+        assert_eq!(ignored.len(), 1);
+        assert!(ignored[0].ends_with(".git/info/exclude: *.local)"));
+    }
+
+    #[test]
+    fn scan_applies_extra_ignore_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+        write_all(dir.path(), &["docs/generated/api.md", "docs/index.md", "x.bak"]);
+        fs::write(dir.path().join(DIVERGE_IGNORE), "/docs/generated\n").unwrap();
+        fs::write(other.path().join(DIVERGE_IGNORE), "*.bak\n").unwrap();
+
+        let files_cfg = IgnoreFiles {
+            vcs: false,
+            extra: vec![dir.path().join(DIVERGE_IGNORE), other.path().join(DIVERGE_IGNORE)],
+        };
+        let (files, ignored) = scan_dir(dir.path().to_str().unwrap(), &[], &files_cfg, &|_| true);
+        assert_eq!(files.keys().collect::<Vec<_>>(), vec![".divergeignore", "docs/index.md"]);
+        assert_eq!(ignored[0], "docs/generated (.divergeignore: /docs/generated)");
+        assert!(ignored[1].starts_with("1 file ("));
+        assert!(ignored[1].ends_with(".divergeignore: *.bak)"));
+    }
+
+    #[test]
+    fn scan_stops_when_callback_says_so() {
+        let dir = tempfile::tempdir().unwrap();
+        write_all(dir.path(), &["a", "b", "c", "d"]);
+        let seen = std::sync::atomic::AtomicUsize::new(0);
+
+        let (files, _) = scan_dir(dir.path().to_str().unwrap(), &[], &IgnoreFiles::default(), &|_| {
+            seen.fetch_add(1, std::sync::atomic::Ordering::Relaxed) < 1
+        });
+        assert_eq!(files.len(), 2);
+        assert_eq!(seen.into_inner(), 2);
     }
 
     #[test]
@@ -154,7 +530,7 @@ mod tests {
         fs::write(dir.path().join("src/app.js"), "code").unwrap();
 
         let ignore = vec!["node_modules".to_string()];
-        let (files, ignored) = scan_dir(dir.path().to_str().unwrap(), &ignore);
+        let (files, ignored) = scan_dir(dir.path().to_str().unwrap(), &ignore, &IgnoreFiles::default(), &|_| true);
         assert_eq!(files.len(), 1);
         assert!(files.contains_key("src/app.js"));
         assert_eq!(ignored, vec!["src/node_modules"]);
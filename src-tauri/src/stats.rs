@@ -0,0 +1,131 @@
+use serde::Serialize;
+use similar::{Algorithm, DiffOp, TextDiff};
+use std::io::{self, Read};
+use std::iter::Sum;
+use std::ops::AddAssign;
+use std::time::Duration;
+
+/// Diffing gives up on finding the shortest edit script after this long, and settles for
+/// a larger one, so a pathological file cannot stall a comparison.
+pub(crate) const DIFF_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Line counts of a diff between two versions of a text file. A replaced line counts as
+/// changed; when a block of `m` lines is replaced by `n`, `min(m, n)` lines changed and the
+/// rest were added or removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct LineStats {
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+}
+
+impl AddAssign for LineStats {
+    fn add_assign(&mut self, other: Self) {
+        self.added += other.added;
+        self.removed += other.removed;
+        self.changed += other.changed;
+    }
+}
+
+impl Sum for LineStats {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |mut total, stats| {
+            total += stats;
+            total
+        })
+    }
+}
+
+/// Diffs `old` against `new` line by line (Myers), returning the line counts and the
+/// percentage of lines both have in common, rounded down.
+pub fn line_diff(old: &str, new: &str) -> (LineStats, u8) {
+    if old == new {
+        return (LineStats::default(), 100);
+    }
+    let diff = TextDiff::configure()
+        .algorithm(Algorithm::Myers)
+        .timeout(DIFF_TIMEOUT)
+        .diff_lines(old, new);
+
+    let mut stats = LineStats::default();
+    for op in diff.ops() {
+        match *op {
+            DiffOp::Equal { .. } => {}
+            DiffOp::Insert { new_len, .. } => stats.added += new_len,
+            DiffOp::Delete { old_len, .. } => stats.removed += old_len,
+            DiffOp::Replace { old_len, new_len, .. } => {
+                let changed = old_len.min(new_len);
+                stats.changed += changed;
+                stats.added += new_len - changed;
+                stats.removed += old_len - changed;
+            }
+        }
+    }
+    (stats, (diff.ratio() * 100.0) as u8)
+}
+
+/// Lines of a one-sided text entry, counted while streaming `reader`: all of them are added
+/// or removed. A last line without a newline counts too.
+pub fn count_lines(mut reader: impl Read) -> io::Result<usize> {
+    let mut buf = [0u8; 64 * 1024];
+    let (mut lines, mut last) = (0, b'\n');
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        lines += buf[..n].iter().filter(|&&b| b == b'\n').count();
+        last = buf[n - 1];
+    }
+    Ok(lines + usize::from(last != b'\n'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_added_removed_and_changed_lines() {
+        let (stats, similarity) = line_diff("a\nb\nc\nd\n", "a\nB\nc\nd\ne\nf\n");
+        assert_eq!(
+            stats,
+            LineStats {
+                added: 2,
+                removed: 0,
+                changed: 1
+            }
+        );
+        assert_eq!(similarity, 60);
+
+        let (stats, _) = line_diff("a\nb\nc\n", "a\nX\n");
+        assert_eq!((stats.added, stats.removed, stats.changed), (0, 1, 1));
+    }
+
+    #[test]
+    fn identical_and_one_sided_texts() {
+        assert_eq!(line_diff("same\n", "same\n"), (LineStats::default(), 100));
+
+        let (stats, similarity) = line_diff("", "one\ntwo\n");
+        assert_eq!((stats.added, stats.removed), (2, 0));
+        assert_eq!(similarity, 0);
+        let (stats, _) = line_diff("one\ntwo\nthree", "");
+        assert_eq!(stats.removed, 3);
+    }
+
+    #[test]
+    fn counts_lines_of_one_sided_texts() {
+        assert_eq!(count_lines("one\ntwo\n".as_bytes()).unwrap(), 2);
+        assert_eq!(count_lines("one\ntwo\nthree".as_bytes()).unwrap(), 3);
+        assert_eq!(count_lines("".as_bytes()).unwrap(), 0);
+    }
+
+    #[test]
+    fn totals_add_up() {
+        let total: LineStats = [line_diff("a\n", "b\n").0, line_diff("", "x\ny\n").0]
+            .into_iter()
+            .sum();
+        assert_eq!((total.added, total.removed, total.changed), (2, 0, 1));
+    }
+}
@@ -0,0 +1,545 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::compare::relative;
+use crate::error::DivergeError;
+use crate::hash::hash_file;
+use crate::history;
+use crate::models::{CompareEntry, CompareResult, EntryStatus, Side};
+use crate::write::{self, FileState};
+
+/// What to do with one path to bring the two trees together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncAction {
+    CopyLeftToRight,
+    CopyRightToLeft,
+    DeleteLeft,
+    DeleteRight,
+    Skip,
+}
+
+/// An action on the file at `rel_path`, the same relative path on both sides.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncItem {
+    pub rel_path: String,
+    pub action: SyncAction,
+}
+
+/// How one item went; `error` is `None` when it succeeded or was skipped.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncItemResult {
+    pub rel_path: String,
+    pub action: SyncAction,
+    pub error: Option<DivergeError>,
+}
+
+/// What running a batch of actions did.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncOutcome {
+    /// One result per item, in the order given.
+    pub results: Vec<SyncItemResult>,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    /// The comparison run again afterwards, when run on the compared directories.
+    pub result: Option<CompareResult>,
+}
+
+/// Which way a plan copies files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncDirection {
+    LeftToRight,
+    RightToLeft,
+}
+
+impl SyncDirection {
+    /// The side files are copied to and deleted from.
+    pub fn target(self) -> Side {
+        match self {
+            Self::LeftToRight => Side::Right,
+            Self::RightToLeft => Side::Left,
+        }
+    }
+
+    fn copy(self) -> SyncAction {
+        match self {
+            Self::LeftToRight => SyncAction::CopyLeftToRight,
+            Self::RightToLeft => SyncAction::CopyRightToLeft,
+        }
+    }
+
+    fn delete(self) -> SyncAction {
+        match self {
+            Self::LeftToRight => SyncAction::DeleteRight,
+            Self::RightToLeft => SyncAction::DeleteLeft,
+        }
+    }
+}
+
+/// Which entries of a comparison a plan covers.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SyncFilters {
+    /// Also delete files that only exist on the target side.
+    pub delete: bool,
+    /// Only these entries, by their path in the comparison; all of them when `None`.
+    pub paths: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    /// The target does not exist yet.
+    Copy,
+    Overwrite,
+    Delete,
+}
+
+impl OperationKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Copy => "copy",
+            Self::Overwrite => "overwrite",
+            Self::Delete => "delete",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedOperation {
+    pub rel_path: String,
+    pub kind: OperationKind,
+    /// Bytes written by a copy or overwrite, or freed by a delete.
+    pub size: u64,
+    /// The target as compared; `None` if it did not exist.
+    pub target: Option<FileState>,
+    /// Why the operation is unsafe: the target changed on disk since the comparison.
+    pub conflict: Option<String>,
+}
+
+/// Every copy, overwrite and delete a sync would make, checked against the files on disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncPlan {
+    pub left: String,
+    pub right: String,
+    pub direction: SyncDirection,
+    pub operations: Vec<PlannedOperation>,
+    pub copies: usize,
+    pub overwrites: usize,
+    pub deletes: usize,
+    pub conflicts: usize,
+    pub bytes_written: u64,
+    pub bytes_deleted: u64,
+}
+
+impl SyncPlan {
+    /// Whether the plan can run: no target changed since the comparison.
+    pub fn is_valid(&self) -> bool {
+        self.conflicts == 0
+    }
+
+    /// The plan as actions for `run`, in order.
+    pub fn items(&self) -> Vec<SyncItem> {
+        self.operations
+            .iter()
+            .map(|op| SyncItem {
+                rel_path: op.rel_path.clone(),
+                action: match op.kind {
+                    OperationKind::Copy | OperationKind::Overwrite => self.direction.copy(),
+                    OperationKind::Delete => self.direction.delete(),
+                },
+            })
+            .collect()
+    }
+}
+
+/// Plans making the target side of `direction` match the other one for the entries of
+/// `result`, the comparison of `left` and `right`. Renamed entries are synced path by
+/// path: the file is copied to its old path, and with `filters.delete` removed from the
+/// new one. Targets that changed since the comparison are flagged as conflicts.
+pub fn plan_sync(
+    left: &str,
+    right: &str,
+    result: &CompareResult,
+    direction: SyncDirection,
+    filters: &SyncFilters,
+) -> SyncPlan {
+    let target = direction.target();
+    let source = match target {
+        Side::Left => Side::Right,
+        Side::Right => Side::Left,
+    };
+    let mut operations = Vec::new();
+    for entry in &result.entries {
+        if filters.paths.as_ref().is_some_and(|paths| !paths.contains(&entry.rel_path)) {
+            continue;
+        }
+        let path_on = |side| match (side, &entry.renamed_from) {
+            (Side::Left, Some(from)) => from.clone(),
+            _ => entry.rel_path.clone(),
+        };
+        let to_path = match target {
+            Side::Left => &entry.left_path,
+            Side::Right => &entry.right_path,
+        };
+        let from = file_state(entry, source);
+        let to = file_state(entry, target).map(|to| with_hash(to, Path::new(to_path)));
+        if entry.status == EntryStatus::Renamed {
+            // The source file goes to its own path, which the target does not have.
+            if let Some(from) = &from {
+                operations.push(operation(path_on(source), OperationKind::Copy, from.size, None));
+            }
+            if let Some(to) = to.filter(|_| filters.delete) {
+                operations.push(operation(path_on(target), OperationKind::Delete, to.size, Some(to)));
+            }
+            continue;
+        }
+        match (from, to) {
+            (Some(from), None) => {
+                operations.push(operation(entry.rel_path.clone(), OperationKind::Copy, from.size, None))
+            }
+            (Some(from), Some(to)) if entry.status != EntryStatus::Identical => operations.push(
+                operation(entry.rel_path.clone(), OperationKind::Overwrite, from.size, Some(to)),
+            ),
+            (None, Some(to)) if filters.delete => operations.push(operation(
+                entry.rel_path.clone(),
+                OperationKind::Delete,
+                to.size,
+                Some(to),
+            )),
+            _ => {}
+        }
+    }
+
+    let mut plan = SyncPlan {
+        left: left.to_string(),
+        right: right.to_string(),
+        direction,
+        operations,
+        copies: 0,
+        overwrites: 0,
+        deletes: 0,
+        conflicts: 0,
+        bytes_written: 0,
+        bytes_deleted: 0,
+    };
+    check_plan(&mut plan);
+    plan
+}
+
+/// Checks every target against the state the plan recorded, flagging the ones that
+/// changed on disk, and updates the totals.
+pub fn check_plan(plan: &mut SyncPlan) {
+    let root = match plan.direction.target() {
+        Side::Left => Path::new(&plan.left),
+        Side::Right => Path::new(&plan.right),
+    };
+    for op in &mut plan.operations {
+        op.conflict = match relative(&op.rel_path) {
+            Ok(rel) => write::changed_since(&root.join(rel), op.target.as_ref()),
+            Err(e) => Some(e.to_string()),
+        };
+    }
+
+    let ops = &plan.operations;
+    let count = |kind| ops.iter().filter(|op| op.kind == kind).count();
+    let bytes = |deletes: bool| {
+        ops.iter()
+            .filter(|op| (op.kind == OperationKind::Delete) == deletes)
+            .map(|op| op.size)
+            .sum()
+    };
+    plan.copies = count(OperationKind::Copy);
+    plan.overwrites = count(OperationKind::Overwrite);
+    plan.deletes = count(OperationKind::Delete);
+    plan.conflicts = ops.iter().filter(|op| op.conflict.is_some()).count();
+    plan.bytes_written = bytes(false);
+    plan.bytes_deleted = bytes(true);
+}
+
+/// Runs a plan after checking it again. Fails with a conflict, touching nothing, if any
+/// target changed since the comparison it was made from.
+pub fn execute_plan(plan: &SyncPlan) -> Result<SyncOutcome, DivergeError> {
+    let mut checked = plan.clone();
+    check_plan(&mut checked);
+    if let Some(op) = checked.operations.iter().find(|op| op.conflict.is_some()) {
+        let more = match checked.conflicts {
+            1 => String::new(),
+            n => format!(" (and {} more)", n - 1),
+        };
+        return Err(DivergeError::Conflict {
+            path: op.rel_path.clone(),
+            message: format!(
+                "{}{}; compare again before syncing",
+                op.conflict.as_deref().unwrap_or_default(),
+                more
+            ),
+        });
+    }
+    let (from, to) = match plan.direction {
+        SyncDirection::LeftToRight => (&plan.left, &plan.right),
+        SyncDirection::RightToLeft => (&plan.right, &plan.left),
+    };
+    Ok(history::operation(format!("sync {} to {}", from, to), || {
+        run(&checked.left, &checked.right, &checked.items(), false)
+    }))
+}
+
+fn operation(
+    rel_path: String,
+    kind: OperationKind,
+    size: u64,
+    target: Option<FileState>,
+) -> PlannedOperation {
+    PlannedOperation {
+        rel_path,
+        kind,
+        size,
+        target,
+        conflict: None,
+    }
+}
+
+/// One side of an entry as it was compared, `None` where the file is missing.
+fn file_state(entry: &CompareEntry, side: Side) -> Option<FileState> {
+    let (size, modified, hash) = match side {
+        Side::Left => (entry.left_size, entry.left_modified, &entry.left_hash),
+        Side::Right => (entry.right_size, entry.right_modified, &entry.right_hash),
+    };
+    Some(FileState {
+        size: size?,
+        modified,
+        hash: hash.clone(),
+    })
+}
+
+/// `state` with its hash, computed now if the comparison did not need it and the file at
+/// `path` is still as compared, so a touched but unchanged target is not a conflict.
+fn with_hash(mut state: FileState, path: &Path) -> FileState {
+    if state.hash.is_none() && write::changed_since(path, Some(&state)).is_none() {
+        state.hash = hash_file(path).ok();
+    }
+    state
+}
+
+/// Runs each item against the trees at `left` and `right`. A failed item does not stop the
+/// others. With `dry_run`, paths are checked but nothing is touched.
+pub fn run(left: &str, right: &str, items: &[SyncItem], dry_run: bool) -> SyncOutcome {
+    let mut outcome = SyncOutcome::default();
+    history::operation(format!("sync {} and {}", left, right), || {
+        for item in items {
+            let error = if item.action == SyncAction::Skip {
+                outcome.skipped += 1;
+                None
+            } else {
+                match run_item(Path::new(left), Path::new(right), item, dry_run) {
+                    Ok(()) => {
+                        outcome.succeeded += 1;
+                        None
+                    }
+                    Err(e) => {
+                        outcome.failed += 1;
+                        Some(e)
+                    }
+                }
+            };
+            outcome.results.push(SyncItemResult {
+                rel_path: item.rel_path.clone(),
+                action: item.action,
+                error,
+            });
+        }
+    });
+    outcome
+}
+
+fn run_item(left: &Path, right: &Path, item: &SyncItem, dry_run: bool) -> Result<(), DivergeError> {
+    let rel = relative(&item.rel_path)?;
+    let (left, right) = (left.join(rel), right.join(rel));
+    let (source, target) = match item.action {
+        SyncAction::CopyLeftToRight => (Some(&left), &right),
+        SyncAction::CopyRightToLeft => (Some(&right), &left),
+        SyncAction::DeleteLeft => (None, &left),
+        SyncAction::DeleteRight => (None, &right),
+        SyncAction::Skip => return Ok(()),
+    };
+    let missing = source.unwrap_or(target);
+    if !missing.is_file() {
+        return Err(DivergeError::NotFound {
+            path: missing.display().to_string(),
+        });
+    }
+    if dry_run {
+        return Ok(());
+    }
+    match source {
+        Some(source) => write::copy_file(source, target),
+        None => write::remove_file(target),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compare::compare;
+    use crate::models::CompareOptions;
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+
+    fn item(rel_path: &str, action: SyncAction) -> SyncItem {
+        SyncItem {
+            rel_path: rel_path.to_string(),
+            action,
+        }
+    }
+
+    #[test]
+    fn runs_each_action() {
+        let (left, right) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        fs::write(left.path().join("to_right.txt"), "l").unwrap();
+        fs::create_dir(right.path().join("sub")).unwrap();
+        fs::write(right.path().join("sub/to_left.txt"), "r").unwrap();
+        fs::write(left.path().join("gone_left.txt"), "x").unwrap();
+        fs::write(right.path().join("gone_right.txt"), "x").unwrap();
+        fs::write(left.path().join("kept.txt"), "x").unwrap();
+        let (l, r) = (left.path().to_str().unwrap(), right.path().to_str().unwrap());
+
+        let items = [
+            item("to_right.txt", SyncAction::CopyLeftToRight),
+            item("sub/to_left.txt", SyncAction::CopyRightToLeft),
+            item("gone_left.txt", SyncAction::DeleteLeft),
+            item("gone_right.txt", SyncAction::DeleteRight),
+            item("kept.txt", SyncAction::Skip),
+            item("missing.txt", SyncAction::CopyLeftToRight),
+            item("../escape.txt", SyncAction::DeleteRight),
+        ];
+        let dry = run(l, r, &items, true);
+        assert_eq!((dry.succeeded, dry.failed, dry.skipped), (4, 2, 1));
+        assert!(!right.path().join("to_right.txt").exists());
+
+        let outcome = run(l, r, &items, false);
+        assert_eq!((outcome.succeeded, outcome.failed, outcome.skipped), (4, 2, 1));
+        assert_eq!(fs::read_to_string(right.path().join("to_right.txt")).unwrap(), "l");
+        assert_eq!(fs::read_to_string(left.path().join("sub/to_left.txt")).unwrap(), "r");
+        assert!(!left.path().join("gone_left.txt").exists());
+        assert!(!right.path().join("gone_right.txt").exists());
+        assert!(left.path().join("kept.txt").exists());
+
+        let errors: Vec<_> = outcome.results.iter().map(|r| r.error.as_ref().map(|e| e.kind())).collect();
+        assert_eq!(errors[5], Some("not_found"));
+        assert_eq!(errors[6], Some("invalid_input"));
+        assert!(errors[..5].iter().all(Option::is_none));
+    }
+
+    /// Left has a new file, right an extra one, and `changed.txt` differs with the same size.
+    fn trees() -> (tempfile::TempDir, tempfile::TempDir) {
+        let (left, right) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        fs::write(left.path().join("new.txt"), "new").unwrap();
+        fs::write(left.path().join("changed.txt"), "aaaa").unwrap();
+        fs::write(right.path().join("changed.txt"), "bbbb").unwrap();
+        fs::write(left.path().join("same.txt"), "same").unwrap();
+        fs::write(right.path().join("same.txt"), "same").unwrap();
+        fs::write(right.path().join("extra.txt"), "extra").unwrap();
+        (left, right)
+    }
+
+    fn plan(left: &Path, right: &Path, direction: SyncDirection, delete: bool) -> SyncPlan {
+        let (l, r) = (left.to_str().unwrap(), right.to_str().unwrap());
+        let result = compare(l, r, &CompareOptions::default());
+        let filters = SyncFilters { delete, paths: None };
+        plan_sync(l, r, &result, direction, &filters)
+    }
+
+    fn summary(plan: &SyncPlan) -> Vec<(&str, OperationKind, u64)> {
+        plan.operations.iter().map(|op| (op.rel_path.as_str(), op.kind, op.size)).collect()
+    }
+
+    #[test]
+    fn plans_copies_overwrites_and_deletes() {
+        let (left, right) = trees();
+
+        let to_right = plan(left.path(), right.path(), SyncDirection::LeftToRight, false);
+        assert_eq!(
+            summary(&to_right),
+            vec![("changed.txt", OperationKind::Overwrite, 4), ("new.txt", OperationKind::Copy, 3)]
+        );
+        assert!(to_right.is_valid());
+
+        let to_left = plan(left.path(), right.path(), SyncDirection::RightToLeft, true);
+        assert_eq!(
+            summary(&to_left),
+            vec![
+                ("changed.txt", OperationKind::Overwrite, 4),
+                ("extra.txt", OperationKind::Copy, 5),
+                ("new.txt", OperationKind::Delete, 3),
+            ]
+        );
+        assert_eq!((to_left.bytes_written, to_left.bytes_deleted), (9, 3));
+
+        let outcome = execute_plan(&to_left).unwrap();
+        assert_eq!(outcome.succeeded, 3);
+        let (l, r) = (left.path().to_str().unwrap(), right.path().to_str().unwrap());
+        let after = compare(l, r, &CompareOptions::default());
+        assert_eq!(after.identical, after.total);
+    }
+
+    #[test]
+    fn targets_are_hashed_when_the_comparison_did_not() {
+        let (left, right) = trees();
+        fs::write(right.path().join("changed.txt"), "longer").unwrap();
+
+        let plan = plan(left.path(), right.path(), SyncDirection::LeftToRight, false);
+        let target = plan.operations[0].target.as_ref().unwrap();
+        assert_eq!(plan.operations[0].rel_path, "changed.txt");
+        assert_eq!(target.hash, Some(blake3::hash(b"longer").to_hex().to_string()));
+    }
+
+    #[test]
+    fn renamed_entries_sync_path_by_path() {
+        let (left, right) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        fs::create_dir(left.path().join("a")).unwrap();
+        fs::create_dir(right.path().join("b")).unwrap();
+        fs::write(left.path().join("a/moved.txt"), "moved").unwrap();
+        fs::write(right.path().join("b/moved.txt"), "moved").unwrap();
+        let (l, r) = (left.path().to_str().unwrap(), right.path().to_str().unwrap());
+        let options = CompareOptions {
+            renames: Some(100),
+            ..Default::default()
+        };
+        let result = compare(l, r, &options);
+        let filters = SyncFilters { delete: true, paths: None };
+
+        let plan = plan_sync(l, r, &result, SyncDirection::LeftToRight, &filters);
+        assert_eq!(
+            summary(&plan),
+            vec![("a/moved.txt", OperationKind::Copy, 5), ("b/moved.txt", OperationKind::Delete, 5)]
+        );
+    }
+
+    #[test]
+    fn targets_changed_since_the_comparison_conflict() {
+        let (left, right) = trees();
+        let mut plan = plan(left.path(), right.path(), SyncDirection::LeftToRight, true);
+        let target = right.path().join("changed.txt");
+
+        // A new modification time alone is fine as long as the contents are the same.
+        let old = SystemTime::now() - Duration::from_secs(3600);
+        fs::File::options().write(true).open(&target).unwrap().set_modified(old).unwrap();
+        check_plan(&mut plan);
+        assert!(plan.is_valid());
+
+        fs::write(&target, "cccc").unwrap();
+        fs::write(right.path().join("new.txt"), "mine").unwrap();
+        check_plan(&mut plan);
+        assert_eq!(plan.conflicts, 2);
+        let conflicts: Vec<_> = plan.operations.iter().filter_map(|op| op.conflict.as_deref()).collect();
+        assert_eq!(conflicts, ["modified since the comparison", "created since the comparison"]);
+
+        let err = execute_plan(&plan).unwrap_err();
+        assert_eq!(err.kind(), "conflict");
+        assert!(right.path().join("extra.txt").exists());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "cccc");
+    }
+}
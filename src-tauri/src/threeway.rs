@@ -0,0 +1,345 @@
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+use crate::compare::entry_kind;
+use crate::error::DivergeError;
+use crate::hash::hash_file;
+use crate::history;
+use crate::merge::{merge_lines, merge_text, MergeOptions};
+use crate::models::{CompareOptions, EntryKind, ThreeWayEntry, ThreeWayResult, ThreeWayStatus};
+use crate::scanner::{scan_dir, IgnoreFiles, ScannedFile, DIVERGE_IGNORE};
+use crate::write::{self, WriteOptions};
+
+/// What `auto_merge` did to the `ours` tree.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MergeOutcome {
+    /// Files copied, deleted or merged into `ours`.
+    pub merged: Vec<String>,
+    /// Files both sides changed in ways that need a manual merge.
+    pub conflicts: Vec<String>,
+    /// The three-way comparison run again after merging, when merged through a `Comparer`.
+    pub result: Option<ThreeWayResult>,
+}
+
+/// Compares `ours` and `theirs` with their common ancestor `base`, as when merging a new
+/// upstream release (`theirs`) into a fork (`ours`). Every file is hashed; `trust_mtime`,
+/// `paranoid` and rename detection do not apply.
+pub fn compare_three_way(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    options: &CompareOptions,
+) -> ThreeWayResult {
+    match ThreadPoolBuilder::new().num_threads(options.threads).build() {
+        Ok(pool) => pool.install(|| compare_on_pool(base, ours, theirs, options)),
+        Err(_) => compare_on_pool(base, ours, theirs, options),
+    }
+}
+
+fn compare_on_pool(base: &str, ours: &str, theirs: &str, options: &CompareOptions) -> ThreeWayResult {
+    let ignore_files = IgnoreFiles {
+        vcs: options.respect_gitignore,
+        extra: [base, ours, theirs].map(|root| Path::new(root).join(DIVERGE_IGNORE)).to_vec(),
+    };
+    let scan = |root| scan_dir(root, &options.ignore_dirs, &ignore_files, &|_| true);
+    let ((base_files, base_ignored), ((ours_files, ours_ignored), (theirs_files, theirs_ignored))) =
+        rayon::join(|| scan(base), || rayon::join(|| scan(ours), || scan(theirs)));
+
+    let mut ignored_dirs: Vec<String> = [base_ignored, ours_ignored, theirs_ignored].concat();
+    ignored_dirs.sort();
+    ignored_dirs.dedup();
+
+    let mut keys: Vec<&String> = base_files
+        .keys()
+        .chain(ours_files.keys())
+        .chain(theirs_files.keys())
+        .collect();
+    keys.sort();
+    keys.dedup();
+
+    let entries: Vec<ThreeWayEntry> = keys
+        .par_iter()
+        .map(|key| {
+            let files = [&base_files, &ours_files, &theirs_files].map(|side| side.get(*key));
+            three_way_entry(key, files, options.max_file_size)
+        })
+        .collect();
+
+    let count = |status| entries.iter().filter(|e| e.status == status).count();
+    ThreeWayResult {
+        total: entries.len(),
+        unchanged: count(ThreeWayStatus::Unchanged),
+        changed_ours: count(ThreeWayStatus::ChangedOurs),
+        changed_theirs: count(ThreeWayStatus::ChangedTheirs),
+        changed_both_identically: count(ThreeWayStatus::ChangedBothIdentically),
+        conflicting: count(ThreeWayStatus::Conflicting),
+        mergeable: entries.iter().filter(|e| e.mergeable).count(),
+        entries,
+        ignored_dirs,
+    }
+}
+
+/// `files` holds the base, ours and theirs version of `key`, where it exists.
+fn three_way_entry(key: &str, files: [Option<&ScannedFile>; 3], max_file_size: u64) -> ThreeWayEntry {
+    // `None` for a file that exists but cannot be read.
+    let hashes = files.map(|file| match file {
+        Some(f) => hash_file(Path::new(&f.abs_path)).ok().map(Some),
+        None => Some(None),
+    });
+    let kind = entry_kind(
+        files.iter().flatten().map(|f| (Path::new(&f.abs_path), f.size)),
+        max_file_size,
+    );
+    let status = match &hashes {
+        [Some(base), Some(ours), Some(theirs)] => classify(base, ours, theirs),
+        _ => ThreeWayStatus::Conflicting,
+    };
+    let mergeable = status == ThreeWayStatus::Conflicting
+        && kind == EntryKind::Text
+        && hashes.iter().all(Option::is_some)
+        && merged_text(files).is_some();
+
+    let [base_hash, ours_hash, theirs_hash] = hashes.map(Option::flatten);
+    ThreeWayEntry {
+        rel_path: key.to_string(),
+        status,
+        kind,
+        base_hash,
+        ours_hash,
+        theirs_hash,
+        mergeable,
+    }
+}
+
+fn classify(base: &Option<String>, ours: &Option<String>, theirs: &Option<String>) -> ThreeWayStatus {
+    match (ours != base, theirs != base) {
+        (false, false) => ThreeWayStatus::Unchanged,
+        (true, false) => ThreeWayStatus::ChangedOurs,
+        (false, true) => ThreeWayStatus::ChangedTheirs,
+        (true, true) if ours == theirs => ThreeWayStatus::ChangedBothIdentically,
+        (true, true) => ThreeWayStatus::Conflicting,
+    }
+}
+
+/// Line-level merge of a file both sides kept, if nothing conflicts.
+fn merged_text(files: [Option<&ScannedFile>; 3]) -> Option<String> {
+    let [base, ours, theirs] = read_sides(files)?;
+    merge_lines(&base, &ours, &theirs)
+}
+
+/// Contents of a file both sides kept. A file added on both sides merges against an
+/// empty base.
+fn read_sides(files: [Option<&ScannedFile>; 3]) -> Option<[String; 3]> {
+    let [base, Some(ours), Some(theirs)] = files else {
+        return None;
+    };
+    let read = |f: &ScannedFile| fs::read_to_string(&f.abs_path).ok();
+    let base = match base {
+        Some(f) => read(f)?,
+        None => String::new(),
+    };
+    Some([base, read(ours)?, read(theirs)?])
+}
+
+/// Brings the changes of `theirs` into `ours`: files only `theirs` changed are copied or
+/// deleted, and conflicting text files are replaced by their line-level merge when
+/// `options` resolve every conflict in them. Other conflicting files are left alone and
+/// listed as conflicts. With `dry_run`, only reports.
+pub fn auto_merge(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    result: &ThreeWayResult,
+    options: &MergeOptions,
+    dry_run: bool,
+) -> Result<MergeOutcome, DivergeError> {
+    let label = format!("merge {} into {}", theirs, ours);
+    history::operation(label, || merge_entries(base, ours, theirs, result, options, dry_run))
+}
+
+fn merge_entries(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    result: &ThreeWayResult,
+    options: &MergeOptions,
+    dry_run: bool,
+) -> Result<MergeOutcome, DivergeError> {
+    let mut outcome = MergeOutcome::default();
+    for entry in &result.entries {
+        let rel = Path::new(&entry.rel_path);
+        let target = Path::new(ours).join(rel);
+        match entry.status {
+            ThreeWayStatus::ChangedTheirs if dry_run => {}
+            ThreeWayStatus::ChangedTheirs => match entry.theirs_hash {
+                Some(_) => write::copy_file(&Path::new(theirs).join(rel), &target)?,
+                None if target.exists() => write::remove_file(&target)?,
+                None => {}
+            },
+            ThreeWayStatus::Conflicting if entry.kind == EntryKind::Text => {
+                let files = [base, ours, theirs].map(|root| scanned(&Path::new(root).join(rel)));
+                let merged = read_sides(files.each_ref().map(Option::as_ref))
+                    .map(|[base, ours, theirs]| merge_text(&base, &ours, &theirs, options))
+                    .filter(|merge| merge.unresolved() == 0);
+                let Some(merged) = merged else {
+                    outcome.conflicts.push(entry.rel_path.clone());
+                    continue;
+                };
+                if !dry_run {
+                    let options = WriteOptions {
+                        keep_line_endings: true,
+                        ..Default::default()
+                    };
+                    write::write_file_with(&target, merged.text.as_bytes(), options)?;
+                }
+            }
+            ThreeWayStatus::Conflicting => {
+                outcome.conflicts.push(entry.rel_path.clone());
+                continue;
+            }
+            _ => continue,
+        }
+        outcome.merged.push(entry.rel_path.clone());
+    }
+    Ok(outcome)
+}
+
+fn scanned(path: &Path) -> Option<ScannedFile> {
+    let meta = fs::metadata(path).ok().filter(|m| m.is_file())?;
+    Some(ScannedFile {
+        abs_path: path.to_string_lossy().into_owned(),
+        size: meta.len(),
+        modified: meta.modified().ok(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Trees {
+        base: tempfile::TempDir,
+        ours: tempfile::TempDir,
+        theirs: tempfile::TempDir,
+    }
+
+    impl Trees {
+        fn new() -> Self {
+            let dir = || tempfile::tempdir().unwrap();
+            Trees {
+                base: dir(),
+                ours: dir(),
+                theirs: dir(),
+            }
+        }
+
+        /// Writes `name` with the given contents on each side; `None` leaves it out.
+        fn file(&self, name: &str, versions: [Option<&str>; 3]) {
+            for (dir, text) in [&self.base, &self.ours, &self.theirs].into_iter().zip(versions) {
+                if let Some(text) = text {
+                    fs::write(dir.path().join(name), text).unwrap();
+                }
+            }
+        }
+
+        fn roots(&self) -> [&str; 3] {
+            [&self.base, &self.ours, &self.theirs].map(|d| d.path().to_str().unwrap())
+        }
+
+        fn compare(&self) -> ThreeWayResult {
+            let [base, ours, theirs] = self.roots();
+            compare_three_way(base, ours, theirs, &CompareOptions::default())
+        }
+    }
+
+    fn status_of<'a>(result: &'a ThreeWayResult, path: &str) -> &'a ThreeWayEntry {
+        result.entries.iter().find(|e| e.rel_path == path).unwrap()
+    }
+
+    #[test]
+    fn classifies_each_path() {
+        let trees = Trees::new();
+        trees.file("same.txt", [Some("x"), Some("x"), Some("x")]);
+        trees.file("ours.txt", [Some("x"), Some("ours"), Some("x")]);
+        trees.file("theirs.txt", [Some("x"), Some("x"), Some("theirs")]);
+        trees.file("both.txt", [Some("x"), Some("y"), Some("y")]);
+        trees.file("clash.txt", [Some("x"), Some("ours"), Some("theirs")]);
+        trees.file("added.txt", [None, None, Some("new")]);
+        trees.file("deleted.txt", [Some("x"), None, Some("x")]);
+        trees.file("edit-vs-delete.txt", [Some("x"), Some("edited"), None]);
+
+        let result = trees.compare();
+        let expect = [
+            ("same.txt", ThreeWayStatus::Unchanged),
+            ("ours.txt", ThreeWayStatus::ChangedOurs),
+            ("theirs.txt", ThreeWayStatus::ChangedTheirs),
+            ("both.txt", ThreeWayStatus::ChangedBothIdentically),
+            ("clash.txt", ThreeWayStatus::Conflicting),
+            ("added.txt", ThreeWayStatus::ChangedTheirs),
+            ("deleted.txt", ThreeWayStatus::ChangedOurs),
+            ("edit-vs-delete.txt", ThreeWayStatus::Conflicting),
+        ];
+        for (path, status) in expect {
+            assert_eq!(status_of(&result, path).status, status, "{}", path);
+        }
+        assert_eq!((result.total, result.conflicting, result.mergeable), (8, 2, 0));
+        assert_eq!(status_of(&result, "added.txt").base_hash, None);
+    }
+
+    #[test]
+    fn non_overlapping_edits_are_mergeable() {
+        let trees = Trees::new();
+        trees.file("f.txt", [Some("1\n2\n3\n4\n"), Some("one\n2\n3\n4\n"), Some("1\n2\n3\nfour\n")]);
+        trees.file("clash.txt", [Some("1\n"), Some("ours\n"), Some("theirs\n")]);
+
+        let result = trees.compare();
+        assert_eq!(status_of(&result, "f.txt").status, ThreeWayStatus::Conflicting);
+        assert!(status_of(&result, "f.txt").mergeable);
+        assert!(!status_of(&result, "clash.txt").mergeable);
+    }
+
+    #[test]
+    fn auto_merge_brings_their_changes_into_ours() {
+        let trees = Trees::new();
+        trees.file("theirs.txt", [Some("x"), Some("x"), Some("new upstream")]);
+        trees.file("added.txt", [None, None, Some("added upstream")]);
+        trees.file("removed.txt", [Some("x"), Some("x"), None]);
+        trees.file("ours.txt", [Some("x"), Some("our fork"), Some("x")]);
+        trees.file("f.txt", [Some("1\n2\n3\n4\n"), Some("one\n2\n3\n4\n"), Some("1\n2\n3\nfour\n")]);
+        trees.file("clash.txt", [Some("1\n"), Some("ours\n"), Some("theirs\n")]);
+        let [base, ours, theirs] = trees.roots();
+        let result = trees.compare();
+
+        let options = MergeOptions::default();
+        let dry = auto_merge(base, ours, theirs, &result, &options, true).unwrap();
+        assert_eq!(dry.merged, vec!["added.txt", "f.txt", "removed.txt", "theirs.txt"]);
+        assert!(trees.ours.path().join("removed.txt").exists());
+
+        let outcome = auto_merge(base, ours, theirs, &result, &options, false).unwrap();
+        assert_eq!(outcome.merged, dry.merged);
+        assert_eq!(outcome.conflicts, vec!["clash.txt"]);
+        let read = |name: &str| fs::read_to_string(trees.ours.path().join(name)).unwrap();
+        assert_eq!(read("theirs.txt"), "new upstream");
+        assert_eq!(read("added.txt"), "added upstream");
+        assert_eq!(read("ours.txt"), "our fork");
+        assert_eq!(read("f.txt"), "one\n2\n3\nfour\n");
+        assert_eq!(read("clash.txt"), "ours\n");
+        assert!(!trees.ours.path().join("removed.txt").exists());
+
+        // Merged files still differ from the base on both sides, but merge cleanly again.
+        let after = trees.compare();
+        assert_eq!(after.changed_theirs, 0);
+        assert_eq!((after.conflicting, after.mergeable), (2, 1));
+
+        let theirs_wins = MergeOptions {
+            favor: Some(crate::merge::Favor::Theirs),
+            ..Default::default()
+        };
+        let outcome = auto_merge(base, ours, theirs, &after, &theirs_wins, false).unwrap();
+        assert!(outcome.conflicts.is_empty());
+        assert_eq!(read("clash.txt"), "theirs\n");
+    }
+}
@@ -0,0 +1,348 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::DivergeError;
+use crate::hash::hash_file;
+use crate::history;
+use crate::scanner::epoch_millis;
+
+/// A file as it was compared or last written, to tell whether it changed on disk since.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileState {
+    pub size: u64,
+    /// Milliseconds since the Unix epoch.
+    pub modified: Option<u64>,
+    /// BLAKE3 hash, when known.
+    pub hash: Option<String>,
+}
+
+/// How a write treats the file it replaces. By default the content is written as is.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct WriteOptions {
+    /// Convert text to the line endings of the file it replaces, and keep that file's
+    /// trailing newline or lack of one. For hand-edited text, not for copies of a file.
+    pub keep_line_endings: bool,
+    /// Also keep the extended attributes of the file it replaces (unix only).
+    pub xattrs: bool,
+}
+
+/// Writes `content` to `path` byte for byte, creating missing parent directories. The file
+/// is replaced atomically and keeps the mode and owner of the file it replaces.
+pub fn write_file(path: &Path, content: &[u8]) -> Result<(), DivergeError> {
+    write_file_with(path, content, WriteOptions::default())
+}
+
+/// Like `write_file`, with `options` for what to keep of the file it replaces.
+pub fn write_file_with(
+    path: &Path,
+    content: &[u8],
+    options: WriteOptions,
+) -> Result<(), DivergeError> {
+    let path = &resolve_link(path)?;
+    let converted = match std::str::from_utf8(content) {
+        Ok(text) if options.keep_line_endings => fs::read(path)
+            .ok()
+            .filter(|original| std::str::from_utf8(original).is_ok())
+            .map(|original| match_line_endings(&original, text)),
+        _ => None,
+    };
+    let content = converted.as_ref().map_or(content, |text| text.as_bytes());
+    replace(path, Some(path), options.xattrs, |file| file.write_all(content))
+}
+
+/// Like `write_file_with`, but unless `force` is set, fails with a `Conflict` instead of
+/// overwriting a file that is no longer `expected`, its state when it was read (`None` if
+/// it did not exist). Returns the state of the file as written, to check the next write
+/// against.
+pub fn write_file_checked(
+    path: &Path,
+    content: &[u8],
+    expected: Option<&FileState>,
+    force: bool,
+    options: WriteOptions,
+) -> Result<FileState, DivergeError> {
+    if !force {
+        check_unchanged(path, expected)?;
+    }
+    write_file_with(path, content, options)?;
+    let meta = fs::metadata(path).map_err(|e| DivergeError::io(path, e))?;
+    let hash = hash_file(path).map_err(|e| DivergeError::io(path, e))?;
+    Ok(FileState {
+        size: meta.len(),
+        modified: meta.modified().ok().and_then(epoch_millis),
+        hash: Some(hash),
+    })
+}
+
+/// Fails with a `Conflict` if `path` is no longer `expected`.
+pub(crate) fn check_unchanged(path: &Path, expected: Option<&FileState>) -> Result<(), DivergeError> {
+    match changed_since(path, expected) {
+        Some(reason) => Err(DivergeError::Conflict {
+            path: path.display().to_string(),
+            message: reason,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Why `path` no longer matches `expected` (`None` expects no file), if it does not. A file
+/// whose modification time moved but whose hash is unchanged still matches.
+pub(crate) fn changed_since(path: &Path, expected: Option<&FileState>) -> Option<String> {
+    let meta = match fs::metadata(path) {
+        Ok(meta) if meta.is_file() => Some(meta),
+        Ok(_) => return Some("no longer a file".to_string()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Some(DivergeError::io(path, e).to_string()),
+    };
+    let (expected, meta) = match (expected, meta) {
+        (None, None) => return None,
+        (None, Some(_)) => return Some("created since the comparison".to_string()),
+        (Some(_), None) => return Some("deleted since the comparison".to_string()),
+        (Some(expected), Some(meta)) => (expected, meta),
+    };
+    if meta.len() != expected.size {
+        return Some("changed size since the comparison".to_string());
+    }
+    if meta.modified().ok().and_then(epoch_millis) == expected.modified {
+        return None;
+    }
+    match &expected.hash {
+        Some(hash) if hash_file(path).ok().as_ref() == Some(hash) => None,
+        _ => Some("modified since the comparison".to_string()),
+    }
+}
+
+/// Deletes `path`, after saving it in the undo history.
+pub fn remove_file(path: &Path) -> Result<(), DivergeError> {
+    history::backup(path, "delete")?;
+    fs::remove_file(path).map_err(|e| DivergeError::io(path, e))
+}
+
+/// Copies `source` over `target` with its permissions, creating missing parent directories.
+/// Like `write_file`, the target is replaced atomically.
+pub fn copy_file(source: &Path, target: &Path) -> Result<(), DivergeError> {
+    let mut from = File::open(source).map_err(|e| DivergeError::io(source, e))?;
+    let target = &resolve_link(target)?;
+    replace(target, Some(source), false, |file| io::copy(&mut from, file).map(|_| ()))
+}
+
+/// Distinguishes temporary files of concurrent writes from the same process.
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Replaces `path` with what `fill` writes into a temporary file next to it, flushed to
+/// disk before it is renamed over `path`, so a crash leaves either the old file or the
+/// new one. The result takes the mode and, where allowed, the owner of `template` if it
+/// exists, and with `xattrs` its extended attributes. The old file is saved in the undo
+/// history first.
+fn replace(
+    path: &Path,
+    template: Option<&Path>,
+    xattrs: bool,
+    fill: impl FnOnce(&mut File) -> io::Result<()>,
+) -> Result<(), DivergeError> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir).map_err(|e| DivergeError::io(dir, e))?;
+    history::backup(path, "write")?;
+    let (temp, mut file) = create_temp(dir, path)?;
+
+    let template = template.and_then(|t| fs::metadata(t).ok().map(|meta| (t, meta)));
+    let written = fill(&mut file)
+        .and_then(|_| match &template {
+            Some((t, meta)) => keep_attributes(&file, &temp, t, meta, xattrs),
+            None => Ok(()),
+        })
+        .and_then(|_| file.sync_all());
+    drop(file);
+    if let Err(e) = written.and_then(|_| fs::rename(&temp, path)) {
+        let _ = fs::remove_file(&temp);
+        return Err(DivergeError::io(path, e));
+    }
+    sync_dir(dir);
+    Ok(())
+}
+
+/// A new, empty file in `dir` named after `path`, created with the default mode.
+fn create_temp(dir: &Path, path: &Path) -> Result<(PathBuf, File), DivergeError> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    loop {
+        let n = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let temp = dir.join(format!(".{}.{}-{}.diverge-tmp", name, std::process::id(), n));
+        match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((temp, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(DivergeError::io(dir, e)),
+        }
+    }
+}
+
+/// Gives the temporary file the mode of `template`, and its owner and extended attributes
+/// where the process is allowed to.
+fn keep_attributes(
+    file: &File,
+    temp: &Path,
+    template: &Path,
+    meta: &fs::Metadata,
+    xattrs: bool,
+) -> io::Result<()> {
+    file.set_permissions(meta.permissions())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        // Only root may give a file away; anyone else keeps owning what they write.
+        let _ = std::os::unix::fs::fchown(file, Some(meta.uid()), Some(meta.gid()));
+        if xattrs {
+            for name in xattr::list(template).into_iter().flatten() {
+                if let Ok(Some(value)) = xattr::get(template, &name) {
+                    let _ = xattr::set(temp, &name, &value);
+                }
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (temp, template, xattrs);
+    Ok(())
+}
+
+/// Makes the rename into `dir` durable. Not every platform can open a directory, so this is
+/// best effort.
+fn sync_dir(dir: &Path) {
+    #[cfg(unix)]
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    #[cfg(not(unix))]
+    let _ = dir;
+}
+
+/// The file a symlink points to, so that writing through it replaces the target rather
+/// than the link.
+fn resolve_link(path: &Path) -> Result<PathBuf, DivergeError> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_symlink() => {
+            fs::canonicalize(path).map_err(|e| DivergeError::io(path, e))
+        }
+        _ => Ok(path.to_path_buf()),
+    }
+}
+
+/// `text` with the line endings of `original` (CRLF when most of its lines end that way),
+/// and with a trailing newline only if `original` had one. An empty original leaves `text`
+/// alone, and one without line breaks only decides the trailing newline.
+pub(crate) fn match_line_endings(original: &[u8], text: &str) -> String {
+    if original.is_empty() {
+        return text.to_string();
+    }
+    let crlf = original.windows(2).filter(|w| w == b"\r\n").count();
+    let lf = original.iter().filter(|&&b| b == b'\n').count() - crlf;
+    let mut out = match (crlf, lf) {
+        (0, 0) => text.to_string(),
+        _ if crlf > lf => text.replace("\r\n", "\n").replace('\n', "\r\n"),
+        _ => text.replace("\r\n", "\n"),
+    };
+
+    let eol = if crlf > lf { "\r\n" } else { "\n" };
+    let ending = if out.ends_with("\r\n") {
+        2
+    } else {
+        usize::from(out.ends_with('\n'))
+    };
+    match (original.ends_with(b"\n"), ending) {
+        (true, 0) if !out.is_empty() => out.push_str(eol),
+        (false, n) => out.truncate(out.len() - n),
+        _ => {}
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checked_write_refuses_files_changed_since_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sub/a.txt");
+
+        let first = write_file_checked(&path, b"one\n", None, false, WriteOptions::default()).unwrap();
+        assert_eq!(first.size, 4);
+        let err = write_file_checked(&path, b"two\n", None, false, WriteOptions::default()).unwrap_err();
+        assert_eq!(err.kind(), "conflict");
+
+        let second = write_file_checked(&path, b"two\n", Some(&first), false, WriteOptions::default()).unwrap();
+        fs::write(&path, b"edit\n").unwrap();
+        let err = write_file_checked(&path, b"three\n", Some(&second), false, WriteOptions::default()).unwrap_err();
+        assert_eq!(
+            err,
+            DivergeError::Conflict {
+                path: path.display().to_string(),
+                message: "changed size since the comparison".to_string(),
+            }
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "edit\n");
+
+        let forced = write_file_checked(&path, b"three\n", Some(&second), true, WriteOptions::default()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "three\n");
+        assert_eq!(forced.size, 6);
+
+        fs::remove_file(&path).unwrap();
+        let err = write_file_checked(&path, b"four\n", Some(&forced), false, WriteOptions::default()).unwrap_err();
+        assert_eq!(err.kind(), "conflict");
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn writes_replace_files_atomically() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.sh");
+        fs::write(&path, "old\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o751)).unwrap();
+        }
+
+        write_file(&path, b"new\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        let names: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(names, vec!["run.sh"]);
+
+        let copy = dir.path().join("sub/copy.sh");
+        copy_file(&path, &copy).unwrap();
+        assert_eq!(fs::read_to_string(&copy).unwrap(), "new\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |p: &Path| fs::metadata(p).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&path), 0o751);
+            assert_eq!(mode(&copy), 0o751);
+        }
+    }
+
+    #[test]
+    fn keeps_line_endings_and_trailing_newline() {
+        assert_eq!(match_line_endings(b"a\r\nb\r\n", "a\nb\nc\n"), "a\r\nb\r\nc\r\n");
+        assert_eq!(match_line_endings(b"a\nb", "a\r\nb\r\nc\r\n"), "a\nb\nc");
+        assert_eq!(match_line_endings(b"a\n", "a\nb"), "a\nb\n");
+        assert_eq!(match_line_endings(b"a", "b\n"), "b");
+        assert_eq!(match_line_endings(b"", "a\r\n"), "a\r\n");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        fs::write(&path, "one\r\ntwo\r\n").unwrap();
+        let options = WriteOptions { keep_line_endings: true, xattrs: true };
+        let state = write_file_checked(&path, b"one\nthree", None, true, options).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"one\r\nthree\r\n");
+        assert_eq!(state.size, 12);
+
+        assert!(!WriteOptions::default().keep_line_endings);
+        write_file(&path, b"exact\n").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"exact\n");
+    }
+}
@@ -5,11 +5,15 @@ import { useToast } from "./hooks/useToast";
 import { Toolbar } from "./components/Toolbar";
 import { FileTree } from "./components/FileTree";
 import { DiffEditorView } from "./components/DiffEditor";
+import { BinaryView } from "./components/BinaryView";
 import { SettingsPanel } from "./components/SettingsPanel";
 import { StatusBar } from "./components/StatusBar";
 import { ToastContainer } from "./components/Toast";
 import { ConfirmDialog } from "./components/ConfirmDialog";
 import { useFileWatcher } from "./hooks/useFileWatcher";
+import { describeFailure, isDivergeError } from "./utils/errors";
+import { formatBytes } from "./utils/format";
+import type { SyncDirection, SyncItem, SyncOutcome, SyncPlan } from "./types";
 import "./App.css";
 
 function App() {
@@ -19,6 +23,9 @@ function App() {
 
   const modifiedCount = Object.keys(cmp.modifiedContents).length;
   const [confirm, setConfirm] = useState<"applyAll" | "saveAll" | null>(null);
+  const [pendingDelete, setPendingDelete] = useState<SyncItem | null>(null);
+  const [syncPlan, setSyncPlan] = useState<SyncPlan | null>(null);
+  const [forceSave, setForceSave] = useState<string | null>(null);
 
   const [sidebarWidth, setSidebarWidth] = useState(
     settings.config?.editor_preferences.sidebar_width ?? 280
@@ -69,38 +76,73 @@ function App() {
 
   const handleSaveAll = useCallback(async () => {
     try {
-      const count = await cmp.saveAll();
-      showToast(`Saved ${count} file${count !== 1 ? "s" : ""} to disk`);
-      return count;
-    } catch {
-      showToast("Save failed", "error");
+      const outcome = await cmp.saveAll();
+      if (!outcome) return 0;
+      const failed = outcome.results.filter((r) => r.error || r.rollback_error);
+      if (failed.length === 0) {
+        showToast(`Saved ${outcome.written} file${outcome.written !== 1 ? "s" : ""} to disk`);
+      } else {
+        const reasons = failed
+          .map((r) => `${r.rel_path}: ${(r.error ?? r.rollback_error)!.message}`)
+          .join(", ");
+        const saved = outcome.rolled_back ? "Saved nothing" : `Saved ${outcome.written}`;
+        showToast(`${saved}; failed ${reasons}`, "error");
+      }
+      return outcome.written;
+    } catch (e) {
+      showToast(describeFailure("Save", e), "error");
       return 0;
     }
   }, [cmp, showToast]);
 
+  // A file changed on disk since the comparison is only overwritten once confirmed.
   const handleSaveFile = useCallback(
-    async (relPath: string) => {
+    async (relPath: string, force = false) => {
       try {
-        await cmp.saveFile(relPath);
+        await cmp.saveFile(relPath, force);
         showToast(`Saved ${relPath.split("/").pop()}`);
-      } catch {
-        showToast(`Failed to save ${relPath}`, "error");
+      } catch (e) {
+        if (!force && isDivergeError(e) && e.kind === "conflict") {
+          setForceSave(relPath);
+        } else {
+          showToast(describeFailure(`Saving ${relPath}`, e), "error");
+        }
       }
     },
     [cmp, showToast]
   );
 
-  const handleApplyAll = useCallback(() => {
-    cmp.applyAllToRight();
-    showToast("Applied all differences to right side");
+  const handleApplyAll = useCallback(async () => {
+    try {
+      await cmp.applyAllToRight();
+      showToast("Applied all differences to right side");
+    } catch (e) {
+      showToast(describeFailure("Loading file contents", e), "error");
+    }
   }, [cmp, showToast]);
 
-  const handleApplySelected = useCallback(() => {
+  const handleApplySelected = useCallback(async () => {
     const count = cmp.checkedFiles.size;
-    cmp.applySelectedToRight();
-    showToast(`Applied ${count} selected file${count !== 1 ? "s" : ""}`);
+    try {
+      await cmp.applySelectedToRight();
+      showToast(`Applied ${count} selected file${count !== 1 ? "s" : ""}`);
+    } catch (e) {
+      showToast(describeFailure("Loading file contents", e), "error");
+    }
   }, [cmp, showToast]);
 
+  const handleApplyLeftToRight = useCallback(
+    async (relPath: string) => {
+      try {
+        await cmp.applyLeftToRight(relPath);
+        showToast("Applied left to right");
+      } catch (e) {
+        showToast(describeFailure(`Loading ${relPath}`, e), "error");
+      }
+    },
+    [cmp, showToast]
+  );
+
   const prevResultRef = useRef(cmp.result);
   useEffect(() => {
     if (cmp.result && cmp.result !== prevResultRef.current && cmp.leftDir && cmp.rightDir) {
@@ -117,6 +159,87 @@ function App() {
     await cmp.compareWith(leftDir, rightDir);
   }, [cmp]);
 
+  const handleApplyPatch = useCallback(async (patchPath: string) => {
+    try {
+      const outcome = await cmp.applyPatch(patchPath);
+      const files = `${outcome.patched.length} file${outcome.patched.length !== 1 ? "s" : ""}`;
+      if (outcome.conflicts.length === 0) {
+        showToast(`Patched ${files}`);
+      } else {
+        const rejected = outcome.conflicts
+          .map((c) => (c.hunk ? `${c.path} hunk #${c.hunk}` : `${c.path}: ${c.reason}`))
+          .join(", ");
+        showToast(`Patched ${files}; rejected ${rejected}`, "error");
+      }
+    } catch (e) {
+      showToast(describeFailure("Applying the patch", e), "error");
+    }
+  }, [cmp, showToast]);
+
+  const reportSync = useCallback((outcome: SyncOutcome) => {
+    const failed = outcome.results.filter((r) => r.error);
+    if (failed.length === 0) {
+      showToast(`Synced ${outcome.succeeded} file${outcome.succeeded !== 1 ? "s" : ""}`);
+    } else {
+      const reasons = failed.map((r) => `${r.rel_path}: ${r.error!.message}`).join(", ");
+      showToast(`Synced ${outcome.succeeded}, failed ${reasons}`, "error");
+    }
+  }, [showToast]);
+
+  const handleSync = useCallback(async (items: SyncItem[]) => {
+    try {
+      reportSync(await cmp.syncEntries(items));
+    } catch (e) {
+      showToast(describeFailure("Syncing", e), "error");
+    }
+  }, [cmp, reportSync, showToast]);
+
+  // Plans a sync of the checked files, or of everything, and asks before running it.
+  const handlePlanSync = useCallback(async (direction: SyncDirection) => {
+    try {
+      const paths = cmp.checkedFiles.size > 0 ? [...cmp.checkedFiles] : null;
+      const plan = await cmp.planSync(direction, { delete: true, paths });
+      if (plan.operations.length === 0) {
+        showToast("Nothing to sync");
+      } else if (plan.conflicts > 0) {
+        showToast(
+          `${plan.conflicts} file${plan.conflicts !== 1 ? "s" : ""} changed on disk since the comparison; refresh before syncing`,
+          "error"
+        );
+      } else {
+        setSyncPlan(plan);
+      }
+    } catch (e) {
+      showToast(describeFailure("Planning the sync", e), "error");
+    }
+  }, [cmp, showToast]);
+
+  const handleRunSyncPlan = useCallback(async (plan: SyncPlan) => {
+    try {
+      reportSync(await cmp.runSyncPlan(plan));
+    } catch (e) {
+      showToast(describeFailure("Syncing", e), "error");
+    }
+  }, [cmp, reportSync, showToast]);
+
+  const handleUndo = useCallback(async () => {
+    try {
+      const [op] = (await cmp.undoLastOperation()).restored;
+      showToast(op ? `Undid ${op.label}` : "Nothing to undo");
+    } catch (e) {
+      showToast(describeFailure("Undoing", e), "error");
+    }
+  }, [cmp, showToast]);
+
+  // Deletions are confirmed first; copies run straight away.
+  const handleSyncSelected = useCallback((item: SyncItem) => {
+    if (item.action === "delete_left" || item.action === "delete_right") {
+      setPendingDelete(item);
+    } else {
+      handleSync([item]);
+    }
+  }, [handleSync]);
+
   const handleRefresh = useCallback(async () => {
     try {
       await cmp.compare();
@@ -139,8 +262,8 @@ function App() {
     try {
       await settings.save();
       showToast("Settings saved");
-    } catch {
-      showToast("Failed to save settings", "error");
+    } catch (e) {
+      showToast(describeFailure("Saving settings", e), "error");
     }
   }, [settings, showToast]);
 
@@ -153,10 +276,16 @@ function App() {
         onSetLeftDir={cmp.setLeftDir}
         onSetRightDir={cmp.setRightDir}
         onCompare={handleCompare}
+        onCancel={cmp.cancelCompare}
+        respectGitignore={cmp.respectGitignore ?? settings.config?.respect_gitignore ?? false}
+        onToggleGitignore={cmp.setRespectGitignore}
         onApplyAll={() => setConfirm("applyAll")}
         onApplySelected={handleApplySelected}
         onSaveAll={() => { setConfirm("saveAll"); return Promise.resolve(0); }}
         onRefresh={handleRefresh}
+        onApplyPatch={handleApplyPatch}
+        onSync={handlePlanSync}
+        onUndo={handleUndo}
         onClear={cmp.clear}
         loading={cmp.loading}
         hasResult={!!cmp.result}
@@ -206,10 +335,13 @@ function App() {
         )}
 
         <div className="editor-panel">
-          {cmp.selectedEntry ? (
+          {cmp.selectedEntry && cmp.selectedContents && cmp.selectedContents.kind !== "text" ? (
+            <BinaryView key={cmp.selectedFile} entry={cmp.selectedEntry} />
+          ) : cmp.selectedEntry && cmp.selectedContents ? (
             <DiffEditorView
               key={cmp.selectedFile}
               entry={cmp.selectedEntry}
+              contents={cmp.selectedContents}
               modifiedContent={cmp.modifiedContents[cmp.selectedFile!]}
               minimapEnabled={settings.config?.editor_preferences.minimap_enabled ?? false}
               showFullContent={settings.config?.editor_preferences.show_full_content ?? true}
@@ -217,15 +349,17 @@ function App() {
               onContentChange={(content) =>
                 cmp.updateModifiedContent(cmp.selectedFile!, content)
               }
-              onApplyLeftToRight={() => {
-                cmp.applyLeftToRight(cmp.selectedFile!);
-                showToast("Applied left to right");
-              }}
+              onApplyLeftToRight={() => handleApplyLeftToRight(cmp.selectedFile!)}
+              onSync={(action) => handleSyncSelected({ rel_path: cmp.selectedFile!, action })}
               onSaveFile={() => handleSaveFile(cmp.selectedFile!)}
             />
           ) : (
             <div className="editor-placeholder">
-              {cmp.result ? (
+              {cmp.selectedEntry ? (
+                <div className="placeholder-text">
+                  {cmp.contentsError ?? "Loading…"}
+                </div>
+              ) : cmp.result ? (
                 <>
                   <div className="placeholder-icon">⇄</div>
                   <div className="placeholder-text">Select a file to view differences</div>
@@ -245,8 +379,9 @@ function App() {
 
       <StatusBar
         result={cmp.result}
+        progress={cmp.progress}
         selectedFile={cmp.selectedFile}
-        selectedEntry={cmp.selectedEntry ?? null}
+        selectedContents={cmp.selectedContents}
         modifiedContent={cmp.selectedFile ? cmp.modifiedContents[cmp.selectedFile] : undefined}
         modifiedCount={modifiedCount}
       />
@@ -266,13 +401,43 @@ function App() {
       {confirm === "saveAll" && (
         <ConfirmDialog
           title="Save All Modified Files"
-          message={`This will write ${modifiedCount} modified file${modifiedCount !== 1 ? "s" : ""} to disk, overwriting the existing files. This cannot be undone.`}
+          message={`This will write ${modifiedCount} modified file${modifiedCount !== 1 ? "s" : ""} to disk, overwriting the existing files. If one fails to save, none are changed, and ↶ Undo Last Write restores the previous versions.`}
           confirmLabel="Save All"
           confirmVariant="accent"
           onConfirm={() => { setConfirm(null); handleSaveAll(); }}
           onCancel={() => setConfirm(null)}
         />
       )}
+      {forceSave && (
+        <ConfirmDialog
+          title="File Changed on Disk"
+          message={`${forceSave} was changed on disk since the comparison. Overwrite it anyway? The changes made outside Diverge will be lost.`}
+          confirmLabel="Overwrite"
+          confirmVariant="danger"
+          onConfirm={() => { setForceSave(null); handleSaveFile(forceSave, true); }}
+          onCancel={() => setForceSave(null)}
+        />
+      )}
+      {syncPlan && (
+        <ConfirmDialog
+          title={syncPlan.direction === "left_to_right" ? "Sync to Right" : "Sync to Left"}
+          message={`This will copy ${syncPlan.copies} new file${syncPlan.copies !== 1 ? "s" : ""}, overwrite ${syncPlan.overwrites} and delete ${syncPlan.deletes} on the ${syncPlan.direction === "left_to_right" ? "right" : "left"} side (${formatBytes(syncPlan.bytes_written)} to write, ${formatBytes(syncPlan.bytes_deleted)} to delete). This cannot be undone.`}
+          confirmLabel="Sync"
+          confirmVariant={syncPlan.deletes > 0 ? "danger" : "accent"}
+          onConfirm={() => { setSyncPlan(null); handleRunSyncPlan(syncPlan); }}
+          onCancel={() => setSyncPlan(null)}
+        />
+      )}
+      {pendingDelete && (
+        <ConfirmDialog
+          title="Delete File"
+          message={`This will delete ${pendingDelete.rel_path} from the ${pendingDelete.action === "delete_left" ? "left" : "right"} side. This cannot be undone.`}
+          confirmLabel="Delete"
+          confirmVariant="danger"
+          onConfirm={() => { setPendingDelete(null); handleSync([pendingDelete]); }}
+          onCancel={() => setPendingDelete(null)}
+        />
+      )}
     </div>
   );
 }
@@ -0,0 +1,66 @@
+import type { CompareEntry } from "../types";
+
+interface BinaryViewProps {
+  entry: CompareEntry;
+}
+
+function formatSize(size: number | null) {
+  return size === null ? "—" : `${size.toLocaleString()} bytes`;
+}
+
+export function BinaryView({ entry }: BinaryViewProps) {
+  const diff = entry.binary_diff;
+  const tooLarge = entry.kind === "too_large";
+
+  return (
+    <div className="diff-editor-container">
+      <div className="diff-editor-header">
+        <div className="diff-editor-filepath">
+          <span className="file-type-badge">{tooLarge ? "BIG" : "BIN"}</span>
+          <span>{entry.rel_path}</span>
+          {entry.renamed_from && (
+            <span className="diff-editor-renamed">← {entry.renamed_from}</span>
+          )}
+          <span className={`diff-editor-badge badge-${entry.status}`}>
+            {entry.status.replace("_", " ").toUpperCase()}
+          </span>
+        </div>
+      </div>
+      <div className="binary-view">
+        <table className="binary-table">
+          <thead>
+            <tr>
+              <th />
+              <th>Left</th>
+              <th>Right</th>
+            </tr>
+          </thead>
+          <tbody>
+            <tr>
+              <th>Size</th>
+              <td>{formatSize(entry.left_size)}</td>
+              <td>{formatSize(entry.right_size)}</td>
+            </tr>
+            <tr>
+              <th>BLAKE3</th>
+              <td className="binary-mono">{entry.left_hash ?? "—"}</td>
+              <td className="binary-mono">{entry.right_hash ?? "—"}</td>
+            </tr>
+            {diff && (
+              <tr>
+                <th>At 0x{diff.offset.toString(16)}</th>
+                <td className="binary-mono">{diff.left_bytes || "(end of file)"}</td>
+                <td className="binary-mono">{diff.right_bytes || "(end of file)"}</td>
+              </tr>
+            )}
+          </tbody>
+        </table>
+        <div className="placeholder-text">
+          {tooLarge
+            ? "File exceeds max_file_size — contents are compared by hash and not loaded."
+            : "Binary file — contents are compared by hash and cannot be edited here."}
+        </div>
+      </div>
+    </div>
+  );
+}
@@ -2,32 +2,46 @@ import { DiffEditor as MonacoDiffEditor } from "@monaco-editor/react";
 import type { editor } from "monaco-editor";
 import { useRef, useEffect, useCallback, useMemo, useState } from "react";
 import { invoke } from "@tauri-apps/api/core";
-import type { CompareEntry, EditorPreferences } from "../types";
+import type {
+  CompareEntry,
+  EditorPreferences,
+  EntryContents,
+  FileState,
+  SyncAction,
+  UnifiedDiff,
+} from "../types";
 import { getLanguageForFile } from "../utils/languageMap";
 import { parseStructure } from "../utils/structureParser";
 import { MONACO_DIFF_OPTIONS } from "../constants/statusConfig";
 import { getFileIcon } from "../utils/fileIcons";
 import { OutlineModal } from "./OutlineModal";
+import { ConfirmDialog } from "./ConfirmDialog";
+import { isDivergeError } from "../utils/errors";
+import { sideState, writeOptions } from "../utils/fileState";
 
 interface DiffEditorProps {
   entry: CompareEntry;
+  contents: EntryContents;
   modifiedContent: string | undefined;
   minimapEnabled: boolean;
   showFullContent: boolean;
   onEditorPrefChange: (patch: Partial<EditorPreferences>) => void;
   onContentChange: (content: string) => void;
   onApplyLeftToRight: () => void;
+  onSync: (action: SyncAction) => void;
   onSaveFile: () => void;
 }
 
 export function DiffEditorView({
   entry,
+  contents,
   modifiedContent,
   minimapEnabled,
   showFullContent,
   onEditorPrefChange,
   onContentChange,
   onApplyLeftToRight,
+  onSync,
   onSaveFile,
 }: DiffEditorProps) {
   const editorRef = useRef<editor.IStandaloneDiffEditor | null>(null);
@@ -39,12 +53,15 @@ export function DiffEditorView({
   const [canRedo, setCanRedo] = useState(false);
   const [leftDirty, setLeftDirty] = useState(false);
   const [savingLeft, setSavingLeft] = useState(false);
+  const [leftConflict, setLeftConflict] = useState(false);
+  // The left file as last compared or saved, so saves never clobber edits made elsewhere.
+  const leftStateRef = useRef<FileState | null>(sideState(entry, "left"));
   const versionRef = useRef({ initial: 0, current: 0 });
   const origVersionRef = useRef(0);
 
   const language = getLanguageForFile(entry.rel_path);
   const fileIcon = getFileIcon(entry.rel_path);
-  const rightContent = modifiedContent ?? entry.right_content;
+  const rightContent = modifiedContent ?? contents.right_content;
 
   const outlineNodes = useMemo(
     () => parseStructure(rightContent, language),
@@ -212,15 +229,25 @@ export function DiffEditorView({
     }, 10);
   }, []);
 
-  const handleSaveLeft = useCallback(async () => {
+  const handleSaveLeft = useCallback(async (force = false) => {
     const ed = editorRef.current?.getOriginalEditor();
     if (!ed || !entry.left_path) return;
     setSavingLeft(true);
     try {
-      await invoke("write_file", { path: entry.left_path, content: ed.getValue() });
+      const content = ed.getValue();
+      leftStateRef.current = await invoke<FileState>("write_file", {
+        path: entry.left_path,
+        content,
+        expected: leftStateRef.current,
+        force,
+        options: writeOptions(content, editorRef.current?.getModifiedEditor().getValue()),
+      });
       setLeftDirty(false);
       const model = ed.getModel();
       if (model) origVersionRef.current = model.getAlternativeVersionId();
+    } catch (e) {
+      if (!isDivergeError(e) || e.kind !== "conflict") throw e;
+      setLeftConflict(true);
     } finally {
       setSavingLeft(false);
     }
@@ -258,6 +285,14 @@ export function DiffEditorView({
     return () => document.removeEventListener("keydown", handler);
   }, [navigateChange, leftDirty, handleSaveLeft, modifiedContent, onSaveFile]);
 
+  const handleCopyPatch = useCallback(async () => {
+    const diff = await invoke<UnifiedDiff>("unified_diff", {
+      relPath: entry.rel_path,
+      renamedFrom: entry.renamed_from ?? null,
+    });
+    await navigator.clipboard.writeText(diff.patch);
+  }, [entry.rel_path, entry.renamed_from]);
+
   return (
     <div className="diff-editor-container">
       <div className="diff-editor-header">
@@ -266,6 +301,11 @@ export function DiffEditorView({
             {fileIcon.label}
           </span>
           <span>{entry.rel_path}</span>
+          {entry.renamed_from && (
+            <span className="diff-editor-renamed" title={`${entry.similarity}% similar`}>
+              ← {entry.renamed_from}
+            </span>
+          )}
           <button
             className="btn-tiny"
             onClick={() => navigator.clipboard.writeText(entry.rel_path)}
@@ -273,7 +313,21 @@ export function DiffEditorView({
           >
             ⧉
           </button>
-          <span className={`diff-editor-badge badge-${entry.status}`}>
+          <button
+            className="btn-tiny"
+            onClick={handleCopyPatch}
+            disabled={entry.status === "identical"}
+            title="Copy unified diff"
+          >
+            ±
+          </button>
+          <span
+            className={`diff-editor-badge badge-${entry.status}`}
+            title={[
+              entry.left_hash && `Left BLAKE3: ${entry.left_hash}`,
+              entry.right_hash && `Right BLAKE3: ${entry.right_hash}`,
+            ].filter(Boolean).join("\n") || undefined}
+          >
             {entry.status.replace("_", " ").toUpperCase()}
           </span>
         </div>
@@ -342,10 +396,46 @@ export function DiffEditorView({
               Apply Left → Right
             </button>
           )}
+          {(entry.status === "different" || entry.status === "only_right") && (
+            <button
+              className="btn btn-sm btn-secondary"
+              onClick={() => onSync("copy_right_to_left")}
+              title="Copy the right file over the left one on disk"
+            >
+              Copy Right → Left
+            </button>
+          )}
+          {entry.status === "only_left" && (
+            <>
+              <button
+                className="btn btn-sm btn-accent"
+                onClick={() => onSync("copy_left_to_right")}
+                title="Copy the left file to the right side on disk"
+              >
+                Copy Left → Right
+              </button>
+              <button
+                className="btn btn-sm btn-danger"
+                onClick={() => onSync("delete_left")}
+                title="Delete the left file from disk"
+              >
+                Delete Left
+              </button>
+            </>
+          )}
+          {entry.status === "only_right" && (
+            <button
+              className="btn btn-sm btn-danger"
+              onClick={() => onSync("delete_right")}
+              title="Delete the right file from disk"
+            >
+              Delete Right
+            </button>
+          )}
           {leftDirty && (
             <button
               className="btn btn-sm btn-success"
-              onClick={handleSaveLeft}
+              onClick={() => handleSaveLeft()}
               disabled={savingLeft}
               title="Save left file to disk"
             >
@@ -365,7 +455,7 @@ export function DiffEditorView({
       </div>
       <div className="diff-editor-monaco">
         <MonacoDiffEditor
-          original={entry.left_content}
+          original={contents.left_content}
           modified={rightContent}
           language={language}
           theme="vs-dark"
@@ -381,6 +471,16 @@ export function DiffEditorView({
           onClose={() => setOutlineOpen(false)}
         />
       )}
+      {leftConflict && (
+        <ConfirmDialog
+          title="File Changed on Disk"
+          message={`${entry.rel_path} was changed on disk since the comparison. Overwrite the left file anyway? The changes made outside Diverge will be lost.`}
+          confirmLabel="Overwrite"
+          confirmVariant="danger"
+          onConfirm={() => { setLeftConflict(false); handleSaveLeft(true); }}
+          onCancel={() => setLeftConflict(false)}
+        />
+      )}
     </div>
   );
 }
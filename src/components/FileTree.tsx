@@ -4,13 +4,19 @@ import { STATUS_STYLES } from "../constants/statusConfig";
 import { getFolderForPath, getFileName } from "../utils/pathUtils";
 import { getFileIcon } from "../utils/fileIcons";
 
-type StatusFilter = "different" | "only_left" | "only_right" | "identical";
-const ALL_STATUSES: StatusFilter[] = ["different", "only_left", "only_right", "identical"];
+type StatusFilter = "different" | "only_left" | "only_right" | "renamed" | "identical";
+const ALL_STATUSES: StatusFilter[] = ["different", "only_left", "only_right", "renamed", "identical"];
+
+function changeCount(entry: CompareEntry) {
+  const s = entry.line_stats;
+  return s ? s.added + s.removed + s.changed : 0;
+}
 
 const FILTER_LABELS: Record<StatusFilter, { icon: string; label: string; color: string }> = {
   different: { icon: "≠", label: "Different", color: "#e5c07b" },
   only_left: { icon: "←", label: "Only left", color: "#e06c75" },
   only_right: { icon: "→", label: "Only right", color: "#c678dd" },
+  renamed: { icon: "↷", label: "Renamed", color: "#61afef" },
   identical: { icon: "✓", label: "Identical", color: "#4ec9b0" },
 };
 
@@ -49,6 +55,7 @@ export function FileTree({
 }: FileTreeProps) {
   const [ignoredCollapsed, setIgnoredCollapsed] = useState(true);
   const [searchQuery, setSearchQuery] = useState("");
+  const [sortByChanges, setSortByChanges] = useState(false);
   const [activeStatuses, setActiveStatuses] = useState<Set<StatusFilter>>(
     new Set(ALL_STATUSES)
   );
@@ -89,11 +96,28 @@ export function FileTree({
       if (!map.has(dir)) map.set(dir, []);
       map.get(dir)!.push(entry);
     }
-    return { grouped: map, folderKeys: [...map.keys()].sort() };
-  }, [filteredEntries]);
+    if (!sortByChanges) return { grouped: map, folderKeys: [...map.keys()].sort() };
+
+    // Most changed first: files within each folder, and folders by their total.
+    const totals = new Map<string, number>();
+    for (const [dir, files] of map) {
+      files.sort((a, b) => changeCount(b) - changeCount(a));
+      totals.set(dir, files.reduce((sum, f) => sum + changeCount(f), 0));
+    }
+    const folderKeys = [...map.keys()].sort(
+      (a, b) => totals.get(b)! - totals.get(a)! || a.localeCompare(b)
+    );
+    return { grouped: map, folderKeys };
+  }, [filteredEntries, sortByChanges]);
 
   const statusCounts = useMemo(() => {
-    const counts: Record<StatusFilter, number> = { different: 0, only_left: 0, only_right: 0, identical: 0 };
+    const counts: Record<StatusFilter, number> = {
+      different: 0,
+      only_left: 0,
+      only_right: 0,
+      renamed: 0,
+      identical: 0,
+    };
     for (const entry of entries) {
       const eff = getEffectiveStatus(entry);
       if (eff === "applied") counts.different++;
@@ -166,6 +190,7 @@ export function FileTree({
     if (statuses.some((s) => s === "only_left" || s === "only_right"))
       return "only_left";
     if (statuses.some((s) => s === "different")) return "different";
+    if (statuses.some((s) => s === "renamed")) return "renamed";
     return "applied";
   };
 
@@ -184,6 +209,13 @@ export function FileTree({
           >
             {allCollapsed ? "▶" : "▼"}
           </button>
+          <button
+            className={`btn-tiny ${sortByChanges ? "active" : ""}`}
+            onClick={() => setSortByChanges(!sortByChanges)}
+            title={sortByChanges ? "Sort by name" : "Sort by most changed"}
+          >
+            ⇅
+          </button>
           <button
             className="btn-tiny"
             onClick={onCheckAllDifferent}
@@ -335,6 +367,12 @@ export function FileTree({
                       <span className="file-name" title={entry.rel_path}>
                         {getFileName(entry.rel_path)}
                       </span>
+                      {entry.line_stats && changeCount(entry) > 0 && (
+                        <span className="file-line-stats">
+                          <span className="line-added">+{entry.line_stats.added + entry.line_stats.changed}</span>
+                          <span className="line-removed">−{entry.line_stats.removed + entry.line_stats.changed}</span>
+                        </span>
+                      )}
                       {effStatus === "applied" && (
                         <span
                           className="file-applied-badge"
@@ -52,7 +52,7 @@ export function SettingsPanel({
     <div className="settings-panel">
       <div className="settings-header" onClick={() => setCollapsed(!collapsed)}>
         <span className="settings-chevron">{collapsed ? "▶" : "▼"}</span>
-        <span className="settings-title">Ignored Directories</span>
+        <span className="settings-title">Ignore Patterns</span>
         <span className="settings-count">{config.ignore_dirs.length}</span>
         {dirty && <span className="settings-dirty">unsaved</span>}
       </div>
@@ -65,7 +65,7 @@ export function SettingsPanel({
               value={newDir}
               onChange={(e) => setNewDir(e.target.value)}
               onKeyDown={(e) => e.key === "Enter" && handleAdd()}
-              placeholder="Add directory..."
+              placeholder="Add pattern (e.g. *.log, /docs/generated)..."
               className="settings-add-input"
             />
             <button
@@ -1,11 +1,12 @@
 import { useEffect, useMemo, useState } from "react";
 import { getVersion } from "@tauri-apps/api/app";
-import type { CompareEntry, CompareResult } from "../types";
+import type { CompareProgress, CompareResult, EntryContents } from "../types";
 
 interface StatusBarProps {
   result: CompareResult | null;
+  progress: CompareProgress | null;
   selectedFile: string | null;
-  selectedEntry: CompareEntry | null;
+  selectedContents: EntryContents | null;
   modifiedContent: string | undefined;
   modifiedCount: number;
 }
@@ -26,21 +27,60 @@ function computeDiffStats(left: string, right: string) {
   return { additions, deletions, lines: rightLines.length };
 }
 
-export function StatusBar({ result, selectedFile, selectedEntry, modifiedContent, modifiedCount }: StatusBarProps) {
+function formatEta(ms: number) {
+  const seconds = Math.ceil(ms / 1000);
+  return seconds < 60 ? `${seconds}s` : `${Math.floor(seconds / 60)}m ${seconds % 60}s`;
+}
+
+export function StatusBar({ result, progress, selectedFile, selectedContents, modifiedContent, modifiedCount }: StatusBarProps) {
   const [version, setVersion] = useState<string | null>(null);
 
   useEffect(() => {
     getVersion().then(setVersion);
   }, []);
 
+  const selectedEntry = useMemo(
+    () => result?.entries.find((e) => e.rel_path === selectedFile) ?? null,
+    [result, selectedFile]
+  );
+
+  // The backend's line diff is exact; fall back to an estimate only for unsaved edits.
   const diffStats = useMemo(() => {
-    if (!selectedEntry) return null;
-    const right = modifiedContent ?? selectedEntry.right_content;
-    return computeDiffStats(selectedEntry.left_content, right);
-  }, [selectedEntry, modifiedContent]);
+    if (!selectedContents || selectedContents.kind !== "text") return null;
+    const right = modifiedContent ?? selectedContents.right_content;
+    const stats = selectedEntry?.line_stats;
+    if (stats && modifiedContent === undefined) {
+      return {
+        additions: stats.added + stats.changed,
+        deletions: stats.removed + stats.changed,
+        lines: right ? right.split("\n").length : 0,
+      };
+    }
+    return computeDiffStats(selectedContents.left_content, right);
+  }, [selectedContents, modifiedContent, selectedEntry]);
   return (
     <div className="status-bar">
       <div className="status-bar-left">
+        {progress && (
+          <>
+            <span className="status-item status-progress">
+              {progress.total === null ? (
+                <>Scanning… <span className="status-count">{progress.discovered}</span> files found</>
+              ) : (
+                <>
+                  Comparing <span className="status-count">{progress.compared}</span>/{progress.total}
+                  {progress.eta_ms !== null && <> · {formatEta(progress.eta_ms)} left</>}
+                </>
+              )}
+            </span>
+            {progress.current_path && (
+              <span className="status-item status-filepath status-current-path" title={progress.current_path}>
+                {progress.current_path}
+              </span>
+            )}
+            <span className="status-sep">│</span>
+          </>
+        )}
         {result ? (
           <>
             <span className="status-item">
@@ -70,6 +110,35 @@ export function StatusBar({ result, selectedFile, selectedEntry, modifiedContent
                 </span>
               </>
             )}
+            {result.renamed > 0 && (
+              <>
+                <span className="status-sep">│</span>
+                <span className="status-item status-renamed">
+                  <span className="status-count">{result.renamed}</span> renamed
+                </span>
+              </>
+            )}
+            {result.line_totals.added + result.line_totals.changed + result.line_totals.removed > 0 && (
+              <>
+                <span className="status-sep">│</span>
+                <span className="status-item" title="Lines changed across all files">
+                  <span style={{ color: "#b5f5e0" }}>
+                    +{result.line_totals.added + result.line_totals.changed}
+                  </span>{" "}
+                  <span style={{ color: "#ffb0b0" }}>
+                    −{result.line_totals.removed + result.line_totals.changed}
+                  </span>
+                </span>
+              </>
+            )}
+            {result.too_large > 0 && (
+              <>
+                <span className="status-sep">│</span>
+                <span className="status-item" title="Compared by hash only">
+                  <span className="status-count">{result.too_large}</span> too large
+                </span>
+              </>
+            )}
           </>
         ) : (
           <span className="status-item">Select two folders to compare</span>
@@ -1,6 +1,6 @@
 import { useState } from "react";
 import { open } from "@tauri-apps/plugin-dialog";
-import type { RecentComparison } from "../types";
+import type { RecentComparison, SyncDirection } from "../types";
 
 interface ToolbarProps {
   leftDir: string;
@@ -9,10 +9,16 @@ interface ToolbarProps {
   onSetLeftDir: (dir: string) => void;
   onSetRightDir: (dir: string) => void;
   onCompare: () => void;
+  onCancel: () => void;
+  respectGitignore: boolean;
+  onToggleGitignore: (value: boolean) => void;
   onApplyAll: () => void;
   onApplySelected: () => void;
   onSaveAll: () => Promise<number>;
   onRefresh: () => void;
+  onApplyPatch: (patchPath: string) => void;
+  onSync: (direction: SyncDirection) => void;
+  onUndo: () => void;
   onClear: () => void;
   loading: boolean;
   hasResult: boolean;
@@ -39,10 +45,16 @@ export function Toolbar({
   onSetLeftDir,
   onSetRightDir,
   onCompare,
+  onCancel,
+  respectGitignore,
+  onToggleGitignore,
   onApplyAll,
   onApplySelected,
   onSaveAll,
   onRefresh,
+  onApplyPatch,
+  onSync,
+  onUndo,
   onClear,
   loading,
   hasResult,
@@ -54,6 +66,20 @@ export function Toolbar({
 }: ToolbarProps) {
   const [recentOpen, setRecentOpen] = useState(false);
 
+  const pickPatch = async () => {
+    try {
+      const selected = await open({
+        multiple: false,
+        title: "Select Patch to Apply to the Right Side",
+        defaultPath: rightDir || cwd || undefined,
+        filters: [{ name: "Patch", extensions: ["patch", "diff"] }],
+      });
+      if (typeof selected === "string") onApplyPatch(selected);
+    } catch (err) {
+      console.error("Patch picker failed:", err);
+    }
+  };
+
   const pickFolder = async (side: "left" | "right") => {
     try {
       const defaultPath = (side === "left" ? leftDir : rightDir) || cwd || undefined;
@@ -113,6 +139,22 @@ export function Toolbar({
           >
             {loading ? "Comparing..." : "Compare"}
           </button>
+          {loading && (
+            <button className="btn btn-secondary" onClick={onCancel} title="Stop this comparison">
+              Cancel
+            </button>
+          )}
+          <label
+            className="toolbar-toggle"
+            title="Skip files ignored by .gitignore, .ignore, .git/info/exclude and global git excludes"
+          >
+            <input
+              type="checkbox"
+              checked={respectGitignore}
+              onChange={(e) => onToggleGitignore(e.target.checked)}
+            />
+            .gitignore
+          </label>
           <div className="recent-dropdown-wrapper">
             <button
               className="btn btn-ghost"
@@ -183,6 +225,30 @@ export function Toolbar({
           >
             💾 Save All
           </button>
+          <button
+            className="btn btn-ghost"
+            onClick={() => onSync("left_to_right")}
+            title="Preview copying and deleting files so the right side matches the left (checked files only, if any)"
+          >
+            ⇉ Sync to Right…
+          </button>
+          <button
+            className="btn btn-ghost"
+            onClick={() => onSync("right_to_left")}
+            title="Preview copying and deleting files so the left side matches the right (checked files only, if any)"
+          >
+            ⇇ Sync to Left…
+          </button>
+          <button
+            className="btn btn-ghost"
+            onClick={onUndo}
+            title="Restore the files changed by the last save, sync, patch or merge"
+          >
+            ↶ Undo Last Write
+          </button>
+          <button className="btn btn-ghost" onClick={pickPatch} title="Apply a unified diff or git patch to the right side">
+            ⇲ Apply Patch…
+          </button>
           <button className="btn btn-ghost" onClick={onRefresh} title="Re-scan directories">
             ↻ Refresh
           </button>
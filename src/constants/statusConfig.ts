@@ -12,6 +12,7 @@ export const STATUS_STYLES: Record<EffectiveStatus, StatusStyle> = {
   different: { icon: "≠", color: "#e5c07b", label: "Different" },
   only_left: { icon: "←", color: "#e06c75", label: "Only in left" },
   only_right: { icon: "→", color: "#c678dd", label: "Only in right" },
+  renamed: { icon: "↷", color: "#61afef", label: "Renamed" },
 };
 
 export const MONACO_DIFF_OPTIONS = {
@@ -1,12 +1,33 @@
-import { useCallback, useRef } from "react";
+import { useCallback, useEffect, useRef, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
 import { useDirectories } from "./useDirectories";
+import { useEntryContents } from "./useEntryContents";
 import { useFileTree } from "./useFileTree";
 import { useModifications } from "./useModifications";
+import { errorMessage } from "../utils/errors";
+import type {
+  CompareResult,
+  PatchOutcome,
+  RestoreOutcome,
+  SyncDirection,
+  SyncFilters,
+  SyncItem,
+  SyncOutcome,
+  SyncPlan,
+} from "../types";
 
 export function useCompare() {
   const dirs = useDirectories();
   const tree = useFileTree(dirs.result);
-  const mods = useModifications(dirs.result, dirs.rightDir, dirs.setResult);
+  const contents = useEntryContents();
+  const mods = useModifications(
+    dirs.result,
+    dirs.rightDir,
+    dirs.setResult,
+    contents,
+    dirs.respectGitignore
+  );
+  const [contentsError, setContentsError] = useState<string | null>(null);
 
   const dirsRef = useRef(dirs);
   dirsRef.current = dirs;
@@ -14,33 +35,105 @@ export function useCompare() {
   treeRef.current = tree;
   const modsRef = useRef(mods);
   modsRef.current = mods;
+  const contentsRef = useRef(contents);
+  contentsRef.current = contents;
 
+  // Reset before comparing, since entries stream into the tree while the job runs.
   const compare = useCallback(async () => {
-    await dirsRef.current.compare();
     treeRef.current.reset();
     modsRef.current.reset();
+    contentsRef.current.reset();
+    await dirsRef.current.compare();
   }, []);
 
   const compareWith = useCallback(async (left: string, right: string) => {
-    await dirsRef.current.compareWith(left, right);
     treeRef.current.reset();
     modsRef.current.reset();
+    contentsRef.current.reset();
+    await dirsRef.current.compareWith(left, right);
   }, []);
 
   const clear = useCallback(() => {
     dirsRef.current.clear();
     treeRef.current.reset();
     modsRef.current.reset();
+    contentsRef.current.reset();
   }, []);
 
+  const { selectedFile } = tree;
+  useEffect(() => {
+    setContentsError(null);
+    if (!selectedFile) return;
+    const entry = dirsRef.current.result?.entries.find((e) => e.rel_path === selectedFile);
+    contentsRef.current
+      .load(selectedFile, entry?.renamed_from)
+      .catch((e) => setContentsError(errorMessage(e)));
+  }, [selectedFile]);
+
+  // Takes the comparison the backend ran again after changing files: loaded contents are
+  // dropped and the selected file is read anew.
+  const adoptResult = useCallback((result: CompareResult | null) => {
+    contentsRef.current.reset();
+    if (result) dirsRef.current.setResult(result);
+    const selected = treeRef.current.selectedFile;
+    if (selected) {
+      const entry = result?.entries.find((e) => e.rel_path === selected);
+      contentsRef.current
+        .load(selected, entry?.renamed_from)
+        .catch((e) => setContentsError(errorMessage(e)));
+    }
+  }, []);
+
+  // Applies a patch file to the right side.
+  const applyPatch = useCallback(async (patchPath: string) => {
+    const patch = await invoke<string>("read_file", { path: patchPath });
+    const outcome = await invoke<PatchOutcome>("apply_patch", {
+      patch,
+      side: "right",
+      respectGitignore: dirsRef.current.respectGitignore,
+    });
+    adoptResult(outcome.result);
+    return outcome;
+  }, [adoptResult]);
+
+  // Copies or deletes files between the sides, reporting each item.
+  const syncEntries = useCallback(async (items: SyncItem[]) => {
+    const outcome = await invoke<SyncOutcome>("sync_entries", {
+      items,
+      respectGitignore: dirsRef.current.respectGitignore,
+    });
+    adoptResult(outcome.result);
+    return outcome;
+  }, [adoptResult]);
+
+  // Lists what a sync of the last comparison would do, without touching anything.
+  const planSync = useCallback((direction: SyncDirection, filters: SyncFilters) => {
+    return invoke<SyncPlan>("plan_sync", { direction, filters });
+  }, []);
+
+  // Runs a plan; the backend refuses it if a target changed since the comparison.
+  const runSyncPlan = useCallback(async (plan: SyncPlan) => {
+    const outcome = await invoke<SyncOutcome>("execute_sync_plan", {
+      plan,
+      respectGitignore: dirsRef.current.respectGitignore,
+    });
+    adoptResult(outcome.result);
+    return outcome;
+  }, [adoptResult]);
+
+  // Restores the files of the latest write or delete from the undo history.
+  const undoLastOperation = useCallback(async () => {
+    const outcome = await invoke<RestoreOutcome>("undo_last_operation", {
+      respectGitignore: dirsRef.current.respectGitignore,
+    });
+    adoptResult(outcome.result);
+    return outcome;
+  }, [adoptResult]);
+
   const applySelectedToRight = useCallback(() => {
-    mods.applySelectedToRight(tree.checkedFiles);
+    return mods.applySelectedToRight(tree.checkedFiles);
   }, [mods, tree.checkedFiles]);
 
-  const saveAll = useCallback(() => {
-    return mods.saveAll(dirs.leftDir);
-  }, [mods, dirs.leftDir]);
-
   return {
     // Directories
     leftDir: dirs.leftDir,
@@ -49,8 +142,12 @@ export function useCompare() {
     setRightDir: dirs.setRightDir,
     result: dirs.result,
     loading: dirs.loading,
+    progress: dirs.progress,
+    cancelCompare: dirs.cancel,
     error: dirs.error,
     cwd: dirs.cwd,
+    respectGitignore: dirs.respectGitignore,
+    setRespectGitignore: dirs.setRespectGitignore,
 
     // File tree
     selectedFile: tree.selectedFile,
@@ -66,6 +163,10 @@ export function useCompare() {
     toggleFolder: tree.toggleFolder,
     toggleAllFolders: tree.toggleAllFolders,
 
+    // Lazily loaded file contents
+    selectedContents: selectedFile ? contents.contents[selectedFile] ?? null : null,
+    contentsError,
+
     // Modifications
     modifiedContents: mods.modifiedContents,
     getEffectiveStatus: mods.getEffectiveStatus,
@@ -74,7 +175,12 @@ export function useCompare() {
     applyAllToRight: mods.applyAllToRight,
     applySelectedToRight,
     saveFile: mods.saveFile,
-    saveAll,
+    saveAll: mods.saveAll,
+    applyPatch,
+    syncEntries,
+    planSync,
+    runSyncPlan,
+    undoLastOperation,
 
     // Top-level actions
     compare,
@@ -1,6 +1,57 @@
 import { useState, useCallback, useEffect, useRef } from "react";
 import { invoke } from "@tauri-apps/api/core";
-import type { CliArgs, CompareResult } from "../types";
+import { listen } from "@tauri-apps/api/event";
+import type {
+  CliArgs,
+  CompareEntry,
+  CompareFinished,
+  CompareProgress,
+  CompareResult,
+} from "../types";
+import { errorMessage } from "../utils/errors";
+
+function emptyResult(): CompareResult {
+  return {
+    entries: [],
+    total: 0,
+    identical: 0,
+    different: 0,
+    only_left: 0,
+    only_right: 0,
+    renamed: 0,
+    too_large: 0,
+    line_totals: { added: 0, removed: 0, changed: 0 },
+    ignored_dirs: [],
+  };
+}
+
+/** Adds streamed entries to a partial result, keeping entries sorted and counts current. */
+function withEntries(prev: CompareResult | null, entries: CompareEntry[]): CompareResult {
+  const result = prev ?? emptyResult();
+  const all = [...result.entries, ...entries].sort((a, b) =>
+    a.rel_path < b.rel_path ? -1 : a.rel_path > b.rel_path ? 1 : 0
+  );
+  const count = (status: CompareEntry["status"]) => all.filter((e) => e.status === status).length;
+  return {
+    ...result,
+    entries: all,
+    total: all.length,
+    identical: count("identical"),
+    different: count("different"),
+    only_left: count("only_left"),
+    only_right: count("only_right"),
+    renamed: count("renamed"),
+    too_large: all.filter((e) => e.kind === "too_large").length,
+    line_totals: all.reduce(
+      (t, e) => ({
+        added: t.added + (e.line_stats?.added ?? 0),
+        removed: t.removed + (e.line_stats?.removed ?? 0),
+        changed: t.changed + (e.line_stats?.changed ?? 0),
+      }),
+      { added: 0, removed: 0, changed: 0 }
+    ),
+  };
+}
 
 export function useDirectories() {
   const [leftDir, setLeftDir] = useState("");
@@ -9,26 +60,92 @@ export function useDirectories() {
   const [loading, setLoading] = useState(false);
   const [error, setError] = useState<string | null>(null);
   const [cwd, setCwd] = useState("");
+  // null until the user toggles it, so the backend falls back to the config value.
+  const [respectGitignore, setRespectGitignore] = useState<boolean | null>(null);
+  const respectGitignoreRef = useRef(respectGitignore);
+  respectGitignoreRef.current = respectGitignore;
+
+  const [progress, setProgress] = useState<CompareProgress | null>(null);
+  const jobRef = useRef<number | null>(null);
 
+  // Runs a comparison as a background job. Entries stream into `result` as they are
+  // compared; the promise resolves once the job is done or cancelled.
   const runCompare = useCallback(
     async (left: string, right: string) => {
       setLoading(true);
       setError(null);
+      setProgress(null);
+      setResult(emptyResult());
+      jobRef.current = null;
+
+      // Events can arrive before start_compare returns the job id, so hold them until then.
+      let jobId: number | null = null;
+      const current = () => jobId !== null && jobRef.current === jobId;
+      const early: (() => void)[] = [];
+      const forJob = <T extends { job_id: number }>(handle: (payload: T) => void) =>
+        (event: { payload: T }) => {
+          const run = () => {
+            if (event.payload.job_id === jobId) handle(event.payload);
+          };
+          if (jobId === null) early.push(run);
+          else run();
+        };
+
+      let finish: () => void = () => {};
+      const finished = new Promise<void>((resolve) => (finish = resolve));
+      const unlisten = await Promise.all([
+        listen<CompareProgress>(
+          "compare-progress",
+          forJob((payload) => {
+            if (current()) setProgress(payload);
+          })
+        ),
+        listen<{ job_id: number; entries: CompareEntry[] }>(
+          "compare-entries",
+          forJob(({ entries }) => {
+            if (current()) setResult((prev) => withEntries(prev, entries));
+          })
+        ),
+        listen<CompareFinished>(
+          "compare-finished",
+          forJob((payload) => {
+            // A run superseded by a newer one just stops listening.
+            if (current()) setResult(payload.status === "done" ? payload.result : null);
+            finish();
+          })
+        ),
+      ]);
+
       try {
-        const res = await invoke<CompareResult>("compare_directories", {
+        jobId = await invoke<number>("start_compare", {
           left,
           right,
+          respectGitignore: respectGitignoreRef.current,
         });
-        setResult(res);
+        jobRef.current = jobId;
+        early.forEach((run) => run());
+        await finished;
       } catch (e) {
-        setError(String(e));
+        if (jobRef.current === null) {
+          setError(errorMessage(e));
+          setResult(null);
+        }
       } finally {
-        setLoading(false);
+        unlisten.forEach((stop) => stop());
+        if (jobRef.current === jobId || jobId === null) {
+          jobRef.current = null;
+          setLoading(false);
+          setProgress(null);
+        }
       }
     },
     []
   );
 
+  const cancel = useCallback(() => {
+    if (jobRef.current !== null) invoke("cancel_compare", { jobId: jobRef.current });
+  }, []);
+
   const initDone = useRef(false);
   useEffect(() => {
     if (initDone.current) return;
@@ -72,8 +189,12 @@ export function useDirectories() {
     setRightDir,
     result,
     setResult,
+    respectGitignore,
+    setRespectGitignore,
     loading,
+    progress,
     error,
+    cancel,
     compare,
     compareWith,
     clear,
@@ -0,0 +1,47 @@
+import { useState, useCallback, useRef } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import type { EntryContents } from "../types";
+
+export function useEntryContents() {
+  const [contents, setContents] = useState<Record<string, EntryContents>>({});
+  const contentsRef = useRef(contents);
+  contentsRef.current = contents;
+
+  // Renamed entries are read from `renamedFrom` on the left and `relPath` on the right.
+  const load = useCallback(async (relPath: string, renamedFrom?: string | null) => {
+    const cached = contentsRef.current[relPath];
+    if (cached) return cached;
+    const loaded = await invoke<EntryContents>("get_entry_contents", { relPath, renamedFrom });
+    contentsRef.current = { ...contentsRef.current, [relPath]: loaded };
+    setContents((prev) => ({ ...prev, [relPath]: loaded }));
+    return loaded;
+  }, []);
+
+  // Reads both sides again, e.g. after a write that converted line endings.
+  const reload = useCallback(async (relPath: string, renamedFrom?: string | null) => {
+    const loaded = await invoke<EntryContents>("get_entry_contents", { relPath, renamedFrom });
+    contentsRef.current = { ...contentsRef.current, [relPath]: loaded };
+    setContents((prev) => ({ ...prev, [relPath]: loaded }));
+    return loaded;
+  }, []);
+
+  const update = useCallback((relPath: string, patch: Partial<EntryContents>) => {
+    setContents((prev) => {
+      if (!prev[relPath]) return prev;
+      return { ...prev, [relPath]: { ...prev[relPath], ...patch } };
+    });
+  }, []);
+
+  const reset = useCallback(() => {
+    contentsRef.current = {};
+    setContents({});
+  }, []);
+
+  return {
+    contents,
+    load,
+    reload,
+    update,
+    reset,
+  };
+}
@@ -1,12 +1,25 @@
 import { useState, useCallback } from "react";
 import { invoke } from "@tauri-apps/api/core";
-import type { CompareEntry, CompareResult, EffectiveStatus } from "../types";
+import type {
+  Change,
+  ChangesOutcome,
+  CompareEntry,
+  CompareResult,
+  EffectiveStatus,
+  FileState,
+} from "../types";
+import { EDITOR_WRITE, sideState, writeOptions } from "../utils/fileState";
+import type { useEntryContents } from "./useEntryContents";
 
 export function useModifications(
   result: CompareResult | null,
   rightDir: string,
-  refreshResult: (res: CompareResult) => void
+  refreshResult: (res: CompareResult) => void,
+  entryContents: ReturnType<typeof useEntryContents>,
+  respectGitignore: boolean | null
 ) {
+  const { contents, load: loadContents, reload: reloadContents } = entryContents;
+
   const [modifiedContents, setModifiedContents] = useState<
     Record<string, string>
   >({});
@@ -15,11 +28,11 @@ export function useModifications(
     (entry: CompareEntry): EffectiveStatus => {
       const mod = modifiedContents[entry.rel_path];
       if (mod !== undefined) {
-        return mod === entry.left_content ? "applied" : "different";
+        return mod === contents[entry.rel_path]?.left_content ? "applied" : "different";
       }
       return entry.status;
     },
-    [modifiedContents]
+    [modifiedContents, contents]
   );
 
   const updateModifiedContent = useCallback(
@@ -29,61 +42,79 @@ export function useModifications(
     []
   );
 
+  const applyEntries = useCallback(
+    async (entries: CompareEntry[]) => {
+      const loaded = await Promise.all(
+        entries.map(async (e) => [e.rel_path, (await loadContents(e.rel_path, e.renamed_from)).left_content] as const)
+      );
+      setModifiedContents((prev) => ({ ...prev, ...Object.fromEntries(loaded) }));
+    },
+    [loadContents]
+  );
+
   const applyLeftToRight = useCallback(
-    (relPath: string) => {
+    async (relPath: string) => {
       const entry = result?.entries.find((e) => e.rel_path === relPath);
-      if (entry) {
-        setModifiedContents((prev) => ({
-          ...prev,
-          [relPath]: entry.left_content,
-        }));
-      }
+      if (entry) await applyEntries([entry]);
     },
-    [result]
+    [result, applyEntries]
   );
 
-  const applyAllToRight = useCallback(() => {
+  const applyAllToRight = useCallback(async () => {
     if (!result) return;
-    const updates: Record<string, string> = {};
-    for (const entry of result.entries) {
-      if (entry.status === "different") {
-        updates[entry.rel_path] = entry.left_content;
-      }
-    }
-    setModifiedContents((prev) => ({ ...prev, ...updates }));
-  }, [result]);
+    await applyEntries(result.entries.filter((e) => e.status === "different" && e.kind === "text"));
+  }, [result, applyEntries]);
 
   const applySelectedToRight = useCallback(
-    (checkedFiles: Set<string>) => {
+    async (checkedFiles: Set<string>) => {
       if (!result) return;
-      const updates: Record<string, string> = {};
-      for (const entry of result.entries) {
-        if (checkedFiles.has(entry.rel_path) && entry.status === "different") {
-          updates[entry.rel_path] = entry.left_content;
-        }
-      }
-      setModifiedContents((prev) => ({ ...prev, ...updates }));
+      await applyEntries(
+        result.entries.filter(
+          (e) => checkedFiles.has(e.rel_path) && e.status === "different" && e.kind === "text"
+        )
+      );
     },
-    [result]
+    [result, applyEntries]
   );
 
+  // Fails with a `conflict` error if the right file changed on disk since the comparison,
+  // unless `force` is set.
   const saveFile = useCallback(
-    async (relPath: string) => {
+    async (relPath: string, force = false) => {
       const entry = result?.entries.find((e) => e.rel_path === relPath);
       if (!entry) return;
       const content = modifiedContents[relPath];
       if (content === undefined) return;
       const targetPath = entry.right_path || `${rightDir}/${relPath}`;
-      await invoke("write_file", { path: targetPath, content });
+      const { left_content } = await loadContents(relPath, entry.renamed_from);
+      const written = await invoke<FileState>("write_file", {
+        path: targetPath,
+        content,
+        expected: sideState(entry, "right"),
+        force,
+        options: writeOptions(content, left_content),
+      });
 
+      // The bytes written can differ from `content` once line endings are kept.
+      const { right_content } = await reloadContents(relPath, entry.renamed_from);
+      const identical =
+        entry.left_hash !== null ? written.hash === entry.left_hash : left_content === right_content;
       if (result) {
         const updatedEntries = result.entries.map((e) => {
           if (e.rel_path !== relPath) return e;
           return {
             ...e,
-            right_content: content,
             right_path: targetPath,
-            status: (e.left_content === content ? "identical" : "different") as CompareEntry["status"],
+            right_size: written.size,
+            right_modified: written.modified,
+            right_hash: written.hash,
+            // Recomputed by the next compare.
+            line_stats: null,
+            similarity: null,
+            status:
+              e.status === "renamed"
+                ? e.status
+                : ((identical ? "identical" : "different") as CompareEntry["status"]),
           };
         });
         refreshResult({ ...result, entries: updatedEntries });
@@ -95,32 +126,53 @@ export function useModifications(
         return next;
       });
     },
-    [result, modifiedContents, rightDir, refreshResult]
+    [result, modifiedContents, rightDir, refreshResult, loadContents, reloadContents]
   );
 
-  const saveAll = useCallback(
-    async (leftDir: string) => {
-      let saved = 0;
-      for (const relPath of Object.keys(modifiedContents)) {
-        try {
-          await saveFile(relPath);
-          saved++;
-        } catch (e) {
-          console.error(`Failed to save ${relPath}:`, e);
-        }
-      }
-      if (saved > 0 && leftDir && rightDir) {
-        const res = await invoke<CompareResult>("compare_directories", {
-          left: leftDir,
-          right: rightDir,
-        });
-        refreshResult(res);
-        setModifiedContents({});
-      }
-      return saved;
-    },
-    [modifiedContents, saveFile, rightDir, refreshResult]
-  );
+  // Writes every modified file in one batch. Nothing is written if a file changed on disk,
+  // and if a write fails the others are put back, so the right side is either fully saved
+  // or untouched; the entries come back compared again, without a rescan.
+  const saveAll = useCallback(async (): Promise<ChangesOutcome | null> => {
+    if (!result) return null;
+    const changes: Change[] = Object.entries(modifiedContents).flatMap(([relPath, content]) => {
+      const entry = result.entries.find((e) => e.rel_path === relPath);
+      if (!entry) return [];
+      return [{
+        rel_path: relPath,
+        renamed_from: entry.renamed_from,
+        side: "right" as const,
+        content,
+        expected: sideState(entry, "right"),
+        write: writeOptions(content, contents[relPath]?.left_content),
+      }];
+    });
+    if (changes.length === 0) return null;
+
+    const outcome = await invoke<ChangesOutcome>("apply_changes", {
+      changes,
+      options: { rollback: true, write: EDITOR_WRITE },
+      respectGitignore,
+    });
+    const saved = outcome.results.filter((r) => r.state !== null).map((r) => r.rel_path);
+    await Promise.all(
+      saved.map((relPath) => {
+        const entry = result.entries.find((e) => e.rel_path === relPath);
+        return reloadContents(relPath, entry?.renamed_from);
+      })
+    );
+    if (outcome.result) {
+      refreshResult(outcome.result);
+    } else {
+      const updated = new Map(outcome.entries.map((e) => [e.rel_path, e]));
+      refreshResult({ ...result, entries: result.entries.map((e) => updated.get(e.rel_path) ?? e) });
+    }
+    setModifiedContents((prev) => {
+      const next = { ...prev };
+      for (const relPath of saved) delete next[relPath];
+      return next;
+    });
+    return outcome;
+  }, [result, modifiedContents, contents, refreshResult, reloadContents, respectGitignore]);
 
   const reset = useCallback(() => {
     setModifiedContents({});
@@ -1,10 +1,193 @@
+export type EntryKind = "text" | "binary" | "too_large";
+
+export interface BinaryDiff {
+  offset: number;
+  left_bytes: string;
+  right_bytes: string;
+}
+
+export interface LineStats {
+  added: number;
+  removed: number;
+  changed: number;
+}
+
 export interface CompareEntry {
   rel_path: string;
-  status: "identical" | "different" | "only_left" | "only_right";
-  left_content: string;
-  right_content: string;
+  status: "identical" | "different" | "only_left" | "only_right" | "renamed";
+  kind: EntryKind;
   left_path: string;
   right_path: string;
+  left_size: number | null;
+  right_size: number | null;
+  left_hash: string | null;
+  right_hash: string | null;
+  /** Modification times in milliseconds since the Unix epoch. */
+  left_modified: number | null;
+  right_modified: number | null;
+  binary_diff: BinaryDiff | null;
+  /** Left path of a renamed entry; `rel_path` is where it lives on the right. */
+  renamed_from: string | null;
+  /** Percentage of lines both sides share, for differing text files and renames. */
+  similarity: number | null;
+  line_stats: LineStats | null;
+}
+
+export interface EntryContents {
+  left_content: string;
+  right_content: string;
+  kind: EntryKind;
+}
+
+export interface UnifiedDiff {
+  patch: string;
+  skipped: string[];
+}
+
+export interface PatchConflict {
+  path: string;
+  hunk: number | null;
+  header: string;
+  reason: string;
+}
+
+export interface PatchOutcome {
+  patched: string[];
+  conflicts: PatchConflict[];
+  result: CompareResult | null;
+}
+
+export type SyncAction =
+  | "copy_left_to_right"
+  | "copy_right_to_left"
+  | "delete_left"
+  | "delete_right"
+  | "skip";
+
+export interface SyncItem {
+  rel_path: string;
+  action: SyncAction;
+}
+
+export interface SyncItemResult {
+  rel_path: string;
+  action: SyncAction;
+  error: DivergeError | null;
+}
+
+export interface SyncOutcome {
+  results: SyncItemResult[];
+  succeeded: number;
+  failed: number;
+  skipped: number;
+  result: CompareResult | null;
+}
+
+export type SyncDirection = "left_to_right" | "right_to_left";
+
+export interface SyncFilters {
+  delete?: boolean;
+  /** Only these entries; all of them when null. */
+  paths?: string[] | null;
+}
+
+export interface FileState {
+  size: number;
+  modified: number | null;
+  hash: string | null;
+}
+
+/** What a write keeps of the file it replaces; its mode is always kept, its line endings
+ * only with `keep_line_endings`. */
+export interface WriteOptions {
+  keep_line_endings?: boolean;
+  xattrs?: boolean;
+}
+
+export interface PlannedOperation {
+  rel_path: string;
+  kind: "copy" | "overwrite" | "delete";
+  size: number;
+  target: FileState | null;
+  /** Set when the target changed on disk since the comparison. */
+  conflict: string | null;
+}
+
+export interface SyncPlan {
+  left: string;
+  right: string;
+  direction: SyncDirection;
+  operations: PlannedOperation[];
+  copies: number;
+  overwrites: number;
+  deletes: number;
+  conflicts: number;
+  bytes_written: number;
+  bytes_deleted: number;
+}
+
+export type Side = "left" | "right";
+
+/** New contents for one side of an entry, written by `apply_changes`. */
+export interface Change {
+  rel_path: string;
+  renamed_from?: string | null;
+  side: Side;
+  content: string;
+  expected: FileState | null;
+  /** Overrides the batch's write options for this change. */
+  write?: WriteOptions;
+}
+
+export interface ApplyOptions {
+  /** All or nothing: write nothing if a file changed on disk, and put back the files
+   * already written if a write fails. Defaults to true. */
+  rollback?: boolean;
+  force?: boolean;
+  write?: WriteOptions;
+}
+
+export interface ChangeResult {
+  rel_path: string;
+  side: Side;
+  state: FileState | null;
+  error: DivergeError | null;
+  rolled_back: boolean;
+  /** Why the file could not be put back; it keeps the new contents. */
+  rollback_error: DivergeError | null;
+}
+
+export interface ChangesOutcome {
+  results: ChangeResult[];
+  written: number;
+  failed: number;
+  rolled_back: boolean;
+  /** The changed entries compared again. */
+  entries: CompareEntry[];
+  /** The last comparison with `entries` merged in, without a rescan. */
+  result: CompareResult | null;
+}
+
+/** One step of the undo history: the files a command wrote or deleted. */
+export interface HistoryOperation {
+  session: string;
+  id: number;
+  label: string;
+  /** Milliseconds since the Unix epoch. */
+  time: number;
+  files: { path: string; backup: string | null }[];
+  undone: boolean;
+}
+
+export interface HistorySession {
+  id: string;
+  operations: HistoryOperation[];
+}
+
+export interface RestoreOutcome {
+  /** Operations undone, newest first. */
+  restored: HistoryOperation[];
+  result: CompareResult | null;
 }
 
 export interface CompareResult {
@@ -14,9 +197,41 @@ export interface CompareResult {
   different: number;
   only_left: number;
   only_right: number;
+  renamed: number;
+  too_large: number;
+  line_totals: LineStats;
   ignored_dirs: string[];
 }
 
+export interface CompareProgress {
+  job_id: number;
+  discovered: number;
+  compared: number;
+  total: number | null;
+  current_path: string | null;
+  eta_ms: number | null;
+}
+
+export type CompareFinished =
+  | { job_id: number; status: "done"; result: CompareResult }
+  | { job_id: number; status: "cancelled" };
+
+export type DivergeErrorKind =
+  | "not_found"
+  | "permission_denied"
+  | "not_a_directory"
+  | "io"
+  | "config"
+  | "conflict"
+  | "invalid_input";
+
+/** Shape of every error returned by a backend command. */
+export interface DivergeError {
+  kind: DivergeErrorKind;
+  path: string | null;
+  message: string;
+}
+
 export interface CliArgs {
   left_dir: string;
   right_dir: string;
@@ -38,6 +253,21 @@ export interface AppConfig {
   ignore_dirs: string[];
   editor_preferences: EditorPreferences;
   recent_comparisons: RecentComparison[];
+  trust_mtime: boolean;
+  paranoid: boolean;
+  max_file_size: number;
+  respect_gitignore: boolean;
+  threads: number;
+  detect_renames: boolean;
+  rename_similarity: number;
+  history: HistoryConfig;
+}
+
+/** Retention of the undo history; 0 lifts a limit. */
+export interface HistoryConfig {
+  enabled: boolean;
+  max_sessions: number;
+  max_age_days: number;
 }
 
 export type EffectiveStatus =
@@ -45,4 +275,5 @@ export type EffectiveStatus =
   | "different"
   | "only_left"
   | "only_right"
+  | "renamed"
   | "applied";
@@ -0,0 +1,27 @@
+import type { DivergeError } from "../types";
+
+export function isDivergeError(e: unknown): e is DivergeError {
+  return typeof e === "object" && e !== null && "kind" in e && "message" in e;
+}
+
+/** Human-readable text for anything a command can throw. */
+export function errorMessage(e: unknown): string {
+  return isDivergeError(e) ? e.message : String(e);
+}
+
+/** A toast for a failed action, worded by error kind. */
+export function describeFailure(action: string, e: unknown): string {
+  if (!isDivergeError(e)) return `${action} failed`;
+  switch (e.kind) {
+    case "permission_denied":
+      return `${action} failed: permission denied for ${e.path}`;
+    case "not_found":
+      return `${action} failed: ${e.path} no longer exists`;
+    case "not_a_directory":
+      return `${action} failed: ${e.path} is not a directory`;
+    case "conflict":
+      return `${action} failed: ${e.path} changed on disk`;
+    default:
+      return `${action} failed: ${e.message}`;
+  }
+}
@@ -0,0 +1,20 @@
+import type { CompareEntry, FileState, WriteOptions } from "../types";
+
+/** One side of an entry as it was compared, or null where the file is missing. Writes
+ * carry it so the backend can refuse to overwrite a file changed on disk since. */
+export function sideState(entry: CompareEntry, side: "left" | "right"): FileState | null {
+  const size = side === "left" ? entry.left_size : entry.right_size;
+  if (size === null) return null;
+  return side === "left"
+    ? { size, modified: entry.left_modified, hash: entry.left_hash }
+    : { size, modified: entry.right_modified, hash: entry.right_hash };
+}
+
+/** Saves from the editor keep the file's line endings, trailing newline and attributes. */
+export const EDITOR_WRITE: WriteOptions = { keep_line_endings: true, xattrs: true };
+
+/** A copy of the other side is written as is, so both files end up identical; edited text
+ * keeps the line endings of the file it replaces. */
+export function writeOptions(content: string, otherSide: string | undefined): WriteOptions {
+  return content === otherSide ? { xattrs: true } : EDITOR_WRITE;
+}